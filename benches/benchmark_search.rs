@@ -1,23 +1,21 @@
-use std::{fs::File, io::BufReader};
+#![allow(clippy::ptr_arg)]
 
 use assignment_1::{
     reader::Reader,
     record::Record,
     search::{naive_search, simple_accelerant_search, Span},
-    suffix_array::SuffixArray,
+    suffix_array::{self, SuffixArray, DEFAULT_ADAPTIVE_THRESHOLD},
 };
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 enum QueryMode {
     Naive,
     Simpaccel,
+    Adaptive,
 }
 
 fn get_suffix_array(filename: &str) -> SuffixArray {
-    bincode::deserialize_from(BufReader::new(
-        File::open(filename).expect("file must exist"),
-    ))
-    .unwrap()
+    SuffixArray::from_bincode_file(filename).unwrap()
 }
 
 fn get_records(filename: &str) -> Vec<Record> {
@@ -26,14 +24,19 @@ fn get_records(filename: &str) -> Vec<Record> {
 }
 
 fn raw_search_function_harness(query_mode: QueryMode, sa: &SuffixArray, records: &Vec<Record>) {
-    let f = match query_mode {
-        QueryMode::Simpaccel => simple_accelerant_search,
-        QueryMode::Naive => naive_search,
-    };
-    let sequence_bytes = sa.sequence.as_bytes();
+    let sequence = sa.sequence();
+    let sequence_bytes = sequence.as_bytes();
     // let span: Span = (0, sequence_bytes.len());
-    let span: Span = (0, sequence_bytes.len() as u32);
+    let span: Span = Span::new(0, sequence_bytes.len() as u32);
     records.iter().for_each(|record| {
+        let f = match query_mode {
+            QueryMode::Simpaccel => simple_accelerant_search,
+            QueryMode::Naive => naive_search,
+            QueryMode::Adaptive if record.sequence().len() < DEFAULT_ADAPTIVE_THRESHOLD => {
+                naive_search
+            }
+            QueryMode::Adaptive => simple_accelerant_search,
+        };
         f(
             sequence_bytes,
             record.sequence().as_bytes(),
@@ -55,10 +58,20 @@ fn simpaccel_harness(sa: &SuffixArray, records: &Vec<Record>) {
     })
 }
 
+fn adaptive_harness(sa: &SuffixArray, records: &Vec<Record>) {
+    let mode = suffix_array::QueryMode::Adaptive {
+        threshold: DEFAULT_ADAPTIVE_THRESHOLD,
+    };
+    records.iter().for_each(|record: &Record| {
+        sa.search(record.sequence(), mode);
+    })
+}
+
 fn search_harness(query_mode: QueryMode, sa: &SuffixArray, records: &Vec<Record>) {
     let f = match query_mode {
         QueryMode::Naive => naive_search_harness,
         QueryMode::Simpaccel => simpaccel_harness,
+        QueryMode::Adaptive => adaptive_harness,
     };
     f(sa, records)
 }
@@ -73,6 +86,9 @@ fn raw_search_criterion(c: &mut Criterion) {
     c.bench_function("raw simpaccel search", |b| {
         b.iter(|| raw_search_function_harness(QueryMode::Simpaccel, &sa, &records))
     });
+    c.bench_function("raw adaptive search", |b| {
+        b.iter(|| raw_search_function_harness(QueryMode::Adaptive, &sa, &records))
+    });
 }
 
 fn prefix_table_criterion(c: &mut Criterion) {
@@ -85,6 +101,9 @@ fn prefix_table_criterion(c: &mut Criterion) {
     c.bench_function("simpaccel search - no prefix table", |b| {
         b.iter(|| search_harness(black_box(QueryMode::Simpaccel), &sa, &records))
     });
+    c.bench_function("adaptive search - no prefix table", |b| {
+        b.iter(|| search_harness(black_box(QueryMode::Adaptive), &sa, &records))
+    });
 
     for k in [1, 2, 3, 5, 8, 12] {
         sa.initialize_prefix_table(k);
@@ -94,6 +113,9 @@ fn prefix_table_criterion(c: &mut Criterion) {
         c.bench_function(format!("simpaccel search k={}", k).as_str(), |b| {
             b.iter(|| search_harness(black_box(QueryMode::Simpaccel), &sa, &records))
         });
+        c.bench_function(format!("adaptive search k={}", k).as_str(), |b| {
+            b.iter(|| search_harness(black_box(QueryMode::Adaptive), &sa, &records))
+        });
     }
 }
 
@@ -1,6 +1,27 @@
-use std::path::PathBuf;
+use std::{ffi::OsString, path::PathBuf};
 
 use clap::Parser;
+use eyre::{eyre, Result};
+
+use crate::search::Span;
+
+/// Parses a `start:end` region, e.g. `1000:2000`
+pub fn parse_region(value: &str) -> std::result::Result<Span, String> {
+    let (start, end) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected start:end but got {value:?}"))?;
+    let parse = |x: &str| {
+        x.trim()
+            .replace(',', "")
+            .parse::<u32>()
+            .map_err(|e| format!("{x:?} is not a position: {e}"))
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+        return Err(format!("the region {value:?} starts after it ends"));
+    }
+    Ok(Span::new(start, end))
+}
 
 #[derive(clap::ValueEnum, Clone, Debug)]
 pub enum QueryMode {
@@ -8,20 +29,210 @@ pub enum QueryMode {
     Naive,
     /// bisect left and right, skipping min lcp comparisons
     Simpaccel,
+    /// naive for short queries and simpaccel for long ones
+    Adaptive,
+}
+
+impl From<&QueryMode> for crate::suffix_array::QueryMode {
+    fn from(mode: &QueryMode) -> Self {
+        match mode {
+            QueryMode::Naive => Self::Naive,
+            QueryMode::Simpaccel => Self::Simpaccel,
+            QueryMode::Adaptive => Self::Adaptive {
+                threshold: crate::suffix_array::DEFAULT_ADAPTIVE_THRESHOLD,
+            },
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum SentinelOrder {
+    /// the $ sentinel sorts before A
+    Smallest,
+    /// the $ sentinel sorts after T
+    Largest,
+}
+
+impl From<&SentinelOrder> for crate::suffix_array::SentinelOrder {
+    fn from(order: &SentinelOrder) -> Self {
+        match order {
+            SentinelOrder::Smallest => Self::Smallest,
+            SentinelOrder::Largest => Self::Largest,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum MatchMode {
+    /// report every position where the query occurs as a substring of the reference
+    Substring,
+    /// only report positions where the query runs to the end of a reference record
+    Whole,
+    /// only report a match at position 0, i.e. when the query is a prefix of the whole reference
+    Anchored,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum IndexFormat {
+    /// the suffix array, sequence and prefix table serialized with bincode
+    Bincode,
+    /// little-endian u32 suffix array with the sequence in a <path>.seq sidecar file
+    RawSa,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum Alphabet {
+    /// index the nucleotides as read
+    Dna,
+    /// index purines (A, G) as R and pyrimidines (C, T) as Y
+    PurinePyrimidine,
+}
+
+impl From<&Alphabet> for crate::encoding::Alphabet {
+    fn from(alphabet: &Alphabet) -> Self {
+        match alphabet {
+            Alphabet::Dna => Self::Dna,
+            Alphabet::PurinePyrimidine => Self::PurinePyrimidine,
+        }
+    }
 }
 
 #[derive(Debug, Parser)]
 /// Builds the suffix array for a given reference files
-/// and saves the result to disk
+/// and saves the result to disk (every record in the file is indexed)
+#[command(allow_missing_positional = true)]
 pub struct BuildsaArgs {
     #[arg(short, long, value_name="k", value_parser = clap::value_parser!(u16).range(1..100))]
     /// Build a prefix table of size <k> for this reference sequence
     pub preftab: Option<u16>,
 
+    #[arg(required_unless_present = "sequence")]
     /// The path to a FASTA file containing the reference sequence
-    pub reference: PathBuf,
+    pub reference: Option<PathBuf>,
     /// The path to the file the suffix array will be saved to
     pub output: PathBuf,
+
+    #[arg(long, conflicts_with = "reference", value_name = "SEQUENCE")]
+    /// Index this literal sequence instead of reading a reference file
+    /// (pass only the OUTPUT path)
+    pub sequence: Option<String>,
+
+    #[arg(short, long, value_enum, default_value = "bincode")]
+    /// The file format the suffix array is saved in
+    pub format: IndexFormat,
+
+    #[arg(long)]
+    /// Store the sequence with 2 bits per base (bincode format only)
+    pub packed: bool,
+
+    #[arg(long, value_enum, default_value = "smallest")]
+    /// Where the $ sentinel sorts relative to the nucleotides in the suffix array
+    pub sentinel: SentinelOrder,
+
+    #[arg(short, long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+    /// The number of threads used to sort the suffixes (requires the parallel feature)
+    pub threads: u16,
+
+    #[arg(long, value_enum, default_value = "dna")]
+    /// Fold the reference (and later the queries) into a reduced alphabet before indexing
+    pub alphabet: Alphabet,
+
+    #[arg(long, value_name = "ATTEMPTS", num_args = 0..=1, default_missing_value = "5", conflicts_with = "sequence")]
+    /// Wait (one second per attempt) for a reference that is empty or still being written by
+    /// another process to stop growing before reading it
+    pub wait: Option<u32>,
+
+    #[arg(long, conflicts_with = "sequence")]
+    /// Record the lowercase (soft-masked) bases of the reference so querysa --unmasked-only
+    /// can leave out matches in masked repeats (bincode format only)
+    pub soft_masked: bool,
+
+    #[arg(long, conflicts_with_all = ["sequence", "packed", "soft_masked", "preftab"])]
+    /// Save only the suffix array (bincode format only). Bases other than A, C, G and T are kept
+    /// as N so querysa --reference can rebuild the same sequence from the reference
+    pub array_only: bool,
+
+    #[arg(long, requires = "preftab", conflicts_with_all = ["array_only", "packed", "soft_masked"])]
+    /// Save only the prefix table and the sequence, without the suffix array (bincode format
+    /// only). The much smaller index can only count queries of exactly k bases
+    pub preftab_only: bool,
+
+    #[arg(
+        long,
+        value_name = "WIDTH",
+        requires = "preftab",
+        conflicts_with = "preftab_only"
+    )]
+    /// Build an adaptive prefix table instead: starting from the k-mers of --preftab, split every
+    /// bucket holding more than WIDTH suffixes by the following bases, up to --adaptive-max-k
+    pub adaptive_width: Option<u32>,
+
+    #[arg(long, value_name = "k", default_value = "20", requires = "adaptive_width", value_parser = clap::value_parser!(u16).range(1..100))]
+    /// The length of the longest prefixes of an adaptive prefix table
+    pub adaptive_max_k: u16,
+
+    #[arg(long, conflicts_with_all = ["sequence", "array_only", "packed", "strict"])]
+    /// Keep IUPAC ambiguity codes (e.g. R for A or G) in the reference instead of replacing them
+    /// with random nucleotides, for querysa --iupac
+    pub iupac: bool,
+
+    #[arg(long, conflicts_with_all = ["array_only", "iupac"])]
+    /// Also index the reverse complement of every record, so querysa finds the matches on both
+    /// strands with a single search and reports them at their forward strand positions
+    pub both_strands: bool,
+
+    #[arg(long, requires = "preftab_only")]
+    /// Count every k-mer of the --preftab-only index together with its reverse complement. The
+    /// table keeps a span per k-mer, both orientations are summed when a query is counted
+    pub canonical_kmers: bool,
+
+    #[arg(long, value_name = "k", conflicts_with_all = ["array_only", "preftab_only"], value_parser = clap::value_parser!(u16).range(1..100))]
+    /// Save a Bloom filter over the k-mers of size <k> of the reference so querysa rules out
+    /// queries whose first k-mer is absent without searching (bincode format only)
+    pub bloom: Option<u16>,
+
+    #[arg(long, value_name = "RATE", default_value = "0.01", requires = "bloom")]
+    /// The false positive rate the Bloom filter is sized for
+    pub bloom_fp_rate: f64,
+
+    #[arg(long)]
+    /// Fail on the first character of the reference that is not A, C, G, T or N instead of
+    /// replacing it, e.g. when a protein FASTA or a GFF file is passed by mistake
+    pub strict: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum QueryFormat {
+    /// FASTA records
+    Fasta,
+    /// One query sequence per line, named line-<n> after the line number
+    Lines,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delimiter {
+    /// `, ` between the fields
+    Comma,
+    /// a tab between the fields (TSV)
+    Tab,
+}
+
+impl Delimiter {
+    /// The separator written between the fields of an output line
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Comma => ", ",
+            Self::Tab => "\t",
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum OutputOrder {
+    /// sorted by position in the reference, stable across index rebuilds
+    Genomic,
+    /// the order of the suffixes in the suffix array
+    Sa,
 }
 
 #[derive(Debug, Parser)]
@@ -29,19 +240,333 @@ pub struct BuildsaArgs {
 pub struct QuerysaArgs {
     /// The path to the binary file generated in buildsa
     pub index: PathBuf,
-    /// The path to a FASTA file containing the queries to run
+    /// The path to a FASTA (or --query-format lines) file containing the queries to run (left
+    /// out with --serve)
     pub queries: PathBuf,
 
     #[arg(value_enum)]
     pub query_mode: QueryMode,
 
-    #[arg(required_unless_present = "quiet")]
-    /// The path to the file the results are written to (not required if quiet flag is set)
+    #[arg(required_unless_present_any = ["quiet", "serve", "summary_only", "stats_only"])]
+    /// The path to the file the results are written to, `-` for standard output (not
+    /// required if quiet flag is set)
     pub output: Option<PathBuf>,
 
+    #[arg(long, requires = "output")]
+    /// Gzip the output file (implied by an output path ending in .gz)
+    pub gzip: bool,
+
     #[arg(short, long)]
     /// run queries without writing the results to the output file
     pub quiet: bool,
+
+    #[arg(long, conflicts_with_all = ["quiet", "serve"])]
+    /// Write only the aggregate summary (query, match and occurrence totals and a histogram of
+    /// occurrences per query) instead of a line per query, to OUTPUT or stdout if no output is given
+    pub summary_only: bool,
+
+    #[arg(long, conflicts_with_all = ["quiet", "serve", "summary_only"])]
+    /// Write only running statistics of the occurrences per query (matched fraction, min, max,
+    /// mean and an approximate median) to OUTPUT or stdout, in memory independent of the batch size
+    pub stats_only: bool,
+
+    #[arg(short, long, value_enum, default_value = "bincode")]
+    /// The file format of the index
+    pub format: IndexFormat,
+
+    #[arg(long, value_name = "PATH", conflicts_with = "format")]
+    /// Load an index written by buildsa --array-only, reading the sequence from this reference
+    pub reference: Option<PathBuf>,
+
+    #[arg(short, long, value_enum, default_value = "substring")]
+    /// Whether a query may occur anywhere in the reference, must be a whole suffix of a
+    /// record or must start the reference
+    pub match_mode: MatchMode,
+
+    #[arg(long, value_name = "SHA256")]
+    /// Fail if the index was not built from a reference with this checksum
+    pub reference_checksum: Option<String>,
+
+    #[arg(long)]
+    /// Print the checksum of the reference the index was built from
+    pub print_checksum: bool,
+
+    #[arg(long, value_name = "NAME")]
+    /// Only report matches in the reference record with this name (positions are relative to the record)
+    pub contig: Option<String>,
+
+    #[arg(long, value_name = "START:END", value_parser = parse_region)]
+    /// Only report matches lying entirely inside this window, in the coordinates positions are
+    /// reported in (relative to --contig, 1-based and closed with --one-based, half-open
+    /// otherwise). The matches are filtered after searching the whole reference, so a query
+    /// takes as long as without the window
+    pub region: Option<Span>,
+
+    #[arg(long)]
+    /// Read the queries as RNA, matching U in a query against T in the reference
+    pub rna_query: bool,
+
+    #[arg(long)]
+    /// Fail on the first query with a character other than A, C, G, T or N instead of
+    /// replacing it with a random nucleotide (e.g. when a protein file is passed by mistake)
+    pub validate_queries: bool,
+
+    #[arg(long)]
+    /// Report and skip query records that fail to parse instead of aborting the run
+    pub skip_errors: bool,
+
+    #[arg(long)]
+    /// Keep the index loaded and answer queries read line by line from stdin, a line `@PATH`
+    /// runs the queries of the file at PATH. Results are written to OUTPUT or stdout if no output
+    /// is given
+    pub serve: bool,
+
+    #[arg(long)]
+    /// Ignore the prefix table stored in the index and bisect the full suffix array
+    pub no_preftab: bool,
+
+    #[arg(long)]
+    /// Report 1-based positions (as in SAM, VCF and GFF). Positions are 0-based by default
+    pub one_based: bool,
+
+    #[arg(long, default_value = "0", value_name = "LENGTH")]
+    /// Skip queries shorter than LENGTH instead of searching for them
+    pub min_query_len: usize,
+
+    #[arg(long)]
+    /// Report the time spent in the prefix table lookup and in the bisection separately
+    /// (substring match mode only)
+    pub phase_timings: bool,
+
+    #[arg(long)]
+    /// Report how many queries the prefix table narrowed the search span for and the mean
+    /// width of the spans left to bisect, to judge whether its k suits the queries
+    pub diagnose: bool,
+
+    #[arg(long, value_enum, default_value = "fasta")]
+    /// The file format of the queries
+    pub query_format: QueryFormat,
+
+    #[arg(long, value_name = "SEPARATOR", conflicts_with = "serve")]
+    /// Split the sequence of every query record on SEPARATOR (e.g. `,`) and search each part,
+    /// reported under the query header with a `/<n>` suffix on its name
+    pub split_on: Option<char>,
+
+    #[arg(long, value_name = "GAP")]
+    /// Report start-end intervals, merging occurrences that start within GAP bases
+    /// of the end of the previous one (0 merges adjacent and overlapping occurrences)
+    pub merge_adjacent: Option<u32>,
+
+    #[arg(long, num_args = 2, value_names = ["MIN", "MAX"])]
+    /// Instead of the positions report `header, length:count...` for every prefix of the
+    /// query with a length between MIN and MAX, stopping once a prefix no longer occurs
+    pub length_profile: Option<Vec<usize>>,
+
+    #[arg(long, value_name = "K", conflicts_with_all = ["length_profile", "soft_clip", "strand_counts", "canonical"], value_parser = clap::value_parser!(u32).range(1..))]
+    /// Instead of the positions report `header, offset:count...` for the windows of K bases
+    /// of the query starting every --step bases (e.g. for mappability tracks)
+    pub tile: Option<u32>,
+
+    #[arg(long, default_value = "1", requires = "tile", value_parser = clap::value_parser!(u32).range(1..))]
+    /// The number of bases between the starts of consecutive --tile windows
+    pub step: u32,
+
+    #[arg(long, value_enum, default_value = "comma")]
+    /// The separator between the header, the count and the positions of an output line
+    pub delimiter: Delimiter,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    /// Flush the output every N queries so partial results survive a crash and can be
+    /// followed with `tail -f` (by default the output is only flushed at the end)
+    pub flush_every: Option<u64>,
+
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    /// Flush the output whenever SECONDS have passed since the last flush
+    pub flush_interval: Option<u64>,
+
+    #[arg(long, value_enum, default_value = "genomic")]
+    /// The order the positions of a query are reported in
+    pub order: OutputOrder,
+
+    #[arg(long, value_name = "N")]
+    /// Report the longest window of each query occurring in the reference after clipping
+    /// up to N bases from each end, as `header, left clip, right clip, count, positions...`
+    /// (substring match mode only)
+    pub soft_clip: Option<usize>,
+
+    #[arg(long, value_name = "PATH", conflicts_with = "serve")]
+    /// Write a bedGraph of how many query occurrences cover each position of the reference
+    pub coverage_out: Option<PathBuf>,
+
+    #[arg(long, value_name = "PATH", conflicts_with = "serve")]
+    /// Write the headers of the queries without any occurrence to a separate file
+    pub unmatched_out: Option<PathBuf>,
+
+    #[arg(long, requires = "unmatched_out")]
+    /// Write the unmatched queries to --unmatched-out as FASTA records instead of headers only
+    pub unmatched_sequences: bool,
+
+    #[arg(long, conflicts_with_all = ["merge_adjacent", "length_profile", "soft_clip", "serve"])]
+    /// Also search the reverse complement of each query (or split the matches of a
+    /// buildsa --both-strands index by strand) and report the strands separately as
+    /// `header, forward count, reverse count, forward positions..., reverse positions...`
+    pub strand_counts: bool,
+
+    #[arg(long, conflicts_with_all = ["merge_adjacent", "length_profile", "soft_clip", "serve", "strand_counts"])]
+    /// Count a query together with its reverse complement (a canonical k-mer count), reporting
+    /// the occurrences of both as one `header, count, positions...` row. A palindromic query is
+    /// only counted once
+    pub canonical: bool,
+
+    #[arg(
+        long,
+        default_value_t = crate::suffix_array::DEFAULT_ADAPTIVE_THRESHOLD,
+        value_name = "LENGTH"
+    )]
+    /// With the adaptive query mode, queries at least this long are searched with simpaccel
+    pub adaptive_threshold: usize,
+
+    #[arg(long)]
+    /// Only report occurrences that lie entirely in unmasked (uppercase) regions of the
+    /// reference (requires an index built with buildsa --soft-masked)
+    pub unmasked_only: bool,
+
+    #[arg(long, conflicts_with_all = ["serve", "summary_only", "stats_only", "length_profile", "tile", "soft_clip", "strand_counts", "canonical", "coverage_out", "unmatched_out", "match_mode"])]
+    /// Let IUPAC codes kept in the reference by buildsa --iupac match the bases they stand for
+    /// (R matches A and G). Much slower, the matching suffixes are scanned instead of bisected
+    pub iupac: bool,
+}
+
+#[derive(Debug, Parser)]
+/// Time a batch of queries against prefix tables of several sizes to choose a value for --preftab
+pub struct TunePreftabArgs {
+    /// The path to a FASTA file containing the reference sequence
+    pub reference: PathBuf,
+    /// The path to a FASTA file containing representative queries
+    pub queries: PathBuf,
+
+    #[arg(value_enum)]
+    pub query_mode: QueryMode,
+
+    #[arg(short, long, value_delimiter = ',', default_value = "1,2,3,4,5,6,8,10,12", value_parser = clap::value_parser!(u16).range(1..100))]
+    /// Comma separated list of prefix table sizes to try
+    pub k: Vec<u16>,
+
+    #[arg(short, long, default_value = "3")]
+    /// The number of times to run the query batch for each k (the fastest run is reported)
+    pub rounds: usize,
+}
+
+#[derive(Debug, Parser)]
+/// Print a summary of an index and statistics about its prefix table
+pub struct DumpsaArgs {
+    /// The path to the binary file generated in buildsa
+    pub index: PathBuf,
+
+    #[arg(short, long, value_enum, default_value = "bincode")]
+    /// The file format of the index
+    pub format: IndexFormat,
+
+    #[arg(short, long, value_name = "k", value_parser = clap::value_parser!(u16).range(1..100))]
+    /// Report statistics for a prefix table of size <k> instead of the one stored in the index
+    pub preftab: Option<u16>,
+
+    #[arg(long)]
+    /// Print the index metadata as a JSON object instead of the summary
+    pub info: bool,
+}
+
+#[derive(Debug, Parser)]
+/// Write the k-mer spectrum of a reference, every k-mer of its prefix table with its count
+pub struct KmerspectrumArgs {
+    /// The path to the binary file generated in buildsa (with --preftab or --preftab-only)
+    pub index: PathBuf,
+
+    /// The path to write `kmer<TAB>count` lines to, standard output if not given
+    pub output: Option<PathBuf>,
+
+    #[arg(short, long, value_enum, default_value = "bincode")]
+    /// The file format of the index
+    pub format: IndexFormat,
+
+    #[arg(short, long, value_name = "k", value_parser = clap::value_parser!(u16).range(1..100))]
+    /// Count the k-mers of size <k> instead of those of the prefix table stored in the index
+    pub preftab: Option<u16>,
+
+    #[arg(long)]
+    /// Count every k-mer together with its reverse complement under the lexicographically
+    /// smaller of the two (implied by an index built with buildsa --canonical-kmers)
+    pub canonical: bool,
+}
+
+#[derive(Debug, Parser)]
+/// Check that an index is internally consistent and that both search algorithms agree on it
+pub struct VerifysaArgs {
+    /// The path to the binary file generated in buildsa
+    pub index: PathBuf,
+
+    #[arg(short, long, value_enum, default_value = "bincode")]
+    /// The file format of the index
+    pub format: IndexFormat,
+
+    #[arg(short, long, default_value = "100")]
+    /// The number of substrings of the reference to search for
+    pub samples: usize,
+
+    #[arg(short, long, default_value = "20", value_parser = clap::value_parser!(u16).range(1..))]
+    /// The length of the sampled substrings
+    pub length: u16,
+}
+
+#[derive(Debug, Parser)]
+/// Print a longest substring that occurs at least twice in the reference of an index
+pub struct LongestrepeatArgs {
+    /// The path to the binary file generated in buildsa
+    pub index: PathBuf,
+
+    #[arg(short, long, value_enum, default_value = "bincode")]
+    /// The file format of the index
+    pub format: IndexFormat,
+}
+
+#[derive(Debug, Parser)]
+/// Run queries against several indices (e.g. one per chromosome) and merge the results per query
+pub struct MultiqueryArgs {
+    /// The path to a FASTA file containing the queries to run
+    pub queries: PathBuf,
+
+    #[arg(value_enum)]
+    pub query_mode: QueryMode,
+
+    /// The path to the file the results are written to
+    pub output: PathBuf,
+
+    #[arg(short, long = "index", required = true, value_name = "INDEX")]
+    /// The path to an index generated in buildsa (repeat for each index). Results are
+    /// tagged with the file name of the index they were found in
+    pub indices: Vec<PathBuf>,
+
+    #[arg(short, long, value_enum, default_value = "bincode")]
+    /// The file format of the indices
+    pub format: IndexFormat,
+
+    #[arg(short, long, value_enum, default_value = "substring")]
+    /// Whether a query may occur anywhere in the reference, must be a whole suffix of a
+    /// record or must start the reference
+    pub match_mode: MatchMode,
+}
+
+#[derive(Debug, Parser)]
+/// Compare two querysa result files, reporting the queries whose sets of positions differ
+pub struct DiffsaArgs {
+    /// The first querysa output file
+    pub first: PathBuf,
+    /// The second querysa output file
+    pub second: PathBuf,
+
+    #[arg(short, long)]
+    /// Only print the summary instead of every differing query
+    pub quiet: bool,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -49,6 +574,12 @@ pub enum SampleStrategy {
     ExactMatch,
     /// Generate queries as random substrings of the reference sequence but randomly modify 5% of the characters
     Perturb,
+    /// Generate random queries that do not occur anywhere in the reference sequence
+    Absent,
+    /// Generate random substrings of the reference like exact-match, but pick the start of a
+    /// query in proportion to how often the k-mer there (see --weight-k) occurs in the
+    /// reference, so repetitive regions are sampled as often as they occur
+    Repetitive,
 }
 
 #[derive(Debug, Parser)]
@@ -75,4 +606,161 @@ pub struct BuildQueryArgs {
     /// The number of queries to generate (defaults to 100)
     #[arg(short, long, default_value = "100")]
     pub queries: usize,
+
+    #[arg(long)]
+    /// Display a progress bar on stderr while the queries are written
+    pub progress: bool,
+
+    #[arg(long)]
+    /// Keep queries out of ambiguous (e.g. N) regions of the reference, which are indexed as
+    /// random nucleotides: sampled and perturbed queries never overlap an ambiguous base and
+    /// absent queries do not match across one, whichever nucleotides it stands for
+    pub skip_ambiguous: bool,
+
+    #[arg(long, value_name = "k", default_value = "8", value_parser = clap::value_parser!(u16).range(1..100))]
+    /// The length of the k-mers counted to weight the query starts of the repetitive strategy
+    pub weight_k: u16,
+}
+
+impl QuerysaArgs {
+    /// Same as [`Parser::try_parse_from`], except that the query file can be left out with
+    /// --serve, which clap cannot express for a positional followed by required positionals
+    pub fn try_parse_serve_from<I, T>(args: I) -> std::result::Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let error = match Self::try_parse_from(&args) {
+            Ok(parsed) => return Ok(parsed),
+            Err(error) => error,
+        };
+        if !args.iter().any(|arg| arg == "--serve") {
+            return Err(error);
+        }
+        // `-` stands in for the query file, at the first place where clap takes it as one
+        (1..=args.len())
+            .find_map(|idx| {
+                let mut args = args.clone();
+                args.insert(idx, OsString::from("-"));
+                Self::try_parse_from(args)
+                    .ok()
+                    .filter(|parsed| parsed.queries.as_os_str() == "-")
+            })
+            .ok_or(error)
+    }
+
+    /// Whether the results are written to standard output with `-` as the output path
+    pub fn output_is_stdout(&self) -> bool {
+        self.output
+            .as_deref()
+            .is_some_and(|path| path.as_os_str() == "-")
+    }
+}
+
+impl BuildQueryArgs {
+    /// Checks the constraints between arguments that clap cannot express
+    pub fn validate(&self) -> Result<()> {
+        if self.min_length > self.max_length {
+            return Err(eyre!(
+                "--min-length ({}) cannot be larger than --max-length ({})",
+                self.min_length,
+                self.max_length
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_buildquery(lengths: [&str; 2]) -> BuildQueryArgs {
+        BuildQueryArgs::try_parse_from([
+            "buildquery",
+            "reference.fa",
+            "queries.fa",
+            "exact-match",
+            "--min-length",
+            lengths[0],
+            "--max-length",
+            lengths[1],
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn regions_are_parsed() {
+        assert_eq!(parse_region("10:20"), Ok(Span::new(10, 20)));
+        assert_eq!(parse_region("1,000:2,000"), Ok(Span::new(1000, 2000)));
+        assert_eq!(parse_region("5:5"), Ok(Span::new(5, 5)));
+        for invalid in ["10", "20:10", "a:10", "-1:10", ""] {
+            assert!(parse_region(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn buildquery_rejects_inverted_lengths() {
+        assert!(parse_buildquery(["30", "10"]).validate().is_err());
+        assert!(parse_buildquery(["10", "10"]).validate().is_ok());
+        assert!(parse_buildquery(["5", "30"]).validate().is_ok());
+    }
+
+    #[test]
+    fn a_dash_writes_querysa_results_to_stdout() {
+        let parse = |output: &str| {
+            QuerysaArgs::try_parse_from(["querysa", "index.bin", "queries.fa", "naive", output])
+                .unwrap()
+        };
+        assert!(parse("-").output_is_stdout());
+        assert!(!parse("results.txt").output_is_stdout());
+    }
+
+    #[test]
+    fn serve_does_not_need_a_query_file() {
+        let parse = |args: &[&str]| {
+            QuerysaArgs::try_parse_serve_from(["querysa"].iter().chain(args))
+                .map(|parsed| (parsed.queries, parsed.output, parsed.serve))
+        };
+        let serve = |output: Option<&str>| (PathBuf::from("-"), output.map(PathBuf::from), true);
+        assert_eq!(
+            parse(&["index.bin", "naive", "--serve"]).unwrap(),
+            serve(None)
+        );
+        assert_eq!(
+            parse(&[
+                "--serve",
+                "--delimiter",
+                "tab",
+                "index.bin",
+                "naive",
+                "out.txt"
+            ])
+            .unwrap(),
+            serve(Some("out.txt"))
+        );
+        assert_eq!(
+            parse(&["index.bin", "-", "naive", "--serve"]).unwrap(),
+            serve(None)
+        );
+        assert!(parse(&["index.bin", "naive", "out.txt"]).is_err());
+        assert!(parse(&["index.bin", "--serve"]).is_err());
+    }
+
+    #[test]
+    fn iupac_conflicts_with_match_mode() {
+        let args = [
+            "querysa",
+            "index.bin",
+            "queries.fa",
+            "naive",
+            "out.txt",
+            "--iupac",
+        ];
+        QuerysaArgs::try_parse_from(args).unwrap();
+        let error =
+            QuerysaArgs::try_parse_from(args.iter().chain(&["--match-mode", "whole"])).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
 }
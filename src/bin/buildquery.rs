@@ -1,99 +1,337 @@
 use std::{
+    collections::HashMap,
     fmt::Write as FmtWrite,
     fs::File,
     io::{BufWriter, Write},
-    iter::zip,
 };
 
 use assignment_1::{
     args::{BuildQueryArgs, SampleStrategy},
+    logging::init_logger,
+    normalize::{iupac_matches, AMBIGUOUS_BASE},
     reader::Reader,
+    record::Record,
+    suffix_array::SuffixArray,
 };
 use clap::Parser;
-use eyre::{ContextCompat, Result, WrapErr};
-use rand::{distributions::Uniform, prelude::Distribution, seq::IteratorRandom, thread_rng, Rng};
-
-fn generate_exact_match_sequences(
-    reference: &str,
-    min_size: usize,
-    max_size: usize,
-    queries: usize,
-) -> Vec<String> {
-    let mut rng = thread_rng();
-    let starts: Vec<usize> = Uniform::new(0, reference.len() - max_size)
-        .sample_iter(&mut rng)
-        .take(queries)
-        .collect();
-    let offsets: Vec<usize> = Uniform::new_inclusive(min_size, max_size)
-        .sample_iter(&mut rng)
-        .take(queries)
-        .collect();
-    zip(starts, offsets)
-        .map(|(start, offset)| reference[start..start + offset].to_string())
-        .collect()
+use eyre::{eyre, ContextCompat, Result, WrapErr};
+use indicatif::ProgressBar;
+use log::{debug, info};
+use rand::{
+    distributions::{Uniform, WeightedIndex},
+    prelude::Distribution,
+    rngs::ThreadRng,
+    seq::IteratorRandom,
+    thread_rng, Rng,
+};
+
+/// How many random sequences are tried before giving up on finding an absent query
+const MAX_ABSENT_ATTEMPTS: usize = 10_000;
+
+/// How many windows are tried before giving up on finding one without ambiguous bases
+const MAX_UNAMBIGUOUS_ATTEMPTS: usize = 10_000;
+
+/// Where in the reference the sampled queries start
+enum StartSampler {
+    Uniform(Uniform<usize>),
+    /// Weighted by how often the k-mer at each start occurs in the reference
+    Weighted(WeightedIndex<u32>),
 }
 
-fn generate_perturbed_sequences(
-    reference: &str,
-    min_size: usize,
-    max_size: usize,
-    queries: usize,
-) -> Vec<String> {
-    let mut rng = thread_rng();
-    let starts: Vec<usize> = Uniform::new(0, reference.len() - max_size)
-        .sample_iter(&mut rng)
-        .take(queries)
-        .collect();
-    let offsets: Vec<usize> = Uniform::new_inclusive(min_size, max_size)
-        .sample_iter(&mut rng)
-        .take(queries)
-        .collect();
-    zip(starts, offsets)
-        .map(|(start, offset)| {
-            let mut buffer = String::new();
-            reference[start..start + offset].chars().for_each(|x| {
-                let num = rng.gen_range(0..100);
-                let next_char = if num <= 5 {
-                    x
-                } else {
-                    "ACTG".chars().choose(&mut rng).unwrap()
-                };
-                write!(&mut buffer, "{next_char}").unwrap();
-            });
-            buffer
+impl Distribution<usize> for StartSampler {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        match self {
+            Self::Uniform(starts) => starts.sample(rng),
+            Self::Weighted(starts) => starts.sample(rng),
+        }
+    }
+}
+
+struct QuerySampler {
+    starts: StartSampler,
+    offsets: Uniform<usize>,
+    rng: ThreadRng,
+}
+
+/// The stretches of the reference a query of at most `max_size` bases shares with a run of
+/// ambiguous bases, the run together with the `max_size - 1` bases on either side of it
+fn ambiguous_neighborhoods(reference: &str, max_size: usize) -> Vec<&str> {
+    let bytes = reference.as_bytes();
+    let mut neighborhoods = Vec::new();
+    let mut start = 0;
+    while let Some(offset) = bytes[start..]
+        .iter()
+        .position(|&b| b == AMBIGUOUS_BASE as u8)
+    {
+        let run_start = start + offset;
+        let run_end = bytes[run_start..]
+            .iter()
+            .position(|&b| b != AMBIGUOUS_BASE as u8)
+            .map_or(bytes.len(), |len| run_start + len);
+        neighborhoods.push(
+            &reference
+                [run_start.saturating_sub(max_size - 1)..(run_end + max_size - 1).min(bytes.len())],
+        );
+        start = run_end;
+    }
+    neighborhoods
+}
+
+impl QuerySampler {
+    fn new(reference: &str, min_size: usize, max_size: usize) -> Self {
+        Self {
+            starts: StartSampler::Uniform(Uniform::new(0, reference.len() - max_size)),
+            offsets: Uniform::new_inclusive(min_size, max_size),
+            rng: thread_rng(),
+        }
+    }
+
+    /// Same as [`QuerySampler::new`] but a start is picked in proportion to the number
+    /// of occurrences of the `k`-mer there, starts on an ambiguous base are never picked
+    fn weighted(reference: &str, min_size: usize, max_size: usize, k: usize) -> Result<Self> {
+        if k > reference.len() {
+            return Err(eyre!(
+                "--weight-k ({k}) is longer than the reference ({} bases)",
+                reference.len()
+            ));
+        }
+        if max_size > reference.len() {
+            return Err(eyre!(
+                "The maximum query length ({max_size}) is longer than the reference ({} bases)",
+                reference.len()
+            ));
+        }
+        let mut counts: HashMap<&str, u32> = HashMap::new();
+        for start in 0..=reference.len() - k {
+            *counts.entry(&reference[start..start + k]).or_default() += 1;
+        }
+        let weights = (0..reference.len() - max_size).map(|start| {
+            let kmer = &reference[start..(start + k).min(reference.len())];
+            if kmer.contains(AMBIGUOUS_BASE) {
+                0
+            } else {
+                counts.get(kmer).copied().unwrap_or(0)
+            }
+        });
+        let starts = WeightedIndex::new(weights)
+            .map_err(|err| eyre!("Could not weight the query starts: {err}"))?;
+        Ok(Self {
+            starts: StartSampler::Weighted(starts),
+            offsets: Uniform::new_inclusive(min_size, max_size),
+            rng: thread_rng(),
         })
-        .collect()
+    }
+
+    fn sample_window<'a>(&mut self, reference: &'a str) -> &'a str {
+        let start = self.starts.sample(&mut self.rng);
+        let offset = self.offsets.sample(&mut self.rng);
+        &reference[start..start + offset]
+    }
+
+    /// Samples a window of the reference, resampling windows that overlap an
+    /// ambiguous base since those were indexed as random nucleotides
+    fn generate_exact_match_sequence(&mut self, reference: &str) -> Result<String> {
+        for _ in 0..MAX_UNAMBIGUOUS_ATTEMPTS {
+            let window = self.sample_window(reference);
+            if !window.contains(AMBIGUOUS_BASE) {
+                return Ok(window.to_string());
+            }
+        }
+        Err(eyre!(
+            "Could not sample a query without ambiguous bases in {MAX_UNAMBIGUOUS_ATTEMPTS} attempts, try a smaller --max-length"
+        ))
+    }
+
+    /// Perturbs a window of the reference, which like an exact match never overlaps an
+    /// ambiguous base
+    fn generate_perturbed_sequence(&mut self, reference: &str) -> Result<String> {
+        let window = self.generate_exact_match_sequence(reference)?;
+        let mut buffer = String::new();
+        window.chars().for_each(|x| {
+            let num = self.rng.gen_range(0..100);
+            let next_char = if num <= 5 {
+                x
+            } else {
+                "ACTG".chars().choose(&mut self.rng).unwrap()
+            };
+            write!(&mut buffer, "{next_char}").unwrap();
+        });
+        Ok(buffer)
+    }
+
+    /// Generates a random sequence that does not occur anywhere in the indexed reference,
+    /// nor in any of the `neighborhoods` whatever nucleotides their ambiguous bases stand for
+    fn generate_absent_sequence(
+        &mut self,
+        index: &SuffixArray,
+        neighborhoods: &[&str],
+    ) -> Result<String> {
+        for _ in 0..MAX_ABSENT_ATTEMPTS {
+            let length = self.offsets.sample(&mut self.rng);
+            let query: String = (0..length)
+                .map(|_| "ACTG".chars().choose(&mut self.rng).unwrap())
+                .collect();
+            let matches_ambiguous = neighborhoods.iter().any(|neighborhood| {
+                neighborhood.as_bytes().windows(length).any(|window| {
+                    window
+                        .iter()
+                        .zip(query.bytes())
+                        .all(|(&reference, base)| iupac_matches(reference, base))
+                })
+            });
+            if !matches_ambiguous && index.simple_accelerant_search(&query).is_none() {
+                return Ok(query);
+            }
+        }
+        Err(eyre!(
+            "Could not generate a query absent from the reference in {MAX_ABSENT_ATTEMPTS} attempts, try a larger --min-length"
+        ))
+    }
 }
 
 pub fn main() -> Result<()> {
     let args = BuildQueryArgs::parse();
-    let mut reader = Reader::from_file(&args.reference).wrap_err(format!(
-        "Could not open reference file {:?}",
-        &args.reference
-    ))?;
+    init_logger();
+    args.validate()?;
+    let mut reader = Reader::from_file(&args.reference)
+        .wrap_err(format!(
+            "Could not open reference file {:?}",
+            &args.reference
+        ))?
+        .keep_ambiguous(args.skip_ambiguous);
     let record = reader
         .next()
         .wrap_err("The reference file was empty")
         .unwrap()
         .wrap_err("Could not parse reference file")?;
-    let queries = match args.strategy {
-        SampleStrategy::ExactMatch => generate_exact_match_sequences(
+    debug!(
+        "Sampling queries from {} with {} bases",
+        record.header(),
+        record.sequence().len()
+    );
+    // only the absent strategy needs to search the reference
+    let index: Option<SuffixArray> = matches!(args.strategy, SampleStrategy::Absent).then(|| {
+        SuffixArray::from_record(Record {
+            header: record.header.clone(),
+            sequence: record.sequence.clone(),
+        })
+    });
+    // without --skip-ambiguous the reference has no ambiguous bases left to avoid
+    let neighborhoods = if args.skip_ambiguous {
+        ambiguous_neighborhoods(record.sequence(), args.max_length as usize)
+    } else {
+        Vec::new()
+    };
+    let mut sampler = match args.strategy {
+        SampleStrategy::Repetitive => QuerySampler::weighted(
             record.sequence(),
             args.min_length as usize,
             args.max_length as usize,
-            args.queries,
-        ),
-        SampleStrategy::Perturb => generate_perturbed_sequences(
+            args.weight_k as usize,
+        )?,
+        _ => QuerySampler::new(
             record.sequence(),
             args.min_length as usize,
             args.max_length as usize,
-            args.queries,
         ),
     };
+    let progress = if args.progress {
+        ProgressBar::new(args.queries as u64)
+    } else {
+        ProgressBar::hidden()
+    };
     let mut writer: BufWriter<File> = BufWriter::new(File::create(&args.output)?);
-    for (idx, query) in queries.iter().enumerate() {
+    for idx in 0..args.queries {
+        let query = match args.strategy {
+            SampleStrategy::ExactMatch | SampleStrategy::Repetitive => {
+                sampler.generate_exact_match_sequence(record.sequence())?
+            }
+            SampleStrategy::Perturb => sampler.generate_perturbed_sequence(record.sequence())?,
+            SampleStrategy::Absent => {
+                sampler.generate_absent_sequence(index.as_ref().unwrap(), &neighborhoods)?
+            }
+        };
         write!(&mut writer, ">query-{idx}\n{query}\n")?;
+        progress.inc(1);
     }
     writer.flush()?;
+    progress.finish();
+    info!("Wrote {} queries to {:?}", args.queries, &args.output);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_matches_do_not_overlap_ambiguous_runs() {
+        let reference = format!(
+            "{}{}{}",
+            "ACGT".repeat(10),
+            "N".repeat(8),
+            "TTGCA".repeat(8)
+        );
+        let mut sampler = QuerySampler::new(&reference, 5, 10);
+        for _ in 0..1000 {
+            let query = sampler.generate_exact_match_sequence(&reference).unwrap();
+            assert!(!query.contains(AMBIGUOUS_BASE), "{query}");
+            assert!(reference.contains(&query));
+        }
+        let ambiguous = "N".repeat(20);
+        let mut sampler = QuerySampler::new(&ambiguous, 5, 10);
+        assert!(sampler.generate_exact_match_sequence(&ambiguous).is_err());
+    }
+
+    #[test]
+    fn perturbed_and_absent_queries_avoid_ambiguous_runs() {
+        let reference = format!("{}NN{}", "AC".repeat(10), "GT".repeat(10));
+        let mut sampler = QuerySampler::new(&reference, 4, 4);
+        for _ in 0..1000 {
+            let query = sampler.generate_perturbed_sequence(&reference).unwrap();
+            assert!(!query.contains(AMBIGUOUS_BASE), "{query}");
+        }
+        let index = SuffixArray::from_sequence(&reference);
+        let neighborhoods = ambiguous_neighborhoods(&reference, 4);
+        assert_eq!(neighborhoods, ["CACNNGTG"]);
+        for _ in 0..1000 {
+            let query = sampler
+                .generate_absent_sequence(&index, &neighborhoods)
+                .unwrap();
+            let matches = reference.as_bytes().windows(4).any(|window| {
+                window
+                    .iter()
+                    .zip(query.bytes())
+                    .all(|(&reference, base)| iupac_matches(reference, base))
+            });
+            assert!(!matches, "{query}");
+        }
+        // every query of 2 bases matches the run itself
+        let mut sampler = QuerySampler::new(&reference, 2, 2);
+        let neighborhoods = ambiguous_neighborhoods(&reference, 2);
+        assert!(sampler.generate_absent_sequence(&index, &[]).is_ok());
+        assert!(sampler
+            .generate_absent_sequence(&index, &neighborhoods)
+            .is_err());
+    }
+
+    #[test]
+    fn weighted_starts_favor_repetitive_regions() {
+        let unique = "GATCCTAGGTCAATGGCTTAGCGTATCGGAACTGTCCATGAGTTCAGGCTAATCGCTGAT";
+        let reference = format!("{}{unique}", "AC".repeat(60));
+        let mut sampler = QuerySampler::weighted(&reference, 5, 10, 4).unwrap();
+        let mut repetitive = 0;
+        for _ in 0..1000 {
+            let query = sampler.generate_exact_match_sequence(&reference).unwrap();
+            assert!(reference.contains(&query));
+            if query.starts_with("AC") || query.starts_with("CA") {
+                repetitive += 1;
+            }
+        }
+        // a uniform sampler starts about 70% of the queries in the repeat
+        assert!(repetitive > 900, "{repetitive}");
+        assert!(QuerySampler::weighted(&"N".repeat(20), 5, 10, 4).is_err());
+        assert!(QuerySampler::weighted("ACGT", 1, 2, 5).is_err());
+        assert!(QuerySampler::weighted("ACGT", 1, 10, 2).is_err());
+    }
+}
@@ -1,44 +1,182 @@
 use std::{
     fs::{metadata, File},
     io::BufWriter,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-use assignment_1::{args::BuildsaArgs, reader::Reader, suffix_array::SuffixArray};
+use assignment_1::{
+    args::{BuildsaArgs, IndexFormat},
+    logging::init_logger,
+    normalize::find_invalid,
+    raw_sa::{sidecar_path, write_raw_sa},
+    reader::{read_reference, sanitize_sequence, wait_for_complete_file},
+    record::Record,
+    suffix_array::{BuildOptions, SuffixArray},
+};
 use clap::Parser;
 use eyre::{eyre, Result, WrapErr};
+use log::{debug, info, warn};
 
 pub fn main() -> Result<()> {
     let args: BuildsaArgs = BuildsaArgs::parse();
-    let mut reader = Reader::from_file(&args.reference).wrap_err(format!(
-        "The reference file {:?} does not exist",
-        &args.reference
-    ))?;
-    let record = match reader.next() {
-        Some(record) => record.wrap_err("could not parse record"),
-        None => Err(eyre!(format!(
-            "The reference file {:?} was empty",
-            &args.reference
-        ))),
-    }?;
+    init_logger();
+    if args.array_only && args.format == IndexFormat::RawSa {
+        return Err(eyre!("--array-only requires the bincode format"));
+    }
+    if args.preftab_only && args.format == IndexFormat::RawSa {
+        return Err(eyre!("--preftab-only requires the bincode format"));
+    }
+    let records: Vec<Record> = match (&args.sequence, &args.reference) {
+        (Some(sequence), _) if sequence.trim().is_empty() => {
+            return Err(eyre!("The --sequence to index is empty"));
+        }
+        (Some(sequence), _) => {
+            if let Some((offset, invalid)) =
+                find_invalid(sequence.trim(), false).filter(|_| args.strict)
+            {
+                return Err(eyre!(
+                    "The --sequence is not a nucleotide sequence: found {invalid:?} at column {}",
+                    offset + 1
+                ));
+            }
+            vec![Record {
+                header: String::from("sequence"),
+                sequence: sanitize_sequence(sequence.trim()),
+            }]
+        }
+        (None, Some(reference)) => {
+            if let Some(attempts) = args.wait {
+                wait_for_complete_file(reference, attempts, Duration::from_secs(1))?;
+            }
+            read_reference(reference, |reader| {
+                reader
+                    .soft_masked(args.soft_masked)
+                    .keep_ambiguous(args.array_only)
+                    .strict(args.strict)
+                    .iupac(args.iupac)
+            })?
+        }
+        (None, None) => unreachable!("clap requires a reference unless --sequence is given"),
+    };
+    for record in &records {
+        debug!(
+            "Read record {} with {} bases",
+            record.header(),
+            record.sequence().len()
+        );
+    }
     let mut now: Instant = Instant::now();
-    let mut suffix_array = SuffixArray::from_record(record);
+    if args.threads > 1 && !cfg!(feature = "parallel") {
+        warn!("buildsa was built without the parallel feature, building on a single thread");
+    }
+    let options = BuildOptions {
+        sentinel: (&args.sentinel).into(),
+        threads: args.threads as usize,
+        alphabet: (&args.alphabet).into(),
+        soft_masked: args.soft_masked,
+        both_strands: args.both_strands,
+    };
+    let mut suffix_array = SuffixArray::from_records_with_options(records, options);
     let mut delta = Instant::now() - now;
-    println!("Constructing the suffix array took {delta:?}");
-    if let Some(k) = args.preftab {
-        println!("Building prefix table with k={k}");
+    info!("Constructing the suffix array took {delta:?}");
+    info!("Reference checksum: {}", suffix_array.checksum());
+    if suffix_array.has_both_strands() {
+        info!(
+            "Indexed {} records and their reverse complements",
+            suffix_array.forward_contigs().len()
+        );
+    } else if suffix_array.contigs().len() > 1 {
+        info!("Indexed {} records", suffix_array.contigs().len());
+    }
+    if let Some(mask) = suffix_array.soft_mask() {
+        info!("Soft-masked {} bases", mask.count());
+    }
+    if let (Some(k), Some(width)) = (args.preftab, args.adaptive_width) {
+        info!(
+            "Building adaptive prefix table with k={k} to {}, splitting buckets wider than {width}",
+            args.adaptive_max_k
+        );
+        now = Instant::now();
+        suffix_array.initialize_adaptive_prefix_table(k, args.adaptive_max_k, width);
+        delta = Instant::now() - now;
+        if let Some(table) = suffix_array.adaptive_prefix_table() {
+            info!(
+                "Constructing the adaptive prefix table ({} buckets, up to {} bases) took {delta:?}",
+                table.len(),
+                table.depth()
+            );
+        }
+    } else if let Some(k) = args.preftab {
+        info!("Building prefix table with k={k}");
         now = Instant::now();
         suffix_array.initialize_prefix_table(k);
         delta = Instant::now() - now;
-        println!("Constructing the prefix table took {delta:?}")
+        info!("Constructing the prefix table took {delta:?}")
     }
-    let writer: BufWriter<File> = BufWriter::new(
-        File::create(&args.output)
-            .wrap_err(format!("Failed to create output file {:?}", &args.output))?,
-    );
-    bincode::serialize_into(writer, &suffix_array)?;
+    if let Some(k) = args.bloom {
+        if !(args.bloom_fp_rate > 0.0 && args.bloom_fp_rate < 1.0) {
+            return Err(eyre!(
+                "--bloom-fp-rate must be between 0 and 1 but got {}",
+                args.bloom_fp_rate
+            ));
+        }
+        now = Instant::now();
+        suffix_array.initialize_bloom_filter(k, args.bloom_fp_rate);
+        if let Some(bloom) = suffix_array.bloom_filter() {
+            info!(
+                "Constructing the Bloom filter ({} bits, {} hashes) took {:?}",
+                bloom.len(),
+                bloom.hashes(),
+                now.elapsed()
+            );
+        }
+    }
+    now = Instant::now();
+    if args.format == IndexFormat::RawSa {
+        if args.preftab.is_some() {
+            warn!("The prefix table is not saved in the raw-sa format");
+        }
+        if args.bloom.is_some() {
+            warn!("The Bloom filter is not saved in the raw-sa format");
+        }
+        if args.soft_masked {
+            warn!("The soft mask is not saved in the raw-sa format");
+        }
+        write_raw_sa(&suffix_array, &args.output)?;
+        info!("Wrote the sequence to {:?}", sidecar_path(&args.output));
+    } else if args.array_only {
+        let writer: BufWriter<File> = BufWriter::new(
+            File::create(&args.output)
+                .wrap_err(format!("Failed to create output file {:?}", &args.output))?,
+        );
+        suffix_array.write_array_only(writer)?;
+        info!("Wrote only the suffix array, pass the reference to querysa --reference");
+    } else if args.preftab_only {
+        let writer: BufWriter<File> = BufWriter::new(
+            File::create(&args.output)
+                .wrap_err(format!("Failed to create output file {:?}", &args.output))?,
+        );
+        let index = suffix_array
+            .into_prefix_table_index()?
+            .canonical(args.canonical_kmers);
+        index.write(writer)?;
+        info!(
+            "Wrote only the prefix table, the index can only count queries of length {}",
+            index.k()
+        );
+    } else {
+        if args.packed {
+            suffix_array.pack()?;
+        }
+        let writer: BufWriter<File> = BufWriter::new(
+            File::create(&args.output)
+                .wrap_err(format!("Failed to create output file {:?}", &args.output))?,
+        );
+        bincode::serialize_into(writer, &suffix_array)?;
+    }
+    debug!("Writing the index took {:?}", now.elapsed());
     let file_size = metadata(&args.output)?.len();
-    println!(
+    info!(
         "The resulting file has size: {file_size} bytes or ~ {} MiB",
         file_size / 1024 / 1024
     );
@@ -0,0 +1,44 @@
+use assignment_1::{
+    args::DiffsaArgs,
+    results::{diff_results, read_results, Results},
+};
+use clap::Parser;
+use eyre::{eyre, Result};
+
+pub fn main() -> Result<()> {
+    let args = DiffsaArgs::parse();
+    let first: Results = read_results(&args.first)?;
+    let second: Results = read_results(&args.second)?;
+    let diff = diff_results(&first, &second);
+    if !args.quiet {
+        for header in &diff.differing {
+            let (a, b) = (&first[header], &second[header]);
+            println!(
+                "{header}: {} positions only in {:?}, {} only in {:?}",
+                a.difference(b).count(),
+                &args.first,
+                b.difference(a).count(),
+                &args.second
+            );
+        }
+        for header in &diff.only_first {
+            println!("{header}: only in {:?}", &args.first);
+        }
+        for header in &diff.only_second {
+            println!("{header}: only in {:?}", &args.second);
+        }
+    }
+    println!(
+        "{} queries match, {} differ, {} only in {:?} and {} only in {:?}",
+        diff.matching,
+        diff.differing.len(),
+        diff.only_first.len(),
+        &args.first,
+        diff.only_second.len(),
+        &args.second
+    );
+    if !diff.is_empty() {
+        return Err(eyre!("The result files differ"));
+    }
+    Ok(())
+}
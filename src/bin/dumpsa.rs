@@ -0,0 +1,93 @@
+use assignment_1::{
+    args::{DumpsaArgs, IndexFormat},
+    raw_sa::read_raw_sa,
+    suffix_array::{read_format_version, IndexInfo, SuffixArray},
+};
+use clap::Parser;
+use eyre::Result;
+
+/// The metadata of the index, with the format version read from a bincode index file
+/// (none for indices written before the layout was versioned)
+fn index_info(suffix_array: &SuffixArray, args: &DumpsaArgs) -> Result<IndexInfo> {
+    let mut info = suffix_array.info();
+    if matches!(args.format, IndexFormat::Bincode) {
+        info.index_format_version = read_format_version(&args.index)?;
+    }
+    Ok(info)
+}
+
+pub fn main() -> Result<()> {
+    let args = DumpsaArgs::parse();
+    let mut suffix_array: SuffixArray = match args.format {
+        IndexFormat::Bincode => SuffixArray::from_bincode_file(&args.index)?,
+        IndexFormat::RawSa => read_raw_sa(&args.index)?,
+    };
+    if let Some(k) = args.preftab {
+        suffix_array.initialize_prefix_table(k);
+    }
+    if args.info {
+        let info = index_info(&suffix_array, &args)?;
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+    println!("Suffixes: {}", suffix_array.suffix_array.len());
+    println!("Records: {}", suffix_array.contigs().len());
+    println!("Sentinel order: {:?}", suffix_array.sentinel_order());
+    println!("Packed: {}", suffix_array.is_packed());
+    println!("Reference checksum: {}", suffix_array.checksum());
+    if let Some(bloom) = suffix_array.bloom_filter() {
+        println!(
+            "Bloom filter: k={}, {} bits, {} hashes",
+            bloom.k(),
+            bloom.len(),
+            bloom.hashes()
+        );
+    }
+    if let Some(table) = suffix_array.adaptive_prefix_table() {
+        println!(
+            "Adaptive prefix table: k={} to {}, {} buckets, buckets wider than {} are split",
+            table.min_k(),
+            table.depth(),
+            table.len(),
+            table.max_width()
+        );
+        return Ok(());
+    }
+    match suffix_array.prefix_table() {
+        Some(table) => println!("Prefix table\n{}", table.stats()),
+        None => println!("No prefix table (pass --preftab to compute one)"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assignment_1::{
+        prefix_table::PrefixTable, suffix_array::FORMAT_VERSION, testing::temp_path,
+    };
+    use std::fs::File;
+
+    #[test]
+    fn legacy_indices_report_no_format_version() {
+        let sa = SuffixArray::from_sequence("ACGTACGGACGTTACGTACGA");
+        let current = temp_path("dumpsa_current.sa");
+        let legacy = temp_path("dumpsa_legacy.sa");
+        bincode::serialize_into(File::create(&current).unwrap(), &sa).unwrap();
+        // the layout of indices written before the format version was added
+        let layout = (&*sa.sequence(), &sa.suffix_array, None::<PrefixTable>);
+        bincode::serialize_into(File::create(&legacy).unwrap(), &layout).unwrap();
+        for (path, expected) in [(&current, Some(FORMAT_VERSION)), (&legacy, None)] {
+            let args = DumpsaArgs::try_parse_from([
+                "dumpsa".as_ref(),
+                path.as_os_str(),
+                "--info".as_ref(),
+            ])
+            .unwrap();
+            let loaded = SuffixArray::from_bincode_file(path).unwrap();
+            let info = index_info(&loaded, &args).unwrap();
+            assert_eq!(info.index_format_version, expected, "{path:?}");
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use assignment_1::{
+    args::{IndexFormat, KmerspectrumArgs},
+    prefix_table::PrefixTable,
+    raw_sa::read_raw_sa,
+    suffix_array::{
+        read_format_version, PrefixTableIndex, SuffixArray, PREFIX_TABLE_ONLY_FORMAT_VERSION,
+    },
+};
+use clap::Parser;
+use eyre::{eyre, Result, WrapErr};
+
+/// Writes `kmer<TAB>count` for every k-mer of the table in lexicographic order, only
+/// the canonical k-mers counted on both strands if `canonical` is set
+fn write_spectrum(table: &PrefixTable, canonical: bool, writer: &mut dyn Write) -> Result<()> {
+    let kmers: Vec<(String, usize)> = if canonical {
+        table.canonical_counts().into_iter().collect()
+    } else {
+        let mut kmers: Vec<(String, usize)> = table
+            .iter()
+            .map(|(kmer, span)| (kmer, span.len()))
+            .collect();
+        kmers.sort_unstable();
+        kmers
+    };
+    for (kmer, count) in kmers {
+        writeln!(writer, "{kmer}\t{count}")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn main() -> Result<()> {
+    let args = KmerspectrumArgs::parse();
+    let mut writer: Box<dyn Write> = match &args.output {
+        Some(filepath) => Box::new(BufWriter::new(
+            File::create(filepath)
+                .wrap_err(format!("Could not create output file {filepath:?}"))?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
+    if args.format == IndexFormat::Bincode
+        && read_format_version(&args.index)? == Some(PREFIX_TABLE_ONLY_FORMAT_VERSION)
+    {
+        if args.preftab.is_some() {
+            return Err(eyre!(
+                "--preftab needs the suffix array but {:?} only holds a prefix table",
+                &args.index
+            ));
+        }
+        let index = PrefixTableIndex::from_file(&args.index)?;
+        let canonical = args.canonical || index.is_canonical();
+        return write_spectrum(index.prefix_table(), canonical, &mut writer);
+    }
+    let mut suffix_array: SuffixArray = match args.format {
+        IndexFormat::Bincode => SuffixArray::from_bincode_file(&args.index)?,
+        IndexFormat::RawSa => read_raw_sa(&args.index)?,
+    };
+    if let Some(k) = args.preftab {
+        suffix_array.initialize_prefix_table(k);
+    }
+    let table = suffix_array.prefix_table().ok_or_else(|| {
+        eyre!("The index has no prefix table, pass --preftab to count the k-mers of a size")
+    })?;
+    write_spectrum(table, args.canonical, &mut writer)
+}
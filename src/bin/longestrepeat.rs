@@ -0,0 +1,39 @@
+use assignment_1::{
+    args::{IndexFormat, LongestrepeatArgs},
+    raw_sa::read_raw_sa,
+    suffix_array::{QueryMode, SuffixArray},
+};
+use clap::Parser;
+use eyre::Result;
+
+pub fn main() -> Result<()> {
+    let args = LongestrepeatArgs::parse();
+    let mut suffix_array: SuffixArray = match args.format {
+        IndexFormat::Bincode => SuffixArray::from_bincode_file(&args.index)?,
+        IndexFormat::RawSa => read_raw_sa(&args.index)?,
+    };
+    suffix_array.unpack();
+    let Some((position, length)) = suffix_array.longest_repeated_substring() else {
+        println!("No substring occurs more than once in the reference");
+        return Ok(());
+    };
+    let sequence = suffix_array.sequence();
+    let repeat = &sequence[position as usize..position as usize + length];
+    let occurrences = suffix_array
+        .search(repeat, QueryMode::Simpaccel)
+        .map_or(0, |span| span.len());
+    let location = suffix_array
+        .contigs()
+        .iter()
+        .find_map(|contig| Some((contig.name.as_str(), contig.locate(position)?)));
+    match location {
+        Some((name, offset)) => println!(
+            "Longest repeated substring: {length} bases at {name}:{offset} ({occurrences} occurrences)"
+        ),
+        None => println!(
+            "Longest repeated substring: {length} bases at {position} ({occurrences} occurrences)"
+        ),
+    }
+    println!("{repeat}");
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use assignment_1::{
+    args::{IndexFormat, MatchMode, MultiqueryArgs},
+    raw_sa::read_raw_sa,
+    reader::Reader,
+    record::Record,
+    search::Span,
+    suffix_array::{QueryMode, SuffixArray},
+};
+use clap::Parser;
+use eyre::{Result, WrapErr};
+use std::{
+    fmt::Write as FmtWrite,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// An index loaded from disk along with the name its results are tagged with
+struct Source {
+    name: String,
+    suffix_array: SuffixArray,
+}
+
+impl Source {
+    fn load(path: &Path, format: &IndexFormat) -> Result<Self> {
+        let suffix_array = match format {
+            IndexFormat::Bincode => SuffixArray::from_bincode_file(path)?,
+            IndexFormat::RawSa => read_raw_sa(path)?,
+        };
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        Ok(Self { name, suffix_array })
+    }
+
+    fn positions(&self, record: &Record, query_mode: QueryMode, match_mode: &MatchMode) -> &[u32] {
+        let result: Option<Span> = match match_mode {
+            MatchMode::Substring => self.suffix_array.search(record.sequence(), query_mode),
+            MatchMode::Whole => self
+                .suffix_array
+                .search_whole(record.sequence(), query_mode),
+            MatchMode::Anchored => self
+                .suffix_array
+                .search_anchored(record.sequence(), query_mode),
+        };
+        match result {
+            Some(span) => &self.suffix_array.suffix_array[span.range()],
+            None => &[],
+        }
+    }
+}
+
+/// Formats the merged results of a query as
+/// `header, total, name_1, count_1, positions_1..., name_2, count_2, ...`
+/// with one group per index in the order the indices were given
+fn format_output_line(record: &Record, results: &[(&str, &[u32])]) -> String {
+    let mut line: String = record.header().to_string();
+    let total: usize = results.iter().map(|(_, positions)| positions.len()).sum();
+    write!(&mut line, ", {total}").unwrap();
+    for (name, positions) in results.iter() {
+        write!(&mut line, ", {name}, {}", positions.len()).unwrap();
+        positions
+            .iter()
+            .for_each(|&idx| write!(&mut line, ", {idx}").unwrap());
+    }
+    line
+}
+
+pub fn main() -> Result<()> {
+    let args = MultiqueryArgs::parse();
+    let sources: Vec<Source> = args
+        .indices
+        .iter()
+        .map(|path| Source::load(path, &args.format))
+        .collect::<Result<_>>()?;
+    let query_mode: QueryMode = (&args.query_mode).into();
+    let reader: Reader = Reader::from_file(&args.queries)
+        .wrap_err(format!("Could not find query file {:?}", &args.queries))?;
+    let mut writer: BufWriter<File> = BufWriter::new(
+        File::create(&args.output)
+            .wrap_err(format!("Could not create output file {:?}", &args.output))?,
+    );
+    let mut total: Duration = Duration::default();
+    let mut record_count = 0_usize;
+    for result in reader {
+        let record: Record = result?;
+        let now: Instant = Instant::now();
+        let results: Vec<(&str, &[u32])> = sources
+            .iter()
+            .map(|source| {
+                (
+                    source.name.as_str(),
+                    source.positions(&record, query_mode, &args.match_mode),
+                )
+            })
+            .collect();
+        total += Instant::now() - now;
+        writeln!(writer, "{}", format_output_line(&record, &results))?;
+        record_count += 1;
+    }
+    writer.flush()?;
+    println!(
+        "Took {total:?} to find matches in {record_count} queries across {} indices",
+        sources.len()
+    );
+    Ok(())
+}
@@ -1,76 +1,1155 @@
 use assignment_1::{
-    args::{QueryMode, QuerysaArgs},
-    reader::Reader,
+    args::{IndexFormat, MatchMode, OutputOrder, QueryFormat, QuerysaArgs},
+    encoding::reverse_complement,
+    logging::init_logger,
+    normalize::{find_invalid, normalize_sequence, NormalizePolicy},
+    raw_sa::read_raw_sa,
+    reader::{read_reference, Reader},
     record::Record,
     search::Span,
-    suffix_array::SuffixArray,
+    suffix_array::{
+        merge_occurrences, read_format_version, Contig, Coverage, OccurrenceStats,
+        PrefixTableIndex, QueryMode, SearchTimings, StartSpanStats, Strand, SuffixArray,
+        PREFIX_TABLE_ONLY_FORMAT_VERSION,
+    },
 };
-use clap::Parser;
-use eyre::{Result, WrapErr};
+use eyre::{eyre, Result, WrapErr};
+use flate2::{write::GzEncoder, Compression};
+use log::{debug, info, warn};
 use std::{
+    collections::{BTreeMap, HashSet},
     fmt::Write as FmtWrite,
     fs::File,
-    io::{BufReader, BufWriter, Write},
+    io::{self, BufRead, BufWriter, Write},
+    path::Path,
     time::{Duration, Instant},
 };
 
-fn format_output_line(suffix_array: &SuffixArray, record: &Record, result: Option<Span>) -> String {
+/// The `--output` file, gzip compressed with `--gzip` or a `.gz` extension, or standard
+/// output when the path is `-`
+enum OutputFile {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Stdout(BufWriter<io::Stdout>),
+    GzipStdout(GzEncoder<BufWriter<io::Stdout>>),
+}
+
+impl OutputFile {
+    fn create(path: &Path, gzip: bool) -> Result<Self> {
+        if path.as_os_str() == "-" {
+            let writer = BufWriter::new(io::stdout());
+            return Ok(if gzip {
+                Self::GzipStdout(GzEncoder::new(writer, Compression::default()))
+            } else {
+                Self::Stdout(writer)
+            });
+        }
+        let writer: BufWriter<File> = BufWriter::new(
+            File::create(path).wrap_err(format!("Could not create output file {path:?}"))?,
+        );
+        if gzip || path.extension().is_some_and(|extension| extension == "gz") {
+            Ok(Self::Gzip(GzEncoder::new(writer, Compression::default())))
+        } else {
+            Ok(Self::Plain(writer))
+        }
+    }
+
+    /// Flushes the output, writing the end of the gzip stream which a dropped encoder
+    /// may leave out
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Plain(mut writer) => writer.flush(),
+            Self::Gzip(encoder) => encoder.finish()?.flush(),
+            Self::Stdout(mut writer) => writer.flush(),
+            Self::GzipStdout(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Stdout(writer) => writer.write(buf),
+            Self::GzipStdout(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Stdout(writer) => writer.flush(),
+            Self::GzipStdout(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Prints the reference checksum, to stderr when the results go to stdout so they can be
+/// piped
+fn print_checksum(checksum: &str, args: &QuerysaArgs) {
+    if args.output_is_stdout() {
+        eprintln!("Reference checksum: {checksum}");
+    } else {
+        println!("Reference checksum: {checksum}");
+    }
+}
+
+/// Formats the matches of a query as `header, count, positions...`
+///
+/// Positions are 0-based unless `--one-based` is set and sorted by position unless
+/// `--order sa` asks for suffix array order. With `--merge-adjacent` the line is
+/// `header, count, start-end...` listing the merged intervals instead, half-open
+/// when 0-based and closed when 1-based.
+fn format_output_line(
+    suffix_array: &SuffixArray,
+    record: &Record,
+    result: Option<Span>,
+    contig: Option<&Contig>,
+    args: &QuerysaArgs,
+) -> String {
+    let Some(span) = result else {
+        return format_positions(record, &[], args);
+    };
+    let query_len = record.sequence().trim().len() as u32;
+    let positions = report_positions(suffix_array, span, query_len, contig, args);
+    format_positions(record, &positions, args)
+}
+
+/// Formats the reported positions of a query as `header, count, positions...`, or as
+/// the merged intervals with `--merge-adjacent`
+fn format_positions(record: &Record, positions: &[u32], args: &QuerysaArgs) -> String {
+    let sep = args.delimiter.as_str();
+    let base = u32::from(args.one_based);
+    let query_len = record.sequence().trim().len() as u32;
     let mut line: String = record.header().to_string();
-    match result {
-        None => write!(&mut line, ", 0").unwrap(),
-        Some((start, end)) => {
-            write!(&mut line, ", {}", end - start).unwrap();
-            // suffix_array.suffix_array[start..end]
-            suffix_array.suffix_array[start as usize..end as usize]
-                .iter()
-                .for_each(|&idx| write!(&mut line, ", {idx}").unwrap());
+    if let Some(gap) = args.merge_adjacent {
+        let intervals = merge_occurrences(positions, query_len, gap);
+        write!(&mut line, "{sep}{}", intervals.len()).unwrap();
+        intervals
+            .iter()
+            .for_each(|span| write!(&mut line, "{sep}{}-{}", span.start + base, span.end).unwrap());
+        return line;
+    }
+    write!(&mut line, "{sep}{}", positions.len()).unwrap();
+    positions
+        .iter()
+        .for_each(|&idx| write!(&mut line, "{sep}{}", idx + base).unwrap());
+    line
+}
+
+/// The position an occurrence of a query `query_len` long is reported at: on the forward
+/// strand, relative to `contig`, or `None` if it lies outside `contig` or `--region` or (with
+/// `--unmasked-only`) overlaps a soft-masked base
+fn locate_occurrence(
+    suffix_array: &SuffixArray,
+    idx: u32,
+    query_len: u32,
+    contig: Option<&Contig>,
+    args: &QuerysaArgs,
+) -> Option<u32> {
+    if args.unmasked_only && !suffix_array.is_unmasked(idx, query_len) {
+        return None;
+    }
+    // matches on the reverse strand of a --both-strands index map back to the forward strand
+    let (_, idx) = suffix_array.locate_strand(idx, query_len);
+    let position = match contig {
+        Some(contig) => contig.locate(idx)?,
+        None => idx,
+    };
+    match args.region {
+        Some(region) => {
+            // a 1-based closed region starts one base earlier in 0-based coordinates
+            let start = region.start.saturating_sub(u32::from(args.one_based));
+            (position >= start && position + query_len <= region.end).then_some(position)
         }
+        None => Some(position),
+    }
+}
+
+/// The positions of the occurrences in `span` as they are reported: relative to
+/// `contig` (and only those inside it) and sorted unless `--order sa` is set
+fn report_positions(
+    suffix_array: &SuffixArray,
+    span: Span,
+    query_len: u32,
+    contig: Option<&Contig>,
+    args: &QuerysaArgs,
+) -> Vec<u32> {
+    report_indices(
+        suffix_array,
+        span.iter_positions(&suffix_array.suffix_array),
+        query_len,
+        contig,
+        args,
+    )
+}
+
+/// Same as [`report_positions`] for the given suffix array entries
+fn report_indices(
+    suffix_array: &SuffixArray,
+    indices: impl Iterator<Item = u32>,
+    query_len: u32,
+    contig: Option<&Contig>,
+    args: &QuerysaArgs,
+) -> Vec<u32> {
+    let mut positions: Vec<u32> = indices
+        .filter_map(|idx| locate_occurrence(suffix_array, idx, query_len, contig, args))
+        .collect();
+    if suffix_array.has_both_strands() {
+        // a palindromic query matches both strands at the same forward position
+        let mut seen = HashSet::new();
+        positions.retain(|&position| seen.insert(position));
+    }
+    if matches!(args.order, OutputOrder::Genomic) || args.merge_adjacent.is_some() {
+        positions.sort_unstable();
+    }
+    positions
+}
+
+/// Formats the matches of a query and of its reverse complement as
+/// `header, forward count, reverse count, forward positions..., reverse positions...`
+fn format_strand_line(
+    suffix_array: &SuffixArray,
+    record: &Record,
+    (forward, reverse): (Option<Span>, Option<Span>),
+    contig: Option<&Contig>,
+    args: &QuerysaArgs,
+) -> String {
+    let sep = args.delimiter.as_str();
+    let base = u32::from(args.one_based);
+    let query_len = record.sequence().trim().len() as u32;
+    let [forward, reverse] = if suffix_array.has_both_strands() {
+        // the forward search already found the matches on both strands of the index
+        let (forward, reverse): (Vec<u32>, Vec<u32>) = forward
+            .map(|span| {
+                span.iter_positions(&suffix_array.suffix_array)
+                    .collect::<Vec<u32>>()
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|&idx| suffix_array.locate_strand(idx, query_len).0 == Strand::Forward);
+        [forward, reverse].map(|indices| {
+            report_indices(suffix_array, indices.into_iter(), query_len, contig, args)
+        })
+    } else {
+        [forward, reverse].map(|result| match result {
+            Some(span) => report_positions(suffix_array, span, query_len, contig, args),
+            None => Vec::new(),
+        })
+    };
+    let mut line: String = record.header().to_string();
+    write!(&mut line, "{sep}{}{sep}{}", forward.len(), reverse.len()).unwrap();
+    forward
+        .iter()
+        .chain(reverse.iter())
+        .for_each(|&idx| write!(&mut line, "{sep}{}", idx + base).unwrap());
+    line
+}
+
+/// Formats the matches of a query and of its reverse complement together as
+/// `header, count, positions...`
+fn format_canonical_line(
+    suffix_array: &SuffixArray,
+    record: &Record,
+    (forward, reverse): (Option<Span>, Option<Span>),
+    contig: Option<&Contig>,
+    args: &QuerysaArgs,
+) -> String {
+    let sep = args.delimiter.as_str();
+    let base = u32::from(args.one_based);
+    let query_len = record.sequence().trim().len() as u32;
+    let mut positions: Vec<u32> = [forward, reverse]
+        .into_iter()
+        .flatten()
+        .flat_map(|span| report_positions(suffix_array, span, query_len, contig, args))
+        .collect();
+    if matches!(args.order, OutputOrder::Genomic) {
+        positions.sort_unstable();
     }
+    let mut line: String = record.header().to_string();
+    write!(&mut line, "{sep}{}", positions.len()).unwrap();
+    positions
+        .iter()
+        .for_each(|&idx| write!(&mut line, "{sep}{}", idx + base).unwrap());
     line
 }
 
-pub fn main() -> Result<()> {
-    let args = QuerysaArgs::parse();
-    let buf_reader = BufReader::new(
-        File::open(&args.index).wrap_err(format!("Could not open index file {:?}", &args.index))?,
+/// Formats the number of occurrences of each prefix length as `header, length:count...`
+fn format_profile_line(
+    suffix_array: &SuffixArray,
+    record: &Record,
+    query_mode: QueryMode,
+    min_len: usize,
+    max_len: usize,
+    sep: &str,
+) -> String {
+    let mut line: String = record.header().to_string();
+    suffix_array
+        .length_profile(record.sequence(), min_len, max_len, query_mode)
+        .iter()
+        .for_each(|(length, count)| write!(&mut line, "{sep}{length}:{count}").unwrap());
+    line
+}
+
+/// Formats the number of occurrences of each window of the query as `header, offset:count...`
+fn format_tile_line(
+    suffix_array: &SuffixArray,
+    record: &Record,
+    query_mode: QueryMode,
+    k: usize,
+    step: usize,
+    sep: &str,
+) -> String {
+    let mut line: String = record.header().to_string();
+    suffix_array
+        .tile_counts(record.sequence(), k, step, query_mode)
+        .iter()
+        .for_each(|(offset, count)| write!(&mut line, "{sep}{offset}:{count}").unwrap());
+    line
+}
+
+/// Formats the output line for a query as selected by the arguments
+fn format_record(
+    suffix_array: &SuffixArray,
+    record: &Record,
+    result: Option<Span>,
+    contig: Option<&Contig>,
+    query_mode: QueryMode,
+    args: &QuerysaArgs,
+) -> String {
+    match (args.length_profile.as_deref(), args.tile) {
+        (Some(&[min_len, max_len]), _) => format_profile_line(
+            suffix_array,
+            record,
+            query_mode,
+            min_len,
+            max_len,
+            args.delimiter.as_str(),
+        ),
+        (_, Some(k)) => format_tile_line(
+            suffix_array,
+            record,
+            query_mode,
+            k as usize,
+            args.step as usize,
+            args.delimiter.as_str(),
+        ),
+        _ => format_output_line(suffix_array, record, result, contig, args),
+    }
+}
+
+/// The number of occurrences reported for a query (only those inside `contig` if given)
+fn count_occurrences(
+    suffix_array: &SuffixArray,
+    record: &Record,
+    result: Option<Span>,
+    contig: Option<&Contig>,
+    args: &QuerysaArgs,
+) -> usize {
+    let query_len = record.sequence().trim().len() as u32;
+    match (result, contig) {
+        (None, _) => 0,
+        (Some(span), None)
+            if !args.unmasked_only && args.region.is_none() && !suffix_array.has_both_strands() =>
+        {
+            span.len()
+        }
+        (Some(span), _) if suffix_array.has_both_strands() => {
+            report_positions(suffix_array, span, query_len, contig, args).len()
+        }
+        (Some(span), _) => span
+            .iter_positions(&suffix_array.suffix_array)
+            .filter(|&idx| locate_occurrence(suffix_array, idx, query_len, contig, args).is_some())
+            .count(),
+    }
+}
+
+/// Formats the totals of a run followed by how many queries had each number of
+/// occurrences, as `occurrences, queries` lines
+fn format_summary(
+    record_count: usize,
+    matched_count: usize,
+    occurrence_count: usize,
+    histogram: &BTreeMap<usize, usize>,
+    sep: &str,
+) -> String {
+    let mut summary = format!(
+        "queries{sep}{record_count}\nmatched queries{sep}{matched_count}\ntotal occurrences{sep}{occurrence_count}\noccurrences{sep}queries\n"
     );
-    let suffix_array: SuffixArray =
-        bincode::deserialize_from(buf_reader).wrap_err("Failed to deserialize suffix array")?;
+    histogram.iter().for_each(|(occurrences, queries)| {
+        writeln!(&mut summary, "{occurrences}{sep}{queries}").unwrap()
+    });
+    summary
+}
+
+/// Adds every reported occurrence of a query to the coverage track
+fn add_coverage(
+    coverage: &mut Coverage,
+    suffix_array: &SuffixArray,
+    record: &Record,
+    result: Option<Span>,
+    contig: Option<&Contig>,
+    args: &QuerysaArgs,
+) {
+    let Some(span) = result else {
+        return;
+    };
+    let query_len = record.sequence().trim().len() as u32;
+    span.iter_positions(&suffix_array.suffix_array)
+        .filter(|&idx| locate_occurrence(suffix_array, idx, query_len, contig, args).is_some())
+        .for_each(|idx| coverage.add(suffix_array.locate_strand(idx, query_len).1, query_len));
+}
+
+/// Writes the header of a query without occurrences, or the whole record as FASTA
+/// when `with_sequence` is set
+fn write_unmatched(writer: &mut dyn Write, record: &Record, with_sequence: bool) -> Result<()> {
+    if with_sequence {
+        writeln!(writer, ">{}\n{}", record.header(), record.sequence().trim())?;
+    } else {
+        writeln!(writer, "{}", record.header())?;
+    }
+    Ok(())
+}
+
+fn run_query(
+    suffix_array: &SuffixArray,
+    record: &Record,
+    query_mode: QueryMode,
+    match_mode: &MatchMode,
+    timings: Option<&mut SearchTimings>,
+) -> Option<Span> {
+    match (match_mode, timings) {
+        (MatchMode::Substring, Some(timings)) => {
+            suffix_array.search_timed(record.sequence(), query_mode, timings)
+        }
+        (MatchMode::Substring, None) => suffix_array.search(record.sequence(), query_mode),
+        (MatchMode::Whole, _) => suffix_array.search_whole(record.sequence(), query_mode),
+        (MatchMode::Anchored, _) => suffix_array.search_anchored(record.sequence(), query_mode),
+    }
+}
+
+/// Searches for the longest window of the query left after clipping at most `max_clip`
+/// bases from each end
+///
+/// Returns a record holding the window, with the number of bases clipped from the left
+/// and right end appended to the header, so results are reported as
+/// `header, left, right, count, positions...`
+fn soft_clip_query(
+    suffix_array: &SuffixArray,
+    record: &Record,
+    max_clip: usize,
+    query_mode: QueryMode,
+    sep: &str,
+) -> (Record, Option<Span>) {
+    let sequence = record
+        .sequence()
+        .trim_matches(|x: char| x.is_ascii_whitespace());
+    let (left, right, result) =
+        match suffix_array.search_soft_clipped(sequence, max_clip, query_mode) {
+            Some((left, right, span)) => (left, right, Some(span)),
+            None => (0, 0, None),
+        };
+    let clipped = Record {
+        header: format!("{}{sep}{left}{sep}{right}", record.header()),
+        sequence: sequence[left..sequence.len() - right].to_string(),
+    };
+    (clipped, result)
+}
+
+/// Answer requests read line by line from stdin until it is closed
+///
+/// Each line is either the path to a FASTA file of queries or a single raw query.
+/// The results for a request are flushed before the next line is read.
+fn serve(
+    suffix_array: &SuffixArray,
+    args: &QuerysaArgs,
+    query_mode: QueryMode,
+    contig: Option<&Contig>,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    let rna = args.rna_query;
+    let line_delimited = matches!(args.query_format, QueryFormat::Lines);
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let request = line.trim();
+        if request.is_empty() {
+            continue;
+        }
+        // a line starting with @ names a query file, any other line is a query sequence
+        let records: Vec<Record> = if let Some(path) = request.strip_prefix('@') {
+            let path = path.trim_start();
+            let reader = Reader::from_file(path);
+            let reader = reader.map(|reader| {
+                reader
+                    .rna(rna)
+                    .strict(args.validate_queries)
+                    .min_length(args.min_query_len)
+                    .line_delimited(line_delimited)
+            });
+            match reader.map(|reader| reader.collect()) {
+                Ok(Ok(records)) => records,
+                Ok(Err(e)) => {
+                    warn!("Could not parse query file {path:?}: {e}");
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Could not open query file {path:?}: {e}");
+                    continue;
+                }
+            }
+        } else {
+            let invalid = find_invalid(request, rna).filter(|_| args.validate_queries);
+            if let Some((offset, invalid)) = invalid {
+                warn!("Query {request:?} is not a nucleotide sequence: found {invalid:?} at offset {offset}");
+                continue;
+            }
+            let policy = NormalizePolicy {
+                rna,
+                ..NormalizePolicy::QUERY
+            };
+            let (sequence, _) = normalize_sequence(request, policy);
+            if sequence.len() < args.min_query_len {
+                continue;
+            }
+            vec![Record {
+                header: request.to_string(),
+                sequence,
+            }]
+        };
+        for record in records {
+            let (record, res) = match args.soft_clip {
+                Some(max_clip) => soft_clip_query(
+                    suffix_array,
+                    &record,
+                    max_clip,
+                    query_mode,
+                    args.delimiter.as_str(),
+                ),
+                None => {
+                    let res = run_query(suffix_array, &record, query_mode, &args.match_mode, None);
+                    (record, res)
+                }
+            };
+            writeln!(
+                writer,
+                "{}",
+                format_record(suffix_array, &record, res, contig, query_mode, args)
+            )?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Counts the queries against an index written by buildsa --preftab-only, writing
+/// `header, count` lines since the occurrences cannot be located without the suffix array
+fn count_with_prefix_table(index: &PrefixTableIndex, args: &QuerysaArgs) -> Result<()> {
+    let unsupported = [
+        ("--serve", args.serve),
+        ("--contig", args.contig.is_some()),
+        ("--region", args.region.is_some()),
+        ("--merge-adjacent", args.merge_adjacent.is_some()),
+        ("--length-profile", args.length_profile.is_some()),
+        ("--tile", args.tile.is_some()),
+        ("--soft-clip", args.soft_clip.is_some()),
+        ("--coverage-out", args.coverage_out.is_some()),
+        ("--unmasked-only", args.unmasked_only),
+        ("--match-mode", args.match_mode != MatchMode::Substring),
+        ("--summary-only", args.summary_only),
+        ("--stats-only", args.stats_only),
+        ("--strand-counts", args.strand_counts),
+        ("--canonical", args.canonical),
+        ("--diagnose", args.diagnose),
+        ("--phase-timings", args.phase_timings),
+        ("--unmatched-out", args.unmatched_out.is_some()),
+        ("--flush-every", args.flush_every.is_some()),
+        ("--flush-interval", args.flush_interval.is_some()),
+        ("--no-preftab", args.no_preftab),
+    ];
+    if let Some((flag, _)) = unsupported.iter().find(|(_, set)| *set) {
+        return Err(eyre!(
+            "{flag} is not supported by {:?}, which only holds a prefix table",
+            &args.index
+        ));
+    }
+    if let Some(expected) = &args.reference_checksum {
+        index.verify_reference(expected)?;
+    }
+    if args.print_checksum {
+        print_checksum(index.checksum(), args);
+    }
     let reader: Reader = Reader::from_file(&args.queries)
-        .wrap_err(format!("Could not find query file {:?}", &args.queries))?;
+        .wrap_err(format!("Could not find query file {:?}", &args.queries))?
+        .rna(args.rna_query)
+        .strict(args.validate_queries)
+        .min_length(args.min_query_len)
+        .line_delimited(matches!(args.query_format, QueryFormat::Lines))
+        .split_on(args.split_on);
+    let mut writer = match &args.output {
+        Some(filepath) => Some(OutputFile::create(filepath, args.gzip)?),
+        None => None,
+    };
+    let sep = args.delimiter.as_str();
+    let (mut record_count, mut occurrence_count) = (0_usize, 0_usize);
+    let mut error_count = 0_usize;
+    let now: Instant = Instant::now();
+    for result in reader {
+        let record: Record = match result {
+            Ok(record) => record,
+            Err(e) if args.skip_errors => {
+                warn!("Skipping a query record that could not be parsed: {e}");
+                error_count += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let count = index
+            .count(record.sequence())
+            .wrap_err(format!("Could not count the query {}", record.header()))?;
+        if let Some(ref mut writer) = writer {
+            writeln!(writer, "{}{sep}{count}", record.header())?;
+        }
+        occurrence_count += count;
+        record_count += 1;
+    }
+    if let Some(writer) = writer {
+        writer.finish()?;
+    }
+    info!(
+        "Took {:?} to count {occurrence_count} total occurrences across {record_count} queries",
+        now.elapsed()
+    );
+    if error_count > 0 {
+        info!("Skipped {error_count} query records that could not be parsed");
+    }
+    Ok(())
+}
+
+/// Searches every query with [`SuffixArray::search_iupac`] against an index built with
+/// buildsa --iupac, writing `header, count, positions...` lines
+fn search_iupac_queries(
+    suffix_array: &SuffixArray,
+    contig: Option<&Contig>,
+    args: &QuerysaArgs,
+) -> Result<()> {
+    let reader: Reader = Reader::from_file(&args.queries)
+        .wrap_err(format!("Could not find query file {:?}", &args.queries))?
+        .rna(args.rna_query)
+        .strict(args.validate_queries)
+        .min_length(args.min_query_len)
+        .line_delimited(matches!(args.query_format, QueryFormat::Lines))
+        .split_on(args.split_on);
+    let mut writer = match &args.output {
+        Some(filepath) => Some(OutputFile::create(filepath, args.gzip)?),
+        None => None,
+    };
+    let (mut record_count, mut occurrence_count) = (0_usize, 0_usize);
+    let mut error_count = 0_usize;
+    let now: Instant = Instant::now();
+    for result in reader {
+        let record: Record = match result {
+            Ok(record) => record,
+            Err(e) if args.skip_errors => {
+                warn!("Skipping a query record that could not be parsed: {e}");
+                error_count += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let query_len = record.sequence().trim().len() as u32;
+        let positions: Vec<u32> = suffix_array
+            .search_iupac(record.sequence())
+            .into_iter()
+            .filter_map(|idx| locate_occurrence(suffix_array, idx, query_len, contig, args))
+            .collect();
+        if let Some(ref mut writer) = writer {
+            writeln!(writer, "{}", format_positions(&record, &positions, args))?;
+        }
+        occurrence_count += positions.len();
+        record_count += 1;
+    }
+    if let Some(writer) = writer {
+        writer.finish()?;
+    }
+    info!(
+        "Took {:?} to find {occurrence_count} IUPAC-compatible occurrences across {record_count} queries",
+        now.elapsed()
+    );
+    if error_count > 0 {
+        info!("Skipped {error_count} query records that could not be parsed");
+    }
+    Ok(())
+}
+
+pub fn main() -> Result<()> {
+    let args = QuerysaArgs::try_parse_serve_from(std::env::args_os()).unwrap_or_else(|e| e.exit());
+    init_logger();
+    run(args)
+}
+
+/// Searches the queries of `args` against its index and writes the results
+fn run(args: QuerysaArgs) -> Result<()> {
+    if args.reference.is_none()
+        && matches!(args.format, IndexFormat::Bincode)
+        && read_format_version(&args.index)? == Some(PREFIX_TABLE_ONLY_FORMAT_VERSION)
+    {
+        return count_with_prefix_table(&PrefixTableIndex::from_file(&args.index)?, &args);
+    }
+    let now: Instant = Instant::now();
+    let mut suffix_array: SuffixArray = match (&args.reference, &args.format) {
+        (Some(reference), _) => {
+            let records = read_reference(reference, |reader| reader.keep_ambiguous(true))?;
+            SuffixArray::from_array_only_file(&args.index, records)?
+        }
+        (None, IndexFormat::Bincode) => SuffixArray::from_bincode_file(&args.index)?,
+        (None, IndexFormat::RawSa) => read_raw_sa(&args.index)?,
+    };
+    debug!(
+        "Loading the index {:?} took {:?}",
+        &args.index,
+        now.elapsed()
+    );
+    if let Some(&[min_len, max_len]) = args.length_profile.as_deref() {
+        if min_len == 0 || min_len > max_len {
+            return Err(eyre!(
+                "--length-profile needs 1 <= MIN <= MAX but got {min_len} and {max_len}"
+            ));
+        }
+    }
+    if args.unmasked_only && suffix_array.soft_mask().is_none() {
+        return Err(eyre!(
+            "--unmasked-only needs an index built with buildsa --soft-masked"
+        ));
+    }
+    suffix_array.skip_prefix_table(args.no_preftab);
+    if let Some(expected) = &args.reference_checksum {
+        suffix_array.verify_reference(expected)?;
+    }
+    if args.print_checksum {
+        print_checksum(suffix_array.checksum(), &args);
+    }
+    let contig: Option<&Contig> = match &args.contig {
+        Some(name) => Some(
+            suffix_array
+                .contig(name)
+                .ok_or_else(|| eyre!("The index does not contain a record named {name}"))?,
+        ),
+        None => None,
+    };
+    let query_mode: QueryMode = match (&args.query_mode).into() {
+        QueryMode::Adaptive { .. } => QueryMode::Adaptive {
+            threshold: args.adaptive_threshold,
+        },
+        query_mode => query_mode,
+    };
+    if args.iupac {
+        return search_iupac_queries(&suffix_array, contig, &args);
+    }
+    if args.serve {
+        return match &args.output {
+            Some(filepath) => {
+                let mut writer = OutputFile::create(filepath, args.gzip)?;
+                serve(&suffix_array, &args, query_mode, contig, &mut writer)?;
+                Ok(writer.finish()?)
+            }
+            None => serve(
+                &suffix_array,
+                &args,
+                query_mode,
+                contig,
+                &mut io::stdout().lock(),
+            ),
+        };
+    }
+    let mut reader: Reader = Reader::from_file(&args.queries)
+        .wrap_err(format!("Could not find query file {:?}", &args.queries))?
+        .rna(args.rna_query)
+        .strict(args.validate_queries)
+        .min_length(args.min_query_len)
+        .line_delimited(matches!(args.query_format, QueryFormat::Lines))
+        .split_on(args.split_on);
     let mut total: Duration = Duration::default();
     let mut record_count = 0_usize;
-    let mut writer = match args.output {
+    let mut occurrence_count = 0_usize;
+    let mut matched_count = 0_usize;
+    let mut histogram: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut stats = OccurrenceStats::default();
+    let mut timings: Option<SearchTimings> = args.phase_timings.then(SearchTimings::default);
+    let mut start_spans: Option<StartSpanStats> = args.diagnose.then(StartSpanStats::default);
+    let mut writer: Option<OutputFile> = match &args.output {
+        Some(filepath) => Some(OutputFile::create(filepath, args.gzip)?),
+        None => None,
+    };
+    let mut unmatched_writer = match &args.unmatched_out {
         Some(filepath) => {
-            let writer: BufWriter<File> = BufWriter::new(
-                File::create(&filepath)
-                    .wrap_err(format!("Could not create output file {:?}", &filepath))?,
-            );
+            let writer: BufWriter<File> = BufWriter::new(File::create(filepath).wrap_err(
+                format!("Could not create unmatched query file {filepath:?}"),
+            )?);
             Some(writer)
         }
         None => None,
     };
-    for result in reader {
-        let record: Record = result?;
+    let mut coverage: Option<Coverage> = args
+        .coverage_out
+        .as_ref()
+        .map(|_| Coverage::new(suffix_array.sequence_len()));
+    let mut error_count = 0_usize;
+    let flush_interval: Option<Duration> = args.flush_interval.map(Duration::from_secs);
+    let mut last_flush: Instant = Instant::now();
+    for result in reader.by_ref() {
+        let record: Record = match result {
+            Ok(record) => record,
+            Err(e) if args.skip_errors => {
+                warn!("Skipping a query record that could not be parsed: {e}");
+                error_count += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
         let now: Instant = Instant::now();
-        let res: Option<Span> = match args.query_mode {
-            QueryMode::Naive => suffix_array.naive_search(record.sequence()),
-            QueryMode::Simpaccel => suffix_array.simple_accelerant_search(record.sequence()),
+        let (record, res) = match args.soft_clip {
+            Some(max_clip) => soft_clip_query(
+                &suffix_array,
+                &record,
+                max_clip,
+                query_mode,
+                args.delimiter.as_str(),
+            ),
+            None => {
+                let res = run_query(
+                    &suffix_array,
+                    &record,
+                    query_mode,
+                    &args.match_mode,
+                    timings.as_mut(),
+                );
+                (record, res)
+            }
         };
+        let reverse: Option<Option<Span>> = (args.strand_counts || args.canonical).then(|| {
+            // the reverse strand of a --both-strands index is searched with the query itself
+            if suffix_array.has_both_strands() {
+                return None;
+            }
+            let reverse_record = Record {
+                header: record.header().to_string(),
+                sequence: reverse_complement(record.sequence().trim()),
+            };
+            // a palindromic query's occurrences are already counted on the forward strand
+            if args.canonical && reverse_record.sequence() == record.sequence().trim() {
+                return None;
+            }
+            run_query(
+                &suffix_array,
+                &reverse_record,
+                query_mode,
+                &args.match_mode,
+                timings.as_mut(),
+            )
+        });
         let delta: Duration = Instant::now() - now;
         total += delta;
-        if let Some(ref mut writer) = writer {
-            writeln!(
-                writer,
-                "{}",
-                format_output_line(&suffix_array, &record, res)
-            )?;
+        if let Some(ref mut start_spans) = start_spans {
+            start_spans.add(
+                suffix_array.start_span(record.sequence()),
+                suffix_array.sequence_len(),
+            );
+        }
+        if let (Some(ref mut writer), false) = (&mut writer, args.summary_only || args.stats_only) {
+            let line = match reverse {
+                Some(reverse) if args.canonical => {
+                    format_canonical_line(&suffix_array, &record, (res, reverse), contig, &args)
+                }
+                Some(reverse) => {
+                    format_strand_line(&suffix_array, &record, (res, reverse), contig, &args)
+                }
+                None => format_record(&suffix_array, &record, res, contig, query_mode, &args),
+            };
+            writeln!(writer, "{line}")?;
+        }
+        let results = [Some(res), reverse].into_iter().flatten();
+        if let Some(ref mut coverage) = coverage {
+            results
+                .clone()
+                .for_each(|res| add_coverage(coverage, &suffix_array, &record, res, contig, &args));
         }
+        let occurrences: usize = results
+            .map(|res| count_occurrences(&suffix_array, &record, res, contig, &args))
+            .sum();
+        debug!("Query {} has {occurrences} occurrences", record.header());
+        occurrence_count += occurrences;
+        matched_count += usize::from(occurrences > 0);
+        match unmatched_writer {
+            Some(ref mut unmatched_writer) if occurrences == 0 => {
+                write_unmatched(unmatched_writer, &record, args.unmatched_sequences)?
+            }
+            _ => (),
+        }
+        if args.summary_only {
+            *histogram.entry(occurrences).or_default() += 1;
+        }
+        stats.add(occurrences);
         record_count += 1;
+        // `u64::is_multiple_of` would need Rust 1.87
+        #[allow(clippy::manual_is_multiple_of)]
+        let flush_due = args
+            .flush_every
+            .is_some_and(|every| record_count as u64 % every == 0)
+            || flush_interval.is_some_and(|interval| last_flush.elapsed() >= interval);
+        if flush_due {
+            if let Some(ref mut writer) = writer {
+                writer.flush()?;
+            }
+            if let Some(ref mut unmatched_writer) = unmatched_writer {
+                unmatched_writer.flush()?;
+            }
+            last_flush = Instant::now();
+        }
     }
-    if let Some(mut writer) = writer {
+    if args.summary_only {
+        let summary = format_summary(
+            record_count,
+            matched_count,
+            occurrence_count,
+            &histogram,
+            args.delimiter.as_str(),
+        );
+        match writer {
+            Some(ref mut writer) => write!(writer, "{summary}")?,
+            None => print!("{summary}"),
+        }
+    }
+    if args.stats_only {
+        match writer {
+            Some(ref mut writer) => writeln!(writer, "{stats}")?,
+            None => println!("{stats}"),
+        }
+    }
+    if let Some(writer) = writer {
+        writer.finish()?;
+    }
+    if let Some(mut unmatched_writer) = unmatched_writer {
+        unmatched_writer.flush()?;
+    }
+    if let (Some(coverage), Some(filepath)) = (coverage, &args.coverage_out) {
+        let mut writer: BufWriter<File> = BufWriter::new(
+            File::create(filepath)
+                .wrap_err(format!("Could not create coverage file {filepath:?}"))?,
+        );
+        let contigs = match contig {
+            Some(contig) => std::slice::from_ref(contig),
+            None => suffix_array.forward_contigs(),
+        };
+        coverage.write_bedgraph(contigs, &mut writer)?;
         writer.flush()?;
     }
-    println!("Took {total:?} to find matches in {record_count} queries");
+    if record_count == 0 {
+        warn!(
+            "The query file {:?} did not contain any queries",
+            &args.queries
+        );
+    }
+    info!("Took {total:?} to find matches in {record_count} queries");
+    info!(
+        "Found {occurrence_count} total occurrences across {record_count} queries ({matched_count} queries matched)"
+    );
+    if let Some(SearchTimings { lookup, bisection }) = timings {
+        let phases = (lookup + bisection).as_secs_f64().max(f64::EPSILON);
+        info!(
+            "Prefix table lookup took {lookup:?} ({:.1}%), bisection took {bisection:?} ({:.1}%)",
+            100.0 * lookup.as_secs_f64() / phases,
+            100.0 * bisection.as_secs_f64() / phases
+        );
+    }
+    if let Some(stats) = start_spans {
+        let has_table =
+            suffix_array.prefix_table().is_some() || suffix_array.adaptive_prefix_table().is_some();
+        if !has_table || args.no_preftab {
+            info!("No prefix table was used, every query bisected the full suffix array");
+        } else {
+            info!(
+                "The prefix table narrowed the span of {} of {} queries ({:.1}%) and ruled out {} without bisecting",
+                stats.narrowed,
+                stats.queries,
+                100.0 * stats.narrowed as f64 / stats.queries.max(1) as f64,
+                stats.ruled_out
+            );
+            info!(
+                "Mean width of the spans left to bisect: {:.1} of {} suffixes",
+                stats.mean_width(),
+                suffix_array.sequence_len()
+            );
+        }
+    }
+    if reader.skipped() > 0 {
+        info!(
+            "Skipped {} queries shorter than {}",
+            reader.skipped(),
+            args.min_query_len
+        );
+    }
+    if error_count > 0 {
+        info!("Skipped {error_count} query records that could not be parsed");
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assignment_1::{suffix_array::BuildOptions, testing::temp_path};
+    use clap::Parser;
+
+    #[test]
+    fn palindromes_are_reported_once_on_a_both_strands_index() {
+        let reference = Record {
+            header: String::from("chr1"),
+            sequence: String::from("TTACGTTT"),
+        };
+        let options = BuildOptions {
+            both_strands: true,
+            ..BuildOptions::default()
+        };
+        let suffix_array = SuffixArray::from_records_with_options(vec![reference], options);
+        let args =
+            QuerysaArgs::try_parse_from(["querysa", "index.bin", "queries.fa", "naive", "out.txt"])
+                .unwrap();
+        let query = Record {
+            header: String::from("q"),
+            sequence: String::from("ACGT"),
+        };
+        let result = suffix_array.search(query.sequence(), QueryMode::Naive);
+        // ACGT is its own reverse complement, both strands match at 2
+        assert_eq!(result.unwrap().len(), 2);
+        let sep = args.delimiter.as_str();
+        assert_eq!(
+            format_output_line(&suffix_array, &query, result, None, &args),
+            format!("q{sep}1{sep}2")
+        );
+        assert_eq!(
+            count_occurrences(&suffix_array, &query, result, None, &args),
+            1
+        );
+    }
+
+    #[test]
+    fn prefix_table_index_rejects_flags_it_cannot_honor() {
+        let mut suffix_array = SuffixArray::from_sequence("ACGTACGGACGTTACGTACGA");
+        suffix_array.initialize_prefix_table(3);
+        let index = suffix_array.into_prefix_table_index().unwrap();
+        for flags in [
+            &["--match-mode", "whole"][..],
+            &["--summary-only"],
+            &["--stats-only"],
+            &["--strand-counts"],
+            &["--canonical"],
+            &["--diagnose"],
+            &["--phase-timings"],
+            &["--unmatched-out", "unmatched.txt", "--unmatched-sequences"],
+            &["--flush-every", "10"],
+            &["--flush-interval", "1"],
+            &["--no-preftab"],
+        ] {
+            let args = QuerysaArgs::try_parse_from(
+                ["querysa", "index.bin", "queries.fa", "naive", "out.txt"]
+                    .iter()
+                    .chain(flags),
+            )
+            .unwrap();
+            let error = count_with_prefix_table(&index, &args).unwrap_err();
+            assert!(error.to_string().starts_with(flags[0]), "{error}");
+        }
+    }
+
+    #[test]
+    fn empty_and_blank_query_files_run_cleanly() {
+        let suffix_array = SuffixArray::from_sequence("ACGTACGGACGTTACGTACGA");
+        let index = temp_path("zero_queries.sa");
+        bincode::serialize_into(File::create(&index).unwrap(), &suffix_array).unwrap();
+        for contents in ["", "\n", "  \n\t\n\r\n"] {
+            let queries = temp_path("zero_queries.fa");
+            let output = temp_path("zero_queries.txt");
+            std::fs::write(&queries, contents).unwrap();
+            let args = QuerysaArgs::try_parse_from([
+                "querysa".as_ref(),
+                index.as_os_str(),
+                queries.as_os_str(),
+                "naive".as_ref(),
+                output.as_os_str(),
+            ])
+            .unwrap();
+            run(args).unwrap();
+            assert!(std::fs::read(&output).unwrap().is_empty(), "{contents:?}");
+            std::fs::remove_file(&queries).unwrap();
+            std::fs::remove_file(&output).unwrap();
+        }
+        std::fs::remove_file(&index).unwrap();
+    }
+
+    #[test]
+    fn repeated_and_threaded_runs_write_identical_output() {
+        use assignment_1::prefix_table::DEFAULT_DENSE_THRESHOLD;
+        use rand::{distributions::Slice, rngs::StdRng, Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(7);
+        let nucleotides = ['A', 'C', 'G', 'T'];
+        let distribution = Slice::new(&nucleotides).unwrap();
+        let sequences: Vec<String> = (0..2)
+            .map(|_| (&mut rng).sample_iter(&distribution).take(2000).collect())
+            .collect();
+        let queries = temp_path("determinism_queries.fa");
+        let mut batch = String::new();
+        for idx in 0..200 {
+            let sequence = &sequences[idx % 2];
+            let length = rng.gen_range(2..20);
+            let start = rng.gen_range(0..sequence.len() - length);
+            writeln!(batch, ">q{idx}\n{}", &sequence[start..start + length]).unwrap();
+        }
+        std::fs::write(&queries, batch).unwrap();
+
+        let run_batch = |threads: usize, name: &str| -> (Vec<u8>, Vec<u8>) {
+            let records = sequences
+                .iter()
+                .enumerate()
+                .map(|(idx, sequence)| Record {
+                    header: format!("chr{}", idx + 1),
+                    sequence: sequence.clone(),
+                })
+                .collect();
+            let options = BuildOptions {
+                threads,
+                ..BuildOptions::default()
+            };
+            let mut suffix_array = SuffixArray::from_records_with_options(records, options);
+            // above the dense threshold so the hashed sparse table is written
+            suffix_array.initialize_prefix_table(DEFAULT_DENSE_THRESHOLD + 1);
+            let index = temp_path(&format!("determinism_{name}.sa"));
+            let output = temp_path(&format!("determinism_{name}.txt"));
+            bincode::serialize_into(File::create(&index).unwrap(), &suffix_array).unwrap();
+            let args = QuerysaArgs::try_parse_from([
+                "querysa".as_ref(),
+                index.as_os_str(),
+                queries.as_os_str(),
+                "simpaccel".as_ref(),
+                output.as_os_str(),
+            ])
+            .unwrap();
+            run(args).unwrap();
+            let written = (
+                std::fs::read(&index).unwrap(),
+                std::fs::read(&output).unwrap(),
+            );
+            std::fs::remove_file(&index).unwrap();
+            std::fs::remove_file(&output).unwrap();
+            written
+        };
+        let expected = run_batch(1, "first");
+        assert!(!expected.1.is_empty());
+        assert_eq!(run_batch(1, "repeated"), expected);
+        #[cfg(feature = "parallel")]
+        assert_eq!(run_batch(4, "threaded"), expected);
+        std::fs::remove_file(&queries).unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+
+use assignment_1::{
+    args::TunePreftabArgs,
+    reader::Reader,
+    record::Record,
+    suffix_array::{QueryMode, SuffixArray},
+};
+use clap::Parser;
+use eyre::{eyre, Result, WrapErr};
+
+fn time_queries(suffix_array: &SuffixArray, records: &[Record], query_mode: QueryMode) -> Duration {
+    let now: Instant = Instant::now();
+    records.iter().for_each(|record| {
+        suffix_array.search(record.sequence(), query_mode);
+    });
+    Instant::now() - now
+}
+
+pub fn main() -> Result<()> {
+    let args = TunePreftabArgs::parse();
+    let mut reader = Reader::from_file(&args.reference).wrap_err(format!(
+        "The reference file {:?} does not exist",
+        &args.reference
+    ))?;
+    let record = match reader.next() {
+        Some(record) => record.wrap_err("could not parse record"),
+        None => Err(eyre!(format!(
+            "The reference file {:?} was empty",
+            &args.reference
+        ))),
+    }?;
+    let records: Vec<Record> = Reader::from_file(&args.queries)
+        .wrap_err(format!("Could not find query file {:?}", &args.queries))?
+        .collect::<Result<Vec<Record>>>()?;
+
+    let query_mode: QueryMode = (&args.query_mode).into();
+    let mut suffix_array = SuffixArray::from_record(record);
+    let mut timings: Vec<(u16, Duration)> = Vec::new();
+    for &k in args.k.iter() {
+        suffix_array.initialize_prefix_table(k);
+        let best = (0..args.rounds.max(1))
+            .map(|_| time_queries(&suffix_array, &records, query_mode))
+            .min()
+            .unwrap_or_default();
+        println!("k={k} took {best:?}");
+        timings.push((k, best));
+    }
+
+    timings.sort_by_key(|&(_, duration)| duration);
+    println!("rank, k, time");
+    for (rank, (k, duration)) in timings.iter().enumerate() {
+        println!("{}, {k}, {duration:?}", rank + 1);
+    }
+    if let Some((k, _)) = timings.first() {
+        println!(
+            "Fastest prefix table size for {} queries: --preftab {k}",
+            records.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use assignment_1::{
+    args::{IndexFormat, VerifysaArgs},
+    raw_sa::read_raw_sa,
+    search::Span,
+    suffix_array::SuffixArray,
+};
+use clap::Parser;
+use eyre::{eyre, Result};
+use rand::{thread_rng, Rng};
+
+/// Searches for `query` (taken from `start` in the sequence) with both algorithms
+/// and describes any disagreement or missing/incorrect position
+fn check_sample(suffix_array: &SuffixArray, query: &str, start: u32) -> Option<String> {
+    let naive = suffix_array.naive_search(query);
+    let accelerated = suffix_array.simple_accelerant_search(query);
+    if naive != accelerated {
+        return Some(format!(
+            "{query} (from {start}): naive found {naive:?} but simpaccel found {accelerated:?}"
+        ));
+    }
+    let Some(span) = naive else {
+        return Some(format!("{query} (from {start}) was not found"));
+    };
+    let positions = &suffix_array.suffix_array[span.range()];
+    if !positions.contains(&start) {
+        return Some(format!(
+            "{query} (from {start}) was not reported at {start}"
+        ));
+    }
+    let sequence = suffix_array.sequence();
+    let sequence = sequence.as_bytes();
+    positions
+        .iter()
+        .find(|&&position| !sequence[position as usize..].starts_with(query.as_bytes()))
+        .map(|position| format!("{query} (from {start}) was reported at {position}"))
+}
+
+pub fn main() -> Result<()> {
+    let args = VerifysaArgs::parse();
+    let mut suffix_array: SuffixArray = match args.format {
+        IndexFormat::Bincode => SuffixArray::from_bincode_file(&args.index)?,
+        IndexFormat::RawSa => read_raw_sa(&args.index)?,
+    };
+    suffix_array.validate()?;
+    println!("Index structure is valid");
+    suffix_array.unpack();
+
+    let contigs: Vec<Span> = suffix_array
+        .contigs()
+        .iter()
+        .map(|contig| contig.span)
+        .filter(|span| !span.is_empty())
+        .collect();
+    if contigs.is_empty() {
+        return Err(eyre!(
+            "The index does not contain a reference to sample from"
+        ));
+    }
+    let mut rng = thread_rng();
+    let mut failures = 0_usize;
+    for _ in 0..args.samples {
+        // sample within a single record so queries never span a $ separator
+        let contig = contigs[rng.gen_range(0..contigs.len())];
+        let length = (args.length as u32).min(contig.len() as u32);
+        let start = rng.gen_range(contig.start..=contig.end - length);
+        let query = &suffix_array.sequence()[start as usize..(start + length) as usize];
+        if let Some(failure) = check_sample(&suffix_array, query, start) {
+            eprintln!("{failure}");
+            failures += 1;
+        }
+    }
+    if failures > 0 {
+        return Err(eyre!(
+            "{failures} of {} sampled queries failed",
+            args.samples
+        ));
+    }
+    println!(
+        "Both search algorithms agree on {} sampled queries",
+        args.samples
+    );
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+
+/// A Bloom filter over the k-mers of a reference, answering whether a k-mer is
+/// definitely absent (no false negatives) or possibly present
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    k: u16,
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+/// 64 bit FNV-1a seeded with `seed`, stable across builds unlike the std hashers
+fn fnv1a(bytes: &[u8], seed: u64) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325 ^ seed, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+impl BloomFilter {
+    /// An empty filter sized to hold `items` k-mers with the given false positive rate
+    pub fn new(k: u16, items: usize, false_positive_rate: f64) -> Self {
+        let rate = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let items = items.max(1) as f64;
+        let bits = (-items * rate.ln() / (2_f64.ln() * 2_f64.ln()))
+            .ceil()
+            .max(64.0);
+        let hashes = (bits / items * 2_f64.ln()).round().clamp(1.0, 32.0) as u32;
+        Self {
+            k,
+            bits: vec![0; (bits as usize).div_ceil(64)],
+            hashes,
+        }
+    }
+
+    /// Builds a filter over every window of `k` bytes of the sequence that does not
+    /// contain the `$` sentinel
+    ///
+    /// ```
+    /// # use assignment_1::bloom::BloomFilter;
+    /// let bloom = BloomFilter::from_sequence(b"ACGTACGG$TTAC$", 3, 0.01);
+    /// assert!(["ACG", "CGT", "GTA", "TAC", "CGG", "TTA"].iter().all(|x| bloom.contains(x)));
+    /// ```
+    pub fn from_sequence(sequence: &[u8], k: u16, false_positive_rate: f64) -> Self {
+        let windows = sequence.windows(k.max(1) as usize);
+        let mut bloom = Self::new(k, windows.len(), false_positive_rate);
+        for kmer in windows.filter(|kmer| !kmer.contains(&b'$')) {
+            bloom.insert_bytes(kmer);
+        }
+        bloom
+    }
+
+    /// The length of the k-mers in the filter
+    pub fn k(&self) -> u16 {
+        self.k
+    }
+
+    /// The size of the filter in bits
+    pub fn len(&self) -> usize {
+        self.bits.len() * 64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    /// The number of bits set for each k-mer
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    /// The bit positions of a k-mer, by double hashing
+    fn positions(&self, kmer: &[u8]) -> impl Iterator<Item = usize> {
+        let (first, second) = (fnv1a(kmer, 0), fnv1a(kmer, 0x9e37_79b9_7f4a_7c15) | 1);
+        let len = self.len() as u64;
+        (0..u64::from(self.hashes))
+            .map(move |idx| (first.wrapping_add(idx.wrapping_mul(second)) % len) as usize)
+    }
+
+    fn insert_bytes(&mut self, kmer: &[u8]) {
+        for position in self.positions(kmer) {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    pub fn insert(&mut self, kmer: &str) {
+        self.insert_bytes(kmer.as_bytes())
+    }
+
+    /// False if the k-mer was definitely never inserted
+    pub fn contains(&self, kmer: &str) -> bool {
+        self.positions(kmer.as_bytes())
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    #[test]
+    fn no_false_negatives_and_few_false_positives() {
+        let mut rng = StdRng::seed_from_u64(701);
+        let sequence: Vec<u8> = (0..20_000)
+            .map(|_| *b"ACGT".choose(&mut rng).unwrap())
+            .collect();
+        let bloom = BloomFilter::from_sequence(&sequence, 12, 0.01);
+        for kmer in sequence.windows(12) {
+            assert!(bloom.contains(std::str::from_utf8(kmer).unwrap()));
+        }
+        let present: std::collections::HashSet<&[u8]> = sequence.windows(12).collect();
+        let absent: Vec<String> = (0..10_000)
+            .map(|_| {
+                (0..12)
+                    .map(|_| *b"ACGT".choose(&mut rng).unwrap() as char)
+                    .collect::<String>()
+            })
+            .filter(|kmer| !present.contains(kmer.as_bytes()))
+            .collect();
+        let false_positives = absent.iter().filter(|kmer| bloom.contains(kmer)).count();
+        assert!(
+            (false_positives as f64) < 0.03 * absent.len() as f64,
+            "{false_positives} of {}",
+            absent.len()
+        );
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    borrow::Cow,
+    ops::{BitAnd, BitOr},
+};
+
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::search::{Sequence, Span};
+
+/// The nucleotide each 2-bit code stands for (A=0, C=1, G=2, T=3)
+pub const NUCLEOTIDES: [u8; 4] = *b"ACGT";
+
+/// The symbols a reference (and its queries) are indexed with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Alphabet {
+    /// The nucleotides as read
+    #[default]
+    Dna,
+    /// Purines (A, G) fold to R and pyrimidines (C, T) fold to Y
+    PurinePyrimidine,
+}
+
+impl Alphabet {
+    /// Maps a symbol to the symbol it is indexed as, symbols outside the alphabet are kept
+    /// ```
+    /// # use assignment_1::encoding::Alphabet;
+    /// assert_eq!(Alphabet::PurinePyrimidine.fold(b'G'), b'R');
+    /// assert_eq!(Alphabet::PurinePyrimidine.fold(b'T'), b'Y');
+    /// assert_eq!(Alphabet::PurinePyrimidine.fold(b'$'), b'$');
+    /// assert_eq!(Alphabet::Dna.fold(b'T'), b'T');
+    /// ```
+    pub fn fold(&self, symbol: u8) -> u8 {
+        match (self, symbol) {
+            (Self::PurinePyrimidine, b'A' | b'G') => b'R',
+            (Self::PurinePyrimidine, b'C' | b'T') => b'Y',
+            _ => symbol,
+        }
+    }
+
+    /// Folds every symbol of a sequence, borrowing it when nothing changes
+    /// ```
+    /// # use assignment_1::encoding::Alphabet;
+    /// assert_eq!(Alphabet::PurinePyrimidine.fold_str("ACGT$"), "RYRY$");
+    /// assert_eq!(Alphabet::Dna.fold_str("ACGT$"), "ACGT$");
+    /// ```
+    pub fn fold_str<'a>(&self, sequence: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Dna => Cow::Borrowed(sequence),
+            Self::PurinePyrimidine => Cow::Owned(
+                sequence
+                    .bytes()
+                    .map(|symbol| self.fold(symbol) as char)
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// The complement of a nucleotide (or purine/pyrimidine) symbol keeping its case, other
+/// symbols are kept
+/// ```
+/// # use assignment_1::encoding::complement;
+/// assert_eq!(complement(b'A'), b'T');
+/// assert_eq!(complement(b'g'), b'c');
+/// assert_eq!(complement(b'R'), b'Y');
+/// assert_eq!(complement(b'$'), b'$');
+/// ```
+pub fn complement(symbol: u8) -> u8 {
+    match symbol {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'a' => b't',
+        b'c' => b'g',
+        b'g' => b'c',
+        b't' => b'a',
+        b'r' => b'y',
+        b'y' => b'r',
+        _ => symbol,
+    }
+}
+
+/// The reverse complement of a sequence, i.e. the other strand read 5' to 3'
+/// ```
+/// # use assignment_1::encoding::reverse_complement;
+/// assert_eq!(reverse_complement("AACGT"), "ACGTT");
+/// assert_eq!(reverse_complement("ACGT"), "ACGT");
+/// assert_eq!(reverse_complement("AAcgN"), "NcgTT");
+/// ```
+pub fn reverse_complement(sequence: &str) -> String {
+    sequence
+        .bytes()
+        .rev()
+        .map(|symbol| complement(symbol) as char)
+        .collect()
+}
+
+/// The canonical form of a k-mer: the lexicographically smaller of the k-mer and its
+/// reverse complement, so both strands of a k-mer share one key
+/// ```
+/// # use assignment_1::encoding::canonical_kmer;
+/// assert_eq!(canonical_kmer("TTGC"), "GCAA");
+/// assert_eq!(canonical_kmer("GCAA"), "GCAA");
+/// assert_eq!(canonical_kmer("ACGT"), "ACGT");
+/// ```
+pub fn canonical_kmer(kmer: &str) -> Cow<'_, str> {
+    let reverse = reverse_complement(kmer);
+    if reverse.as_str() < kmer {
+        Cow::Owned(reverse)
+    } else {
+        Cow::Borrowed(kmer)
+    }
+}
+
+/// Convert a nucleotide character to its 2-bit code
+/// ```
+/// # use assignment_1::encoding::encode_nucleotide;
+/// assert_eq!(encode_nucleotide(b'G').unwrap(), 2);
+/// assert!(encode_nucleotide(b'$').is_err());
+/// ```
+pub fn encode_nucleotide(nucleotide: u8) -> Result<u8> {
+    match nucleotide {
+        b'A' => Ok(0),
+        b'C' => Ok(1),
+        b'G' => Ok(2),
+        b'T' => Ok(3),
+        _ => Err(eyre!(
+            "Received unexpected nucleotide: {}",
+            nucleotide as char
+        )),
+    }
+}
+
+/// Convert unpacked 2-bit codes (one code per byte) to the nucleotide characters they stand for
+/// ```
+/// # use assignment_1::encoding::decode_codes;
+/// assert_eq!(decode_codes(&[0, 1, 2, 3]).unwrap(), b"ACGT".to_vec());
+/// assert!(decode_codes(&[4]).is_err());
+/// ```
+pub fn decode_codes(codes: &[u8]) -> Result<Vec<u8>> {
+    codes
+        .iter()
+        .map(|&code| {
+            NUCLEOTIDES
+                .get(code as usize)
+                .copied()
+                .ok_or_else(|| eyre!("Received unexpected nucleotide code: {code}"))
+        })
+        .collect()
+}
+
+/// Pack 2-bit codes four to a byte, the first code in the most significant bits
+/// ```
+/// # use assignment_1::encoding::pack_codes;
+/// assert_eq!(pack_codes(&[0, 1, 2, 3, 3]), vec![0b00011011, 0b11000000]);
+/// ```
+pub fn pack_codes(codes: &[u8]) -> Vec<u8> {
+    codes
+        .chunks(4)
+        .map(|chunk| {
+            chunk.iter().enumerate().fold(0_u8, |byte, (idx, &code)| {
+                byte | ((code & 3) << (6 - 2 * idx))
+            })
+        })
+        .collect()
+}
+
+/// Unpack the first `len` 2-bit codes from bytes written by [`pack_codes`]
+/// ```
+/// # use assignment_1::encoding::unpack_codes;
+/// assert_eq!(unpack_codes(&[0b00011011, 0b11000000], 5).unwrap(), vec![0, 1, 2, 3, 3]);
+/// assert!(unpack_codes(&[0b00011011], 5).is_err());
+/// ```
+pub fn unpack_codes(packed: &[u8], len: usize) -> Result<Vec<u8>> {
+    if packed.len() * 4 < len {
+        return Err(eyre!(
+            "{} packed bytes cannot hold {len} nucleotides",
+            packed.len()
+        ));
+    }
+    Ok((0..len)
+        .map(|idx| (packed[idx / 4] >> (6 - 2 * (idx % 4))) & 3)
+        .collect())
+}
+
+/// A nucleotide sequence stored with 2 bits per base
+///
+/// `$` separators and the sentinel cannot be packed so their positions are kept separately.
+/// Bytes are decoded on the fly so the searches can read it like the unpacked sequence.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct PackedSequence {
+    len: usize,
+    packed: Vec<u8>,
+    sentinels: Vec<u32>,
+}
+
+impl PackedSequence {
+    /// Packs a sequence of A, C, G, T and `$` characters
+    /// ```
+    /// # use assignment_1::encoding::PackedSequence;
+    /// let packed = PackedSequence::pack("ACGT$GA$").unwrap();
+    /// assert_eq!(packed.len(), 8);
+    /// assert_eq!(packed.unpack(), "ACGT$GA$");
+    /// assert!(PackedSequence::pack("ACNT").is_err());
+    /// ```
+    pub fn pack(sequence: &str) -> Result<Self> {
+        let mut sentinels: Vec<u32> = Vec::new();
+        let codes = sequence
+            .bytes()
+            .enumerate()
+            .map(|(idx, nucleotide)| {
+                if nucleotide == b'$' {
+                    sentinels.push(idx as u32);
+                    Ok(0)
+                } else {
+                    encode_nucleotide(nucleotide)
+                }
+            })
+            .collect::<Result<Vec<u8>>>()?;
+        Ok(Self {
+            len: codes.len(),
+            packed: pack_codes(&codes),
+            sentinels,
+        })
+    }
+
+    pub fn unpack(&self) -> String {
+        (0..self.len).map(|idx| self.byte_at(idx) as char).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Sequence for PackedSequence {
+    fn byte_at(&self, index: usize) -> u8 {
+        if index >= self.len {
+            panic!(
+                "index {index} is out of bounds for a sequence of length {}",
+                self.len
+            );
+        }
+        if self.sentinels.binary_search(&(index as u32)).is_ok() {
+            return b'$';
+        }
+        NUCLEOTIDES[((self.packed[index / 4] >> (6 - 2 * (index % 4))) & 3) as usize]
+    }
+}
+
+/// The soft-masked (lowercase) positions of a sequence, one bit per base
+///
+/// Repeat-masked references mark repeats in lowercase. The mask keeps that
+/// information once the sequence is uppercased for indexing.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct SoftMask {
+    len: usize,
+    bits: Vec<u64>,
+}
+
+impl SoftMask {
+    /// Marks the lowercase bases of a sequence
+    /// ```
+    /// # use assignment_1::{encoding::SoftMask, search::Span};
+    /// let mask = SoftMask::from_sequence("ACgtA$");
+    /// assert!(mask.is_masked(2));
+    /// assert!(!mask.is_masked(4));
+    /// assert!(mask.any_masked(Span::new(0, 3)));
+    /// assert!(!mask.any_masked(Span::new(4, 6)));
+    /// assert_eq!(mask.count(), 2);
+    /// ```
+    pub fn from_sequence(sequence: &str) -> Self {
+        let mut bits = vec![0_u64; sequence.len().div_ceil(64)];
+        sequence
+            .bytes()
+            .enumerate()
+            .filter(|(_, base)| base.is_ascii_lowercase())
+            .for_each(|(idx, _)| bits[idx / 64] |= 1 << (idx % 64));
+        Self {
+            len: sequence.len(),
+            bits,
+        }
+    }
+
+    /// Whether the base at `position` is masked, positions past the end are not
+    pub fn is_masked(&self, position: usize) -> bool {
+        position < self.len && self.bits[position / 64] & (1 << (position % 64)) != 0
+    }
+
+    /// Whether any base of the positions in `span` is masked
+    pub fn any_masked(&self, span: Span) -> bool {
+        span.range().any(|position| self.is_masked(position))
+    }
+
+    /// The number of masked bases
+    pub fn count(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// A fixed length set of positions, one bit per position, combined with `&` and `|`
+///
+/// ```
+/// # use assignment_1::encoding::BitVec;
+/// let mut a = BitVec::new(100);
+/// let mut b = BitVec::new(100);
+/// [3, 70, 99].iter().for_each(|&x| a.set(x));
+/// [70, 5].iter().for_each(|&x| b.set(x));
+/// assert_eq!((&a & &b).ones().collect::<Vec<_>>(), [70]);
+/// assert_eq!((&a | &b).count_ones(), 4);
+/// assert!(a.get(99) && !a.get(5) && !a.get(100));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitVec {
+    len: usize,
+    bits: Vec<u64>,
+}
+
+impl BitVec {
+    /// A set of `len` positions with none set
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            bits: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    /// # Panics
+    /// If `position` is not less than the length
+    pub fn set(&mut self, position: usize) {
+        assert!(
+            position < self.len,
+            "{position} is out of {} bits",
+            self.len
+        );
+        self.bits[position / 64] |= 1 << (position % 64);
+    }
+
+    /// Whether `position` is set, positions past the end are not
+    pub fn get(&self, position: usize) -> bool {
+        position < self.len && self.bits[position / 64] & (1 << (position % 64)) != 0
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// The set positions in increasing order
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bits.iter().enumerate().flat_map(|(idx, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                (word != 0).then(|| {
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    idx * 64 + bit
+                })
+            })
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn zip_with(&self, other: &Self, f: impl Fn(u64, u64) -> u64) -> Self {
+        assert_eq!(
+            self.len, other.len,
+            "cannot combine bit vectors of different lengths"
+        );
+        Self {
+            len: self.len,
+            bits: self
+                .bits
+                .iter()
+                .zip(&other.bits)
+                .map(|(&a, &b)| f(a, b))
+                .collect(),
+        }
+    }
+}
+
+impl BitAnd for &BitVec {
+    type Output = BitVec;
+
+    /// The positions set in both, panics if the lengths differ
+    fn bitand(self, other: Self) -> BitVec {
+        self.zip_with(other, |a, b| a & b)
+    }
+}
+
+impl BitOr for &BitVec {
+    type Output = BitVec;
+
+    /// The positions set in either, panics if the lengths differ
+    fn bitor(self, other: Self) -> BitVec {
+        self.zip_with(other, |a, b| a | b)
+    }
+}
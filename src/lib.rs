@@ -1,6 +1,16 @@
 pub mod args;
+pub mod bloom;
+pub mod encoding;
+pub mod logging;
+pub mod normalize;
+#[cfg(feature = "parallel")]
+pub mod parallel;
 pub mod prefix_table;
+pub mod raw_sa;
 pub mod reader;
 pub mod record;
+pub mod results;
 pub mod search;
 pub mod suffix_array;
+#[doc(hidden)]
+pub mod testing;
@@ -0,0 +1,12 @@
+use env_logger::{Builder, Env};
+
+/// Sets up leveled logging to stderr for a binary, keeping stdout for results
+///
+/// Messages at `info` and above are shown by default, `RUST_LOG` (e.g. `RUST_LOG=debug`
+/// or `RUST_LOG=warn`) changes the verbosity.
+pub fn init_logger() {
+    Builder::from_env(Env::default().default_filter_or("info"))
+        .format_target(false)
+        .format_timestamp(None)
+        .init();
+}
@@ -0,0 +1,265 @@
+use rand::{seq::IteratorRandom, thread_rng, Rng};
+
+/// The base ambiguous nucleotides are read as with [`UnknownBase::Mark`]
+pub const AMBIGUOUS_BASE: char = 'N';
+
+/// What happens to a character other than A, C, G and T (or U when reading RNA)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownBase {
+    /// Replace it with a random nucleotide
+    #[default]
+    Random,
+    /// Replace it with [`AMBIGUOUS_BASE`]
+    Mark,
+    /// Keep it (uppercased) as it is
+    Keep,
+    /// Drop it, the caller decides whether that is an error
+    Reject,
+    /// Keep IUPAC ambiguity codes (uppercased) and replace anything else with
+    /// [`AMBIGUOUS_BASE`], see [`iupac_matches`]
+    Iupac,
+}
+
+/// How characters of a reference or a query are normalized to nucleotides
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NormalizePolicy {
+    pub unknown: UnknownBase,
+    /// Read U as T
+    pub rna: bool,
+    /// Keep lowercase (soft-masked) bases lowercase instead of uppercasing them
+    pub keep_case: bool,
+}
+
+impl NormalizePolicy {
+    /// The policy queries are searched with: uppercase, anything else is kept as is
+    pub const QUERY: Self = Self {
+        unknown: UnknownBase::Keep,
+        rna: false,
+        keep_case: false,
+    };
+}
+
+/// Counts of what [`normalize_sequence`] changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SanitizeStats {
+    /// Lowercase characters that were read
+    pub lowercase: usize,
+    /// Characters other than A, C, G and T that were replaced, marked or kept
+    pub unknown: usize,
+    /// Characters that were dropped by [`UnknownBase::Reject`]
+    pub rejected: usize,
+}
+
+fn normalize_with<R>(c: char, policy: NormalizePolicy, rng: &mut R) -> Option<char>
+where
+    R: Rng + ?Sized,
+{
+    let upper = match c.to_ascii_uppercase() {
+        'U' if policy.rna => 'T',
+        upper => upper,
+    };
+    let base = match upper {
+        'A' | 'C' | 'G' | 'T' => upper,
+        _ => match policy.unknown {
+            UnknownBase::Random => "ACTG"
+                .chars()
+                .choose(rng)
+                .expect("Expected to choose a random character"),
+            UnknownBase::Mark => AMBIGUOUS_BASE,
+            UnknownBase::Keep => upper,
+            UnknownBase::Reject => return None,
+            UnknownBase::Iupac if upper.is_ascii() && iupac_bases(upper as u8).is_some() => upper,
+            UnknownBase::Iupac => AMBIGUOUS_BASE,
+        },
+    };
+    if policy.keep_case && c.is_ascii_lowercase() {
+        Some(base.to_ascii_lowercase())
+    } else {
+        Some(base)
+    }
+}
+
+/// Normalizes a single character, `None` if the policy rejects it
+/// ```
+/// # use assignment_1::normalize::{normalize_nucleotide, NormalizePolicy, UnknownBase};
+/// let mark = NormalizePolicy { unknown: UnknownBase::Mark, ..NormalizePolicy::default() };
+/// assert_eq!(normalize_nucleotide('g', mark), Some('G'));
+/// assert_eq!(normalize_nucleotide('R', mark), Some('N'));
+/// let reject = NormalizePolicy { unknown: UnknownBase::Reject, ..NormalizePolicy::default() };
+/// assert_eq!(normalize_nucleotide('R', reject), None);
+/// ```
+pub fn normalize_nucleotide(c: char, policy: NormalizePolicy) -> Option<char> {
+    normalize_with(c, policy, &mut thread_rng())
+}
+
+/// Normalizes every character of a sequence, returning the normalized sequence and
+/// counts of the lowercase, unknown and rejected characters
+/// ```
+/// # use assignment_1::normalize::{normalize_sequence, NormalizePolicy, UnknownBase};
+/// let policy = NormalizePolicy { unknown: UnknownBase::Mark, ..NormalizePolicy::default() };
+/// let (sequence, stats) = normalize_sequence("acgTNX", policy);
+/// assert_eq!(sequence, "ACGTNN");
+/// assert_eq!((stats.lowercase, stats.unknown, stats.rejected), (3, 2, 0));
+/// ```
+pub fn normalize_sequence(sequence: &str, policy: NormalizePolicy) -> (String, SanitizeStats) {
+    let mut rng = thread_rng();
+    let mut stats = SanitizeStats::default();
+    let mut normalized = String::with_capacity(sequence.len());
+    for c in sequence.chars() {
+        if c.is_ascii_lowercase() {
+            stats.lowercase += 1;
+        }
+        let known = match c.to_ascii_uppercase() {
+            'A' | 'C' | 'G' | 'T' => true,
+            'U' => policy.rna,
+            _ => false,
+        };
+        match normalize_with(c, policy, &mut rng) {
+            Some(base) => {
+                if !known {
+                    stats.unknown += 1;
+                }
+                normalized.push(base);
+            }
+            None => stats.rejected += 1,
+        }
+    }
+    (normalized, stats)
+}
+
+/// The position and value of the first character that is neither a nucleotide nor
+/// [`AMBIGUOUS_BASE`] in either case, with `rna` U counts as a nucleotide
+/// ```
+/// # use assignment_1::normalize::find_invalid;
+/// assert_eq!(find_invalid("ACGTNacgtn", false), None);
+/// assert_eq!(find_invalid("ACGU", false), Some((3, 'U')));
+/// assert_eq!(find_invalid("ACGU", true), None);
+/// assert_eq!(find_invalid("MKVLA", false), Some((0, 'M')));
+/// ```
+pub fn find_invalid(sequence: &str, rna: bool) -> Option<(usize, char)> {
+    sequence
+        .char_indices()
+        .find(|&(_, c)| match c.to_ascii_uppercase() {
+            'A' | 'C' | 'G' | 'T' | AMBIGUOUS_BASE => false,
+            'U' => !rna,
+            _ => true,
+        })
+}
+
+/// The nucleotides an IUPAC code stands for, `None` if it is not an IUPAC code
+/// ```
+/// # use assignment_1::normalize::iupac_bases;
+/// assert_eq!(iupac_bases(b'R'), Some(&b"AG"[..]));
+/// assert_eq!(iupac_bases(b'n'), Some(&b"ACGT"[..]));
+/// assert_eq!(iupac_bases(b'X'), None);
+/// ```
+pub fn iupac_bases(code: u8) -> Option<&'static [u8]> {
+    let bases: &'static [u8] = match code.to_ascii_uppercase() {
+        b'A' => b"A",
+        b'C' => b"C",
+        b'G' => b"G",
+        b'T' => b"T",
+        b'R' => b"AG",
+        b'Y' => b"CT",
+        b'S' => b"CG",
+        b'W' => b"AT",
+        b'K' => b"GT",
+        b'M' => b"AC",
+        b'B' => b"CGT",
+        b'D' => b"AGT",
+        b'H' => b"ACT",
+        b'V' => b"ACG",
+        b'N' => b"ACGT",
+        _ => return None,
+    };
+    Some(bases)
+}
+
+/// Whether a base of the reference matches a base of the query: the same symbol or,
+/// for an IUPAC code in the reference, one of the nucleotides it stands for
+/// ```
+/// # use assignment_1::normalize::iupac_matches;
+/// assert!(iupac_matches(b'R', b'A') && iupac_matches(b'R', b'G'));
+/// assert!(!iupac_matches(b'R', b'C'));
+/// assert!(iupac_matches(b'R', b'R') && !iupac_matches(b'A', b'R'));
+/// assert!(!iupac_matches(b'$', b'A'));
+/// ```
+pub fn iupac_matches(reference: u8, query: u8) -> bool {
+    reference == query || iupac_bases(reference).is_some_and(|bases| bases.contains(&query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(unknown: UnknownBase) -> NormalizePolicy {
+        NormalizePolicy {
+            unknown,
+            ..NormalizePolicy::default()
+        }
+    }
+
+    #[test]
+    fn lowercase_is_uppercased_unless_the_case_is_kept() {
+        for unknown in [
+            UnknownBase::Random,
+            UnknownBase::Mark,
+            UnknownBase::Keep,
+            UnknownBase::Reject,
+            UnknownBase::Iupac,
+        ] {
+            let (sequence, stats) = normalize_sequence("acgtACGT", policy(unknown));
+            assert_eq!(sequence, "ACGTACGT");
+            assert_eq!(stats.lowercase, 4);
+            assert_eq!(stats.unknown + stats.rejected, 0);
+        }
+        let keep_case = NormalizePolicy {
+            keep_case: true,
+            ..NormalizePolicy::default()
+        };
+        assert_eq!(normalize_sequence("acGT", keep_case).0, "acGT");
+        assert_eq!(normalize_nucleotide('t', keep_case), Some('t'));
+    }
+
+    #[test]
+    fn unknown_characters_follow_the_policy() {
+        let input = "ANnX-T";
+        let (random, stats) = normalize_sequence(input, policy(UnknownBase::Random));
+        assert_eq!(random.len(), input.len());
+        assert!(random.bytes().all(|x| b"ACGT".contains(&x)), "{random}");
+        assert_eq!(stats.unknown, 4);
+
+        let (marked, stats) = normalize_sequence(input, policy(UnknownBase::Mark));
+        assert_eq!(marked, "ANNNNT");
+        assert_eq!(stats.unknown, 4);
+
+        let (kept, stats) = normalize_sequence(input, policy(UnknownBase::Keep));
+        assert_eq!(kept, "ANNX-T");
+        assert_eq!(stats.unknown, 4);
+
+        let (rejected, stats) = normalize_sequence(input, policy(UnknownBase::Reject));
+        assert_eq!(rejected, "AT");
+        assert_eq!((stats.unknown, stats.rejected), (0, 4));
+
+        let (iupac, stats) = normalize_sequence("ArYX-n", policy(UnknownBase::Iupac));
+        assert_eq!(iupac, "ARYNNN");
+        assert_eq!(stats.unknown, 5);
+    }
+
+    #[test]
+    fn rna_reads_uracil_as_thymine() {
+        let rna = NormalizePolicy {
+            unknown: UnknownBase::Reject,
+            rna: true,
+            keep_case: true,
+        };
+        assert_eq!(normalize_sequence("ACGUu", rna).0, "ACGTt");
+        assert_eq!(normalize_nucleotide('U', policy(UnknownBase::Reject)), None);
+        let keep_case = NormalizePolicy {
+            keep_case: true,
+            ..NormalizePolicy::default()
+        };
+        // unknown lowercase bases are replaced in lowercase
+        assert!(b"acgt".contains(&(normalize_nucleotide('n', keep_case).unwrap() as u8)));
+    }
+}
@@ -0,0 +1,52 @@
+use rayon::{prelude::*, ThreadPoolBuilder};
+
+/// Sorts the suffixes of `text` on `threads` threads
+///
+/// Suffixes are compared directly, so this takes O(n log n) comparisons that each
+/// cost up to the length of the longest repeat. Every suffix is distinct so the
+/// result is identical to the single threaded construction.
+pub fn suffix_array(text: &[u8], threads: usize) -> Vec<u32> {
+    let mut suffix_array: Vec<u32> = (0..text.len() as u32).collect();
+    let sort = |suffix_array: &mut Vec<u32>| {
+        suffix_array.par_sort_unstable_by(|&a, &b| text[a as usize..].cmp(&text[b as usize..]))
+    };
+    match ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool.install(|| sort(&mut suffix_array)),
+        Err(_) => sort(&mut suffix_array),
+    }
+    suffix_array
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{distributions::Slice, rngs::StdRng, Rng, SeedableRng};
+    use suffix::SuffixTable;
+
+    #[test]
+    fn test_parallel_matches_suffix_crate() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let nucleotides = ['A', 'C', 'T', 'G'];
+        let nucleotide_distribution = Slice::new(&nucleotides).unwrap();
+        let mut inputs: Vec<String> = vec![
+            String::from("$"),
+            String::from("AAAAAAAAAAAA$"),
+            String::from("ACGTTGCA$TTGCAACG$"),
+            String::from("BANANA$"),
+        ];
+        for size in [10, 1000, 20000] {
+            let sequence: String = (&mut rng)
+                .sample_iter(&nucleotide_distribution)
+                .take(size)
+                .chain(['$'].iter())
+                .collect();
+            inputs.push(sequence);
+        }
+        for sequence in inputs.iter() {
+            let expected = SuffixTable::new(sequence.as_str()).table().to_vec();
+            for threads in [2, 4] {
+                assert_eq!(suffix_array(sequence.as_bytes(), threads), expected);
+            }
+        }
+    }
+}
@@ -1,9 +1,53 @@
 use eyre::{eyre, Report, Result};
 use itertools::Itertools;
 use serde::{ser::SerializeTupleVariant, Deserialize, Serialize};
-use std::{collections::HashMap, iter::zip};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    iter::zip,
+};
 
-use crate::search::Span;
+use crate::{encoding::canonical_kmer, search::Span};
+
+/// Sparse tables with a k below this value are written to disk as dense tables
+pub const DEFAULT_DENSE_THRESHOLD: u16 = 12;
+
+/// The longest prefix whose dense index fits in a usize
+pub const MAX_INDEX_K: u16 = (usize::BITS / 2) as u16;
+
+/// The largest k for which a dense table of 4^k entries can be allocated,
+/// sparse tables with a larger k are never converted to dense
+pub const MAX_DENSE_K: u16 = MAX_INDEX_K - 1;
+
+/// Summary of how much a prefix table narrows the search
+///
+/// Widths are the number of suffixes sharing a k-mer prefix. A large maximum
+/// points at repetitive k-mers where the table barely helps, a larger k splits them up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefixTableStats {
+    pub k: u16,
+    /// The number of k-mers present in the table
+    pub count: usize,
+    pub min_width: u32,
+    pub max_width: u32,
+    pub mean_width: f64,
+    /// The k-mer with the widest span
+    pub largest: Option<String>,
+}
+
+impl fmt::Display for PrefixTableStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "k: {}", self.k)?;
+        writeln!(f, "k-mers present: {}", self.count)?;
+        writeln!(f, "min span width: {}", self.min_width)?;
+        writeln!(f, "max span width: {}", self.max_width)?;
+        write!(f, "mean span width: {:.2}", self.mean_width)?;
+        if let Some(largest) = &self.largest {
+            write!(f, "\nlargest bucket: {largest}")?;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
 pub enum PrefixTable {
@@ -12,39 +56,66 @@ pub enum PrefixTable {
 }
 
 impl PrefixTable {
-    pub fn new_dense(k: u16) -> Self {
-        Self::Dense(vec![None; 4_usize.pow(k as u32)])
+    pub fn new_dense(k: u16) -> Result<Self> {
+        if k > MAX_DENSE_K {
+            return Err(eyre!(
+                "A dense prefix table supports k up to {MAX_DENSE_K} but k={k} was requested"
+            ));
+        }
+        Ok(Self::Dense(vec![None; 4_usize.pow(k as u32)]))
     }
 
     pub fn new_sparse(k: u16) -> Self {
         Self::Sparse(k, HashMap::new())
     }
 
+    /// An empty sparse table with room for `capacity` k-mers before it rehashes
+    pub fn sparse_with_capacity(k: u16, capacity: usize) -> Self {
+        Self::Sparse(k, HashMap::with_capacity(capacity))
+    }
+
     pub fn k(&self) -> u16 {
         match self {
-            Self::Dense(table) => (table.len() as f32).log(4.0) as u16,
+            // a dense table holds 4^k spans
+            Self::Dense(table) => (table.len().trailing_zeros() / 2) as u16,
             Self::Sparse(k, _) => *k,
         }
     }
 
+    /// `"sparse"` or `"dense"`
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Self::Sparse(..) => "sparse",
+            Self::Dense(_) => "dense",
+        }
+    }
+
+    /// The span of a k-mer, `None` if it is not stored (or is not a k-mer of this table)
     pub fn get(&self, k: &str) -> Option<Span> {
         match self {
             Self::Sparse(_, table) => table.get(k).copied(),
             Self::Dense(table) => {
-                let index = prefix_to_index(k).unwrap();
-                if let Some(pair) = table.get(index) {
-                    return *pair;
+                // a shorter prefix would address the slot of a different k-mer
+                if k.len() != self.k() as usize {
+                    return None;
                 }
-                None
+                let index = prefix_to_index(k).ok()?;
+                table.get(index).copied().flatten()
             }
         }
     }
 
+    /// Stores the span of a k-mer
+    ///
+    /// # Panics
+    /// If the table is dense and `k` is not a k-mer of A, C, G and T of its length
     pub fn insert(&mut self, k: String, v: Span) {
+        let len = self.k() as usize;
         match self {
             Self::Dense(table) => {
+                assert_eq!(k.len(), len, "{k} is not a {len}-mer");
                 let index = prefix_to_index(&k).unwrap();
-                table.insert(index, Some(v))
+                table[index] = Some(v);
             }
             Self::Sparse(_, table) => {
                 table.insert(k, v);
@@ -52,9 +123,13 @@ impl PrefixTable {
         }
     }
 
+    /// Converts a sparse table to a dense one (tables with k > MAX_DENSE_K or
+    /// k-mers outside ACGT stay sparse)
     pub fn to_dense(other: Self) -> Self {
         match other {
             Self::Dense(_) => other,
+            Self::Sparse(k, _) if k > MAX_DENSE_K => other,
+            Self::Sparse(_, ref table) if !is_nucleotide_table(table) => other,
             Self::Sparse(k, mut table) => {
                 let dense = (0..k)
                     .map(|_| "ACGT".chars())
@@ -69,9 +144,13 @@ impl PrefixTable {
         }
     }
 
+    /// Copies a sparse table into a dense one (tables with k > MAX_DENSE_K or
+    /// k-mers outside ACGT stay sparse)
     pub fn clone_dense(other: &Self) -> Self {
         match other {
             Self::Dense(_) => other.clone(),
+            Self::Sparse(k, _) if *k > MAX_DENSE_K => other.clone(),
+            Self::Sparse(_, table) if !is_nucleotide_table(table) => other.clone(),
             Self::Sparse(k, table) => {
                 let dense = (0..*k)
                     .map(|_| "ACGT".chars())
@@ -86,6 +165,92 @@ impl PrefixTable {
         }
     }
 
+    /// The number of occurrences of every k-mer in the table together with its reverse
+    /// complement, keyed by the [`canonical_kmer`]
+    ///
+    /// Both strands of a k-mer are stored in a table built from a reference, so their
+    /// spans are summed. A palindromic k-mer is only counted once.
+    /// ```
+    /// # use assignment_1::{prefix_table::PrefixTable, search::Span};
+    /// let mut table = PrefixTable::new_sparse(2);
+    /// table.insert(String::from("TG"), Span::new(10, 13));
+    /// table.insert(String::from("CA"), Span::new(4, 5));
+    /// table.insert(String::from("AT"), Span::new(0, 2));
+    /// let counts = table.canonical_counts();
+    /// assert_eq!(counts.get("CA"), Some(&4));
+    /// assert_eq!(counts.get("AT"), Some(&2));
+    /// assert_eq!(counts.get("TG"), None);
+    /// ```
+    pub fn canonical_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for (kmer, span) in self.iter() {
+            *counts
+                .entry(canonical_kmer(&kmer).into_owned())
+                .or_default() += span.len();
+        }
+        counts
+    }
+
+    /// Every k-mer stored in the table with its span, in lexicographic order for a dense
+    /// table and in no particular order for a sparse one
+    /// ```
+    /// # use assignment_1::{prefix_table::PrefixTable, search::Span};
+    /// let mut table = PrefixTable::new_dense(2).unwrap();
+    /// table.insert(String::from("GT"), Span::new(3, 8));
+    /// table.insert(String::from("AC"), Span::new(1, 3));
+    /// let kmers: Vec<(String, usize)> =
+    ///     table.iter().map(|(kmer, span)| (kmer, span.len())).collect();
+    /// assert_eq!(kmers, [(String::from("AC"), 2), (String::from("GT"), 5)]);
+    /// ```
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (String, Span)> + '_> {
+        let k = self.k();
+        match self {
+            Self::Sparse(_, table) => {
+                Box::new(table.iter().map(|(prefix, span)| (prefix.clone(), *span)))
+            }
+            Self::Dense(table) => {
+                Box::new(table.iter().enumerate().filter_map(move |(index, span)| {
+                    span.map(|span| (index_to_prefix(index, k), span))
+                }))
+            }
+        }
+    }
+
+    /// Computes the number of k-mers and the distribution of their span widths
+    ///
+    /// ```
+    /// # use assignment_1::{prefix_table::PrefixTable, search::Span};
+    /// let mut table = PrefixTable::new_sparse(2);
+    /// table.insert(String::from("AC"), Span::new(1, 3));
+    /// table.insert(String::from("GT"), Span::new(3, 8));
+    /// let stats = table.stats();
+    /// assert_eq!((stats.count, stats.min_width, stats.max_width), (2, 2, 5));
+    /// assert_eq!(stats.mean_width, 3.5);
+    /// assert_eq!(stats.largest.as_deref(), Some("GT"));
+    /// ```
+    pub fn stats(&self) -> PrefixTableStats {
+        let spans: Vec<(String, Span)> = self.iter().collect();
+        let widths = || spans.iter().map(|(_, span)| span.len() as u32);
+        let largest = spans
+            .iter()
+            // ties go to the smallest k-mer so the result does not depend on hash order
+            .max_by(|(a, a_span), (b, b_span)| a_span.len().cmp(&b_span.len()).then(b.cmp(a)))
+            .map(|(prefix, _)| prefix.clone());
+        let total: u64 = widths().map(u64::from).sum();
+        PrefixTableStats {
+            k: self.k(),
+            count: spans.len(),
+            min_width: widths().min().unwrap_or(0),
+            max_width: widths().max().unwrap_or(0),
+            mean_width: if spans.is_empty() {
+                0.0
+            } else {
+                total as f64 / spans.len() as f64
+            },
+            largest,
+        }
+    }
+
     pub fn to_sparse(other: Self) -> Self {
         let k = other.k();
         match other {
@@ -97,7 +262,7 @@ impl PrefixTable {
                         .map(|_| "ACGT".chars())
                         .multi_cartesian_product()
                         .map(|x| x.iter().collect::<String>()),
-                    table.into_iter(),
+                    table,
                 )
                 .for_each(|(prefix, span)| {
                     if let Some(value) = span {
@@ -110,24 +275,52 @@ impl PrefixTable {
     }
 }
 
-impl Serialize for PrefixTable {
+/// Serializes a prefix table using a custom dense/sparse crossover
+///
+/// Sparse tables with `k < threshold` are converted to dense tables on the wire,
+/// larger tables (and any with `k > MAX_DENSE_K` or k-mers outside ACGT) are written as is. Dense tables are always written as dense.
+///
+/// ```
+/// # use assignment_1::{prefix_table::PrefixTable, search::Span};
+/// let mut table = PrefixTable::new_sparse(3);
+/// table.insert(String::from("ACG"), Span::new(0, 2));
+/// let bytes = bincode::serialize(&table.with_dense_threshold(3)).unwrap();
+/// let copied: PrefixTable = bincode::deserialize(&bytes).unwrap();
+/// assert_eq!(copied, table);
+/// ```
+pub struct DenseThreshold<'a> {
+    table: &'a PrefixTable,
+    threshold: u16,
+}
+
+impl PrefixTable {
+    pub fn with_dense_threshold(&self, threshold: u16) -> DenseThreshold<'_> {
+        DenseThreshold {
+            table: self,
+            threshold,
+        }
+    }
+}
+
+impl Serialize for DenseThreshold<'_> {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        match self {
-            Self::Sparse(k, table) => {
-                if *k < 12 {
-                    let dense = Self::clone_dense(self);
+        match self.table {
+            PrefixTable::Sparse(k, table) => {
+                if *k < self.threshold && *k <= MAX_DENSE_K && is_nucleotide_table(table) {
+                    let dense = PrefixTable::clone_dense(self.table);
                     return dense.serialize(serializer);
                 }
                 let mut state =
                     serializer.serialize_tuple_variant("PrefixTable", 0, "Sparse", 2)?;
                 state.serialize_field(k)?;
-                state.serialize_field(table)?;
+                // sorted so the same table always serializes to the same bytes
+                state.serialize_field(&table.iter().collect::<BTreeMap<_, _>>())?;
                 state.end()
             }
-            Self::Dense(table) => {
+            PrefixTable::Dense(table) => {
                 let mut state = serializer.serialize_tuple_variant("PrefixTable", 1, "Dense", 1)?;
                 state.serialize_field(table)?;
                 state.end()
@@ -136,6 +329,136 @@ impl Serialize for PrefixTable {
     }
 }
 
+impl Serialize for PrefixTable {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.with_dense_threshold(DEFAULT_DENSE_THRESHOLD)
+            .serialize(serializer)
+    }
+}
+
+/// A prefix table keyed by prefixes of varying length, like a trie of the suffix array
+///
+/// Every `min_k`-mer of the reference has a bucket and every bucket holding more than
+/// `max_width` suffixes is split into the buckets of the prefixes one base longer, up to
+/// `max_k` bases. Repetitive k-mers are narrowed further than with a uniform `min_k`
+/// without storing every `max_k`-mer.
+///
+/// ```
+/// # use assignment_1::{prefix_table::AdaptivePrefixTable, search::Span};
+/// let mut table = AdaptivePrefixTable::new(1, 3, 2);
+/// table.insert(String::from("A"), Span::new(1, 6));
+/// table.insert(String::from("AC"), Span::new(1, 4));
+/// table.insert(String::from("ACG"), Span::new(1, 3));
+/// table.insert(String::from("ACT"), Span::new(3, 4));
+/// table.insert(String::from("AG"), Span::new(4, 6));
+/// table.insert(String::from("C"), Span::new(6, 8));
+/// assert_eq!(table.lookup("ACTTA"), Some(Span::new(3, 4)));
+/// assert_eq!(table.lookup("AGA"), Some(Span::new(4, 6)));
+/// assert_eq!(table.lookup("AC"), Some(Span::new(1, 4)));
+/// assert_eq!(table.lookup("CCC"), Some(Span::new(6, 8)));
+/// // AA is not in the split bucket of A, so no suffix starts with it
+/// assert_eq!(table.lookup("AAC"), None);
+/// assert_eq!(table.lookup("G"), None);
+/// ```
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct AdaptivePrefixTable {
+    min_k: u16,
+    max_k: u16,
+    max_width: u32,
+    #[serde(serialize_with = "serialize_sorted")]
+    buckets: HashMap<String, Span>,
+}
+
+impl AdaptivePrefixTable {
+    pub fn new(min_k: u16, max_k: u16, max_width: u32) -> Self {
+        Self {
+            min_k,
+            max_k: max_k.max(min_k),
+            max_width,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// The length of the shortest prefixes, queries shorter than this are not narrowed
+    pub fn min_k(&self) -> u16 {
+        self.min_k
+    }
+
+    pub fn max_k(&self) -> u16 {
+        self.max_k
+    }
+
+    /// Buckets holding more suffixes than this are split
+    pub fn max_width(&self) -> u32 {
+        self.max_width
+    }
+
+    /// The number of buckets of every length
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// The length of the longest prefix stored
+    pub fn depth(&self) -> u16 {
+        self.buckets
+            .keys()
+            .map(|prefix| prefix.len() as u16)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Whether the bucket of `prefix` is split into the buckets one base longer
+    pub fn is_split(&self, prefix: &str, span: Span) -> bool {
+        span.len() > self.max_width as usize && prefix.len() < self.max_k as usize
+    }
+
+    /// Stores the span of a prefix, every shorter prefix down to `min_k` bases must be
+    /// stored and split for it to be looked up
+    pub fn insert(&mut self, prefix: String, span: Span) {
+        self.buckets.insert(prefix, span);
+    }
+
+    /// The span of the longest stored prefix of the query, descending from its `min_k`
+    /// bases through the split buckets. `None` if no suffix starts with the query's
+    /// prefix of that length (or the query is shorter than `min_k`)
+    pub fn lookup(&self, query: &str) -> Option<Span> {
+        let min_k = self.min_k as usize;
+        let mut span = *self.buckets.get(query.get(..min_k)?)?;
+        for len in min_k + 1..=query.len().min(self.max_k as usize) {
+            if !self.is_split(&query[..len - 1], span) {
+                break;
+            }
+            span = *self.buckets.get(query.get(..len)?)?;
+        }
+        Some(span)
+    }
+}
+
+/// Serializes a map in key order so the same table always serializes to the same bytes
+fn serialize_sorted<S>(
+    map: &HashMap<String, Span>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+/// Whether every k-mer can be addressed in a dense table
+fn is_nucleotide_table(table: &HashMap<String, Span>) -> bool {
+    table
+        .keys()
+        .all(|prefix| prefix.bytes().all(|x| b"ACGT".contains(&x)))
+}
+
 fn nucleotide_to_int(nucleotide: &char) -> Result<usize> {
     match nucleotide {
         'A' => Ok(0),
@@ -156,12 +479,36 @@ fn nucleotide_to_value(nucleotide: &char) -> Result<char> {
     }
 }
 
+/// The inverse of [`prefix_to_index`] for a table of k-mers
+fn index_to_prefix(mut index: usize, k: u16) -> String {
+    let mut prefix: Vec<u8> = (0..k)
+        .map(|_| {
+            let nucleotide = b"ACGT"[index % 4];
+            index /= 4;
+            nucleotide
+        })
+        .collect();
+    prefix.reverse();
+    String::from_utf8(prefix).unwrap()
+}
+
+fn check_addressable(prefix: &str) -> Result<()> {
+    if prefix.len() > MAX_INDEX_K as usize {
+        return Err(eyre!(
+            "A prefix of length {} is too long to index a dense prefix table (max {MAX_INDEX_K})",
+            prefix.len()
+        ));
+    }
+    Ok(())
+}
+
 /// Convert a prefix like AAC to it's position in the prefix array
 /// ```
 /// # use assignment_1::prefix_table::prefix_to_index;
 /// assert_eq!(prefix_to_index("AAC").unwrap(), 1);
 /// assert_eq!(prefix_to_index("ATC").unwrap(), 13);
 pub fn prefix_to_index(prefix: &str) -> Result<usize> {
+    check_addressable(prefix)?;
     let result = prefix
         .chars()
         .map(|x| nucleotide_to_value(&x))
@@ -175,6 +522,7 @@ pub fn prefix_to_index(prefix: &str) -> Result<usize> {
 /// assert_eq!(prefix_to_index("AAC").unwrap(), 1);
 /// assert_eq!(prefix_to_index("ATC").unwrap(), 13);
 pub fn prefix_to_index_custom(prefix: &str) -> Result<usize> {
+    check_addressable(prefix)?;
     let mut result = 0_usize;
     prefix
         .chars()
@@ -191,13 +539,96 @@ pub fn prefix_to_index_custom(prefix: &str) -> Result<usize> {
 pub mod tests {
 
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// A sparse table holding a random subset of the k-mers with random spans
+    fn random_table(rng: &mut StdRng, k: u16) -> PrefixTable {
+        let mut table = PrefixTable::new_sparse(k);
+        for index in 0..4_usize.pow(k as u32) {
+            if rng.gen_bool(0.3) {
+                let start = rng.gen_range(0..1000);
+                let span = Span::new(start, start + rng.gen_range(1..50));
+                table.insert(index_to_prefix(index, k), span);
+            }
+        }
+        table
+    }
+
+    fn all_kmers(k: u16) -> impl Iterator<Item = String> {
+        (0..4_usize.pow(k as u32)).map(move |index| index_to_prefix(index, k))
+    }
+
+    #[test]
+    fn test_reverse_complements_share_a_canonical_bucket() {
+        let mut rng = StdRng::seed_from_u64(701);
+        for k in 1..=5 {
+            let table = random_table(&mut rng, k);
+            let counts = table.canonical_counts();
+            for kmer in all_kmers(k) {
+                let reverse = crate::encoding::reverse_complement(&kmer);
+                assert_eq!(canonical_kmer(&kmer), canonical_kmer(&reverse));
+                let len = |kmer: &str| table.get(kmer).map_or(0, |span| span.len());
+                let expected = if reverse == kmer {
+                    len(&kmer)
+                } else {
+                    len(&kmer) + len(&reverse)
+                };
+                let count = counts.get(canonical_kmer(&kmer).as_ref()).copied();
+                assert_eq!(count.unwrap_or(0), expected, "{kmer}");
+            }
+            assert!(counts
+                .keys()
+                .all(|kmer| canonical_kmer(kmer) == kmer.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_dense_sparse_round_trips_keep_every_span() {
+        let mut rng = StdRng::seed_from_u64(701);
+        for k in 1..=6 {
+            for _ in 0..5 {
+                let sparse = random_table(&mut rng, k);
+                let dense = PrefixTable::clone_dense(&sparse);
+                assert!(matches!(dense, PrefixTable::Dense(_)));
+                assert_eq!(dense.k(), k);
+                assert_eq!(PrefixTable::to_dense(sparse.clone()), dense);
+                assert_eq!(PrefixTable::to_sparse(dense.clone()), sparse);
+                assert_eq!(
+                    PrefixTable::to_dense(PrefixTable::to_sparse(dense.clone())),
+                    dense
+                );
+
+                let mut inserted = PrefixTable::new_dense(k).unwrap();
+                if let PrefixTable::Sparse(_, spans) = &sparse {
+                    for (kmer, span) in spans {
+                        inserted.insert(kmer.clone(), *span);
+                    }
+                }
+                assert_eq!(inserted, dense);
+                for kmer in all_kmers(k) {
+                    assert_eq!(dense.get(&kmer), sparse.get(&kmer), "{kmer}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dense_get_of_a_foreign_key_is_none() {
+        let mut table = PrefixTable::new_dense(3).unwrap();
+        table.insert(String::from("AAC"), Span::new(1, 2));
+        assert_eq!(table.get("AAC"), Some(Span::new(1, 2)));
+        // "AC" addresses the same slot as "AAC"
+        assert_eq!(table.get("AC"), None);
+        assert_eq!(table.get("AACA"), None);
+        assert_eq!(table.get("ANC"), None);
+    }
 
     #[test]
     fn test_serialize_deserialize_sparse() {
         let mut table = PrefixTable::new_sparse(2);
-        table.insert(String::from("AA"), (0, 1));
-        table.insert(String::from("AC"), (1, 3));
-        table.insert(String::from("TT"), (3, 5));
+        table.insert(String::from("AA"), Span::new(0, 1));
+        table.insert(String::from("AC"), Span::new(1, 3));
+        table.insert(String::from("TT"), Span::new(3, 5));
         let table_bytes = bincode::serialize(&table).unwrap();
         let copied: PrefixTable = bincode::deserialize(&table_bytes).unwrap();
         let sparse = PrefixTable::to_sparse(copied);
@@ -207,10 +638,84 @@ pub mod tests {
     #[test]
     fn test_serialize_large_k() {
         let mut table = PrefixTable::new_sparse(20);
-        table.insert(String::from("AAAAAAAAAAAAAAAAAAAA"), (0, 3));
-        table.insert(String::from("TTTTTTTTTTTTTTTTTTTT"), (3, 5));
+        table.insert(String::from("AAAAAAAAAAAAAAAAAAAA"), Span::new(0, 3));
+        table.insert(String::from("TTTTTTTTTTTTTTTTTTTT"), Span::new(3, 5));
+        let table_bytes = bincode::serialize(&table).unwrap();
+        let copied: PrefixTable = bincode::deserialize(&table_bytes).unwrap();
+        assert_eq!(copied, table);
+    }
+
+    #[test]
+    fn test_large_k_is_not_addressable() {
+        let prefix = "A".repeat(40);
+        assert!(prefix_to_index(&prefix).is_err());
+        assert!(prefix_to_index_custom(&prefix).is_err());
+        assert!(PrefixTable::new_dense(40).is_err());
+        assert_eq!(
+            prefix_to_index(&"T".repeat(MAX_INDEX_K as usize)).unwrap(),
+            usize::MAX
+        );
+
+        let mut table = PrefixTable::new_sparse(40);
+        table.insert(prefix, Span::new(0, 3));
+        assert_eq!(PrefixTable::clone_dense(&table), table);
+        assert_eq!(round_trip(&table, 99), table);
+    }
+
+    fn round_trip(table: &PrefixTable, threshold: u16) -> PrefixTable {
+        let table_bytes = bincode::serialize(&table.with_dense_threshold(threshold)).unwrap();
+        bincode::deserialize(&table_bytes).unwrap()
+    }
+
+    #[test]
+    fn test_serialize_below_threshold_is_dense() {
+        let mut table = PrefixTable::new_sparse(3);
+        table.insert(String::from("ACG"), Span::new(0, 2));
+        let copied = round_trip(&table, 4);
+        assert!(matches!(copied, PrefixTable::Dense(_)));
+        assert_eq!(PrefixTable::to_sparse(copied), table);
+    }
+
+    #[test]
+    fn test_serialize_at_threshold_is_sparse() {
+        let mut table = PrefixTable::new_sparse(3);
+        table.insert(String::from("ACG"), Span::new(0, 2));
+        let copied = round_trip(&table, 3);
+        assert_eq!(copied, table);
+    }
+
+    #[test]
+    fn test_serialize_default_threshold_boundary() {
+        let mut table = PrefixTable::new_sparse(DEFAULT_DENSE_THRESHOLD);
+        table.insert(
+            "A".repeat(DEFAULT_DENSE_THRESHOLD as usize),
+            Span::new(0, 1),
+        );
         let table_bytes = bincode::serialize(&table).unwrap();
         let copied: PrefixTable = bincode::deserialize(&table_bytes).unwrap();
         assert_eq!(copied, table);
+        assert_eq!(
+            table_bytes,
+            bincode::serialize(&table.with_dense_threshold(DEFAULT_DENSE_THRESHOLD)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stats_dense_and_sparse_agree() {
+        let mut table = PrefixTable::new_sparse(2);
+        table.insert(String::from("AA"), Span::new(1, 2));
+        table.insert(String::from("CT"), Span::new(2, 6));
+        table.insert(String::from("TG"), Span::new(6, 8));
+        let dense = PrefixTable::clone_dense(&table);
+        assert_eq!(dense.stats(), table.stats());
+        assert_eq!(table.stats().largest.as_deref(), Some("CT"));
+        assert_eq!(table.stats().mean_width, 7.0 / 3.0);
+    }
+
+    #[test]
+    fn test_stats_empty_table() {
+        let stats = PrefixTable::new_sparse(3).stats();
+        assert_eq!((stats.count, stats.min_width, stats.max_width), (0, 0, 0));
+        assert_eq!(stats.largest, None);
     }
 }
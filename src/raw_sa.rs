@@ -0,0 +1,120 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use eyre::{eyre, Result, WrapErr};
+
+use crate::suffix_array::SuffixArray;
+
+/// The path of the text file holding the sequence for a raw suffix array file
+/// ```
+/// # use assignment_1::raw_sa::sidecar_path;
+/// # use std::path::PathBuf;
+/// assert_eq!(sidecar_path("ecoli.sa"), PathBuf::from("ecoli.sa.seq"));
+/// ```
+pub fn sidecar_path<P>(path: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let mut sidecar: OsString = path.as_ref().as_os_str().to_owned();
+    sidecar.push(".seq");
+    PathBuf::from(sidecar)
+}
+
+/// Writes the suffix array as contiguous little-endian u32s and the sequence to a `.seq` sidecar
+///
+/// Other tools expect the suffix array of the text without a sentinel, so the `$` suffix
+/// is left out of the array and the trailing `$` is left out of the text.
+/// The prefix table is not written.
+pub fn write_raw_sa<P>(suffix_array: &SuffixArray, path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if suffix_array.is_packed() {
+        return Err(eyre!(
+            "A packed index must be unpacked before writing it as a raw suffix array"
+        ));
+    }
+    let mut writer = BufWriter::new(
+        File::create(path).wrap_err(format!("Failed to create output file {path:?}"))?,
+    );
+    let sentinel = suffix_array.suffix_array.len() as u32 - 1;
+    for idx in suffix_array
+        .suffix_array
+        .iter()
+        .filter(|&&idx| idx != sentinel)
+    {
+        writer.write_all(&idx.to_le_bytes())?;
+    }
+    writer.flush()?;
+
+    let sidecar = sidecar_path(path);
+    fs::write(&sidecar, suffix_array.sequence().trim_end_matches('$'))
+        .wrap_err(format!("Failed to create sequence file {sidecar:?}"))?;
+    Ok(())
+}
+
+/// Reads a suffix array written by [`write_raw_sa`] (or another tool using the same layout)
+pub fn read_raw_sa<P>(path: P) -> Result<SuffixArray>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let sidecar = sidecar_path(path);
+    let mut sequence = fs::read_to_string(&sidecar)
+        .wrap_err(format!("Could not read sequence file {sidecar:?}"))?
+        .trim_end()
+        .to_string();
+
+    let mut bytes: Vec<u8> = Vec::new();
+    BufReader::new(File::open(path).wrap_err(format!("Could not open index file {path:?}"))?)
+        .read_to_end(&mut bytes)?;
+    if bytes.len() != 4 * sequence.len() {
+        return Err(eyre!(
+            "raw suffix array {path:?} has {} bytes but the sequence has {} characters",
+            bytes.len(),
+            sequence.len()
+        ));
+    }
+    let mut suffix_array: Vec<u32> = Vec::with_capacity(sequence.len() + 1);
+    suffix_array.push(sequence.len() as u32);
+    suffix_array.extend(
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])),
+    );
+    sequence.push('$');
+    Ok(SuffixArray::from_parts(sequence, suffix_array))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{record::Record, testing::temp_path};
+
+    #[test]
+    fn test_raw_sa_round_trip() {
+        let record = Record {
+            header: String::from("test"),
+            sequence: String::from("AGGTGGCAATGCGCGCTCATCGCCTTGCAT"),
+        };
+        let sa = SuffixArray::from_record(record);
+        let path = temp_path("raw_sa_round_trip.sa");
+        write_raw_sa(&sa, &path).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().len() as usize,
+            4 * (sa.suffix_array.len() - 1)
+        );
+        let copied = read_raw_sa(&path).unwrap();
+        fs::remove_file(sidecar_path(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(copied.sequence(), sa.sequence());
+        assert_eq!(copied.suffix_array, sa.suffix_array);
+        assert_eq!(copied.checksum(), sa.checksum());
+        assert_eq!(copied.naive_search("GCA"), sa.naive_search("GCA"));
+    }
+}
@@ -1,19 +1,146 @@
 use std::{
+    collections::VecDeque,
     fs::{self, File},
     io::{self, BufRead},
     path::Path,
+    thread,
+    time::Duration,
 };
 
-use rand::{rngs::ThreadRng, seq::IteratorRandom, thread_rng};
-
-use crate::record::Record;
-use eyre::eyre;
+use crate::{
+    normalize::{find_invalid, normalize_sequence, NormalizePolicy, UnknownBase},
+    record::Record,
+};
+use eyre::{eyre, WrapErr};
 
 pub const START_CHARACTER: char = '>';
 
+/// Lines starting with this character are comments and are skipped
+pub const COMMENT_CHARACTER: char = ';';
+
+/// Transliterate an RNA sequence to DNA by replacing uracil with thymine
+/// ```
+/// # use assignment_1::reader::rna_to_dna;
+/// assert_eq!(rna_to_dna("ACGU"), "ACGT");
+/// assert_eq!(rna_to_dna("acgu"), "acgt");
+/// ```
+pub fn rna_to_dna(sequence: &str) -> String {
+    sequence
+        .chars()
+        .map(|x| match x {
+            'U' => 'T',
+            'u' => 't',
+            _ => x,
+        })
+        .collect()
+}
+
+/// Transliterate a DNA sequence to RNA by replacing thymine with uracil
+/// ```
+/// # use assignment_1::reader::dna_to_rna;
+/// assert_eq!(dna_to_rna("ACGT"), "ACGU");
+/// ```
+pub fn dna_to_rna(sequence: &str) -> String {
+    sequence
+        .chars()
+        .map(|x| match x {
+            'T' => 'U',
+            't' => 'u',
+            _ => x,
+        })
+        .collect()
+}
+
+/// Uppercases a sequence and replaces anything other than A, C, G and T with a
+/// random nucleotide, the same sanitization applied to sequences read from a file
+/// ```
+/// # use assignment_1::reader::sanitize_sequence;
+/// assert_eq!(sanitize_sequence("acgT"), "ACGT");
+/// assert!(sanitize_sequence("ANT").bytes().all(|x| b"ACGT".contains(&x)));
+/// ```
+pub fn sanitize_sequence(sequence: &str) -> String {
+    normalize_sequence(sequence, NormalizePolicy::default()).0
+}
+
+/// Reads every record of a reference FASTA file with a [`Reader`] set up by `configure`
+/// (e.g. `|reader| reader.soft_masked(true)`)
+///
+/// Fails when the file cannot be parsed, holds no records or when none of its
+/// records contain any bases, since such a reference cannot be indexed.
+pub fn read_reference<P, F>(path: P, configure: F) -> eyre::Result<Vec<Record>>
+where
+    P: AsRef<Path>,
+    F: FnOnce(Reader) -> Reader,
+{
+    let path = path.as_ref();
+    let reader =
+        Reader::from_file(path).wrap_err(format!("The reference file {path:?} does not exist"))?;
+    let records = configure(reader)
+        .collect::<eyre::Result<Vec<Record>>>()
+        .wrap_err(format!("could not parse a record of {path:?}"))?;
+    if records.is_empty() {
+        return Err(eyre!(
+            "The reference file {path:?} was empty (pass --wait if it is still being written)"
+        ));
+    }
+    if records.iter().all(|record| record.sequence().is_empty()) {
+        return Err(eyre!(
+            "The reference file {path:?} does not contain any bases"
+        ));
+    }
+    Ok(records)
+}
+
+/// Waits until a file is non-empty and its size stays the same for one `interval`
+///
+/// Gives up after `attempts` checks, reporting whether the file stayed empty or was
+/// still growing. Returns the final size of the file.
+pub fn wait_for_complete_file<P>(path: P, attempts: u32, interval: Duration) -> eyre::Result<u64>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let file_size = || -> eyre::Result<u64> {
+        Ok(fs::metadata(path)
+            .wrap_err(format!("The reference file {path:?} does not exist"))?
+            .len())
+    };
+    let mut size = file_size()?;
+    for _ in 0..attempts {
+        thread::sleep(interval);
+        let next_size = file_size()?;
+        if next_size > 0 && next_size == size {
+            return Ok(size);
+        }
+        size = next_size;
+    }
+    match size {
+        0 => Err(eyre!(
+            "The reference file {path:?} was still empty after waiting {attempts} times"
+        )),
+        _ => Err(eyre!(
+            "The reference file {path:?} was still being written (now {size} bytes) after waiting {attempts} times"
+        )),
+    }
+}
+
 pub struct Reader {
     reader: io::BufReader<fs::File>,
     buffer: String,
+    rna: bool,
+    min_length: usize,
+    skipped: usize,
+    line_delimited: bool,
+    /// The (1-based) number of the last line read
+    line_number: usize,
+    keep_ambiguous: bool,
+    soft_masked: bool,
+    strict: bool,
+    iupac: bool,
+    /// Split the sequence of every record into several records on this character
+    split_on: Option<char>,
+    /// The records split off the last record read, returned before reading on
+    pending: VecDeque<Record>,
 }
 
 impl Reader {
@@ -21,9 +148,82 @@ impl Reader {
         Self {
             reader,
             buffer: String::new(),
+            rna: false,
+            min_length: 0,
+            skipped: 0,
+            line_delimited: false,
+            line_number: 0,
+            keep_ambiguous: false,
+            soft_masked: false,
+            strict: false,
+            iupac: false,
+            split_on: None,
+            pending: VecDeque::new(),
         }
     }
 
+    /// Treat the records as RNA and read U as T instead of as an unknown nucleotide
+    pub fn rna(mut self, rna: bool) -> Self {
+        self.rna = rna;
+        self
+    }
+
+    /// Silently skip records whose sequence is shorter than `min_length`
+    pub fn min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    /// Read one sequence per line instead of FASTA, records are named `line-<n>`
+    /// after their (1-based) line number and blank lines are ignored
+    pub fn line_delimited(mut self, line_delimited: bool) -> Self {
+        self.line_delimited = line_delimited;
+        self
+    }
+
+    /// Read anything other than A, C, G and T as
+    /// [`AMBIGUOUS_BASE`](crate::normalize::AMBIGUOUS_BASE) instead of replacing it
+    /// with a random nucleotide, so ambiguous regions can be told apart
+    pub fn keep_ambiguous(mut self, keep_ambiguous: bool) -> Self {
+        self.keep_ambiguous = keep_ambiguous;
+        self
+    }
+
+    /// Keep lowercase (soft-masked) bases lowercase instead of uppercasing them,
+    /// unknown lowercase bases are replaced in lowercase
+    pub fn soft_masked(mut self, soft_masked: bool) -> Self {
+        self.soft_masked = soft_masked;
+        self
+    }
+
+    /// Fail on the first character that is not A, C, G, T or N (or U with [`Reader::rna`])
+    /// instead of replacing it, to catch protein or text files early
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Keep IUPAC ambiguity codes (e.g. R for A or G) instead of replacing them, for
+    /// [`SuffixArray::search_iupac`](crate::suffix_array::SuffixArray::search_iupac).
+    /// Takes precedence over [`Reader::keep_ambiguous`]
+    pub fn iupac(mut self, iupac: bool) -> Self {
+        self.iupac = iupac;
+        self
+    }
+
+    /// Split the sequence of every record on `separator` (e.g. `,` for `ACGT,TTGA`) and read
+    /// each part as its own record, named after the record with a 1-based `/<n>` suffix on
+    /// its name. Empty parts are left out but still numbered
+    pub fn split_on(mut self, separator: Option<char>) -> Self {
+        self.split_on = separator;
+        self
+    }
+
+    /// The number of records skipped so far for being shorter than the minimum length
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
     pub fn from_file<P>(filename: P) -> io::Result<Self>
     where
         P: AsRef<Path>,
@@ -32,49 +232,163 @@ impl Reader {
         Ok(Self::new(io::BufReader::new(file)))
     }
 
-    fn sanitize_line(&self, line: &str, rng: &mut ThreadRng) -> String {
-        let converted: String = line
-            .trim_end()
-            .to_uppercase()
-            .chars()
-            .map(|x| match x {
-                'A' | 'C' | 'T' | 'G' => x,
-                _ => "ACTG"
-                    .chars()
-                    .choose(rng)
-                    .expect("Expected to choose a random character"),
-            })
-            .collect();
-        converted
+    /// How the sequence lines are normalized with the options of this reader
+    pub fn policy(&self) -> NormalizePolicy {
+        NormalizePolicy {
+            unknown: match (self.iupac, self.keep_ambiguous) {
+                (true, _) => UnknownBase::Iupac,
+                (false, true) => UnknownBase::Mark,
+                (false, false) => UnknownBase::Random,
+            },
+            rna: self.rna,
+            keep_case: self.soft_masked,
+        }
+    }
+
+    fn sanitize_line(&self, line: &str, header: &str) -> eyre::Result<String> {
+        let line = line.trim_end();
+        if self.strict {
+            if let Some((offset, invalid)) = find_invalid(line, self.rna) {
+                return Err(eyre!(
+                    "record {header} is not a nucleotide sequence: found {invalid:?} at line {}, column {} ({line:?})",
+                    self.line_number,
+                    offset + 1
+                ));
+            }
+        }
+        Ok(normalize_sequence(line, self.policy()).0)
     }
 
     pub fn read(&mut self, record: &mut Record) -> eyre::Result<()> {
         record.clear();
-        let mut rng: ThreadRng = thread_rng();
-        if self.buffer.trim_end().is_empty() {
-            self.reader.read_line(&mut self.buffer)?;
-            if self.buffer.trim_end().is_empty() {
+        if let Some(next) = self.pending.pop_front() {
+            *record = next;
+            return Ok(());
+        }
+        if self.line_delimited {
+            self.read_line(record)?;
+        } else {
+            self.read_fasta(record)?;
+        }
+        if let Some(separator) = self.split_on.filter(|_| !record.is_empty()) {
+            self.split_record(record, separator)?;
+        }
+        Ok(())
+    }
+
+    /// Splits the (unsanitized) sequence of `record` on `separator`, leaving the first part
+    /// in `record` and queueing the others
+    fn split_record(&mut self, record: &mut Record, separator: char) -> eyre::Result<()> {
+        let header = std::mem::take(&mut record.header);
+        let sequence = std::mem::take(&mut record.sequence);
+        let (name, description) = match header.split_once(char::is_whitespace) {
+            Some((name, description)) => (name, format!(" {description}")),
+            None => (header.as_str(), String::new()),
+        };
+        let parts = sequence
+            .split(separator)
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, part)| !part.is_empty())
+            .map(|(idx, part)| {
+                let header = format!("{name}/{}{description}", idx + 1);
+                let sequence = self.sanitize_line(part, &header)?;
+                Ok(Record { header, sequence })
+            })
+            .collect::<eyre::Result<Vec<Record>>>()?;
+        self.pending.extend(parts);
+        match self.pending.pop_front() {
+            Some(first) => *record = first,
+            // keep the header so a record without any part is not mistaken for the end
+            None => record.header = header,
+        }
+        Ok(())
+    }
+
+    /// Reads the next FASTA record
+    fn read_fasta(&mut self, record: &mut Record) -> eyre::Result<()> {
+        // the buffer carries the header of the next record over from the previous
+        // call, skip blank and comment lines until a header or the end of file
+        while self.buffer.trim_end().is_empty() || self.buffer.starts_with(COMMENT_CHARACTER) {
+            self.buffer.clear();
+            if self.reader.read_line(&mut self.buffer)? == 0 {
                 return Ok(());
             }
+            self.line_number += 1;
         }
 
         if !self.buffer.starts_with(START_CHARACTER) {
-            return Err(eyre!("invalid start character in line: {}", &self.buffer));
+            let error = eyre!("invalid start character in line: {}", &self.buffer);
+            self.skip_to_header()?;
+            return Err(error);
         }
 
         record.set_header(self.buffer[1..].trim_end().to_owned());
         loop {
             self.buffer.clear();
-            self.reader.read_line(&mut self.buffer)?;
+            if self.reader.read_line(&mut self.buffer)? == 0 {
+                break;
+            }
+            self.line_number += 1;
             let next_part = self.buffer.trim_end();
-            if next_part.is_empty() || next_part.starts_with(START_CHARACTER) {
+            if next_part.starts_with(START_CHARACTER) {
                 break;
             }
-            record.push_sequence_part(&self.sanitize_line(next_part, &mut rng));
+            if next_part.is_empty() || next_part.starts_with(COMMENT_CHARACTER) {
+                continue;
+            }
+            if self.split_on.is_some() {
+                // sanitized once split, the separator is not a base
+                record.push_sequence_part(next_part);
+                continue;
+            }
+            match self.sanitize_line(next_part, record.header()) {
+                Ok(sanitized) => record.push_sequence_part(&sanitized),
+                Err(error) => {
+                    self.skip_to_header()?;
+                    return Err(error);
+                }
+            }
         }
 
         Ok(())
     }
+
+    /// Discards lines up to the next header (left in the buffer) so reading can resume
+    /// with the next record after a malformed one
+    fn skip_to_header(&mut self) -> io::Result<()> {
+        loop {
+            self.buffer.clear();
+            if self.reader.read_line(&mut self.buffer)? == 0 {
+                return Ok(());
+            }
+            self.line_number += 1;
+            if self.buffer.starts_with(START_CHARACTER) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn read_line(&mut self, record: &mut Record) -> eyre::Result<()> {
+        loop {
+            self.buffer.clear();
+            if self.reader.read_line(&mut self.buffer)? == 0 {
+                return Ok(());
+            }
+            self.line_number += 1;
+            let sequence = self.buffer.trim();
+            if !sequence.is_empty() {
+                record.set_header(format!("line-{}", self.line_number));
+                if self.split_on.is_some() {
+                    record.push_sequence_part(sequence);
+                    return Ok(());
+                }
+                let sanitized = self.sanitize_line(sequence, record.header())?;
+                record.push_sequence_part(&sanitized);
+                return Ok(());
+            }
+        }
+    }
 }
 
 impl Iterator for Reader {
@@ -82,15 +396,268 @@ impl Iterator for Reader {
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut record = Record::new();
-        match self.read(&mut record) {
-            Ok(()) => {
-                if record.is_empty() {
-                    None
-                } else {
-                    Some(Ok(record))
+        loop {
+            match self.read(&mut record) {
+                Ok(()) => {
+                    if record.is_empty() {
+                        return None;
+                    }
+                    if record.sequence().len() < self.min_length {
+                        self.skipped += 1;
+                        continue;
+                    }
+                    return Some(Ok(record));
                 }
+                Err(e) => return Some(Err(e)),
             }
-            Err(e) => Some(Err(e)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    fn read_records(name: &str, contents: &str) -> Vec<(String, String)> {
+        let path = temp_path(name);
+        fs::write(&path, contents).unwrap();
+        let records: Vec<(String, String)> = Reader::from_file(&path)
+            .unwrap()
+            .map(|record| {
+                let record = record.unwrap();
+                (record.header, record.sequence)
+            })
+            .collect();
+        fs::remove_file(&path).unwrap();
+        records
+    }
+
+    #[test]
+    fn test_empty_and_blank_files_have_no_records() {
+        for contents in ["", "\n", "  \n\t\n\r\n"] {
+            assert!(read_records("reader_blank.fa", contents).is_empty());
+            let path = temp_path("reader_blank.txt");
+            fs::write(&path, contents).unwrap();
+            let reader = Reader::from_file(&path).unwrap().line_delimited(true);
+            assert_eq!(reader.count(), 0);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reference_without_bases_is_rejected() {
+        for (name, contents) in [
+            ("reference_empty.fa", ""),
+            ("reference_blank.fa", "\n \n"),
+            ("reference_headers.fa", ">chr1\n>chr2\n"),
+        ] {
+            let path = temp_path(name);
+            fs::write(&path, contents).unwrap();
+            assert!(
+                read_reference(&path, |reader| reader).is_err(),
+                "{contents:?}"
+            );
+            fs::remove_file(&path).unwrap();
+        }
+        let path = temp_path("reference_partial.fa");
+        fs::write(&path, ">chr1\n>chr2\nACGT\n").unwrap();
+        assert_eq!(read_reference(&path, |reader| reader).unwrap().len(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_wait_for_complete_file() {
+        let path = temp_path("reader_wait.fa");
+        let interval = Duration::from_millis(1);
+        fs::write(&path, "").unwrap();
+        let error = wait_for_complete_file(&path, 2, interval).unwrap_err();
+        assert!(error.to_string().contains("still empty"));
+        fs::write(&path, ">chr1\nACGT\n").unwrap();
+        assert_eq!(wait_for_complete_file(&path, 2, interval).unwrap(), 11);
+        fs::remove_file(&path).unwrap();
+        assert!(wait_for_complete_file(&path, 2, interval).is_err());
+    }
+
+    #[test]
+    fn test_single_record_without_trailing_newline() {
+        let records = read_records("assignment_1_test_single_no_newline.fa", ">one\nACGT\nTTGA");
+        assert_eq!(records, [(String::from("one"), String::from("ACGTTTGA"))]);
+    }
+
+    #[test]
+    fn test_multiple_records_without_trailing_newline() {
+        let expected = [
+            (String::from("one"), String::from("ACGT")),
+            (String::from("two"), String::from("TTGACC")),
+        ];
+        for contents in [
+            ">one\nACGT\n>two\nTTGA\nCC",
+            ">one\nACGT\n>two\nTTGA\nCC\n",
+            ">one\r\nACGT\r\n>two\r\nTTGA\r\nCC",
+            ">one\nACGT\n\n>two\nTTGA\nCC",
+            ">one\nACGT\n\n\n>two\nTTGA\nCC\n\n",
+        ] {
+            let records = read_records("assignment_1_test_multi_no_newline.fa", contents);
+            assert_eq!(records, expected, "{contents:?}");
+        }
+    }
+
+    #[test]
+    fn test_comment_and_blank_lines_are_skipped() {
+        let contents = "; a comment\n\n;another\n>one\nACGT\n; inside\nAC\n\n\n\n>two\n\nGG\n;\n";
+        assert_eq!(
+            read_records("reader_comments.fa", contents),
+            vec![
+                (String::from("one"), String::from("ACGTAC")),
+                (String::from("two"), String::from("GG")),
+            ]
+        );
+        assert!(read_records("reader_only_comments.fa", ";one\n\n;two\n").is_empty());
+    }
+
+    #[test]
+    fn test_min_length_skips_short_records() {
+        let path = temp_path("min_length.fa");
+        fs::write(&path, ">short\nAC\n>long\nACGT\nAC\n>exact\nACGT\n>empty\n").unwrap();
+        let mut reader = Reader::from_file(&path).unwrap().min_length(4);
+        let headers: Vec<String> = reader
+            .by_ref()
+            .map(|record| record.unwrap().header)
+            .collect();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(headers, ["long", "exact"]);
+        assert_eq!(reader.skipped(), 2);
+    }
+
+    #[test]
+    fn test_keep_ambiguous_marks_unknown_bases() {
+        let path = temp_path("keep_ambiguous.fa");
+        fs::write(&path, ">one\nacnnT\nRYGU\n").unwrap();
+        let record = Reader::from_file(&path)
+            .unwrap()
+            .keep_ambiguous(true)
+            .next()
+            .unwrap()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(record.sequence(), "ACNNTNNGN");
+    }
+
+    #[test]
+    fn test_strict_rejects_non_nucleotides() {
+        let path = temp_path("strict.fa");
+        fs::write(&path, ">dna\nACGTn\n>protein\nMKVLAAG\n").unwrap();
+        let mut reader = Reader::from_file(&path).unwrap().strict(true);
+        assert_eq!(reader.next().unwrap().unwrap().header(), "dna");
+        let error = reader.next().unwrap().unwrap_err().to_string();
+        assert!(error.contains("protein"), "{error}");
+        assert!(error.contains("'M' at line 4, column 1"), "{error}");
+
+        fs::write(&path, "ACGU\n").unwrap();
+        let reader = || Reader::from_file(&path).unwrap().line_delimited(true);
+        assert!(reader().strict(true).next().unwrap().is_err());
+        assert!(reader().strict(true).rna(true).next().unwrap().is_ok());
+        assert!(reader().next().unwrap().is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reading_resumes_after_a_malformed_record() {
+        let path = temp_path("resume.fa");
+        fs::write(
+            &path,
+            "stray\nlines\n>one\nACGT\n>bad\nMKV\nLL\n\n>two\nGG\n>worse\nAC\nEQ",
+        )
+        .unwrap();
+        let results: Vec<eyre::Result<Record>> =
+            Reader::from_file(&path).unwrap().strict(true).collect();
+        fs::remove_file(&path).unwrap();
+        let headers: Vec<Option<String>> = results
+            .iter()
+            .map(|record| record.as_ref().ok().map(|record| record.header.clone()))
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                None,
+                Some(String::from("one")),
+                None,
+                Some(String::from("two")),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_soft_masked_keeps_lowercase_bases() {
+        let path = temp_path("soft_masked.fa");
+        fs::write(&path, ">one\nACgt\nnNu\n").unwrap();
+        let records = read_reference(&path, |reader| reader.soft_masked(true)).unwrap();
+        let rna = Reader::from_file(&path)
+            .unwrap()
+            .soft_masked(true)
+            .rna(true)
+            .next()
+            .unwrap()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        let sequence = records[0].sequence();
+        assert_eq!(&sequence[..4], "ACgt");
+        assert!(sequence[4..5].bytes().all(|x| b"acgt".contains(&x)));
+        assert!(sequence[5..6].bytes().all(|x| b"ACGT".contains(&x)));
+        assert!(sequence[6..7].bytes().all(|x| b"acgt".contains(&x)));
+        assert!(rna.sequence().ends_with('t'));
+    }
+
+    #[test]
+    fn test_split_on_reads_every_part_as_a_record() {
+        let path = temp_path("split_on.fa");
+        fs::write(
+            &path,
+            ">q1 two parts\nACGT,tt\nGA\n>q2\nCCA\n>q3\nAC,,GG,\n",
+        )
+        .unwrap();
+        let records: Vec<Record> = Reader::from_file(&path)
+            .unwrap()
+            .split_on(Some(','))
+            .min_length(2)
+            .collect::<eyre::Result<_>>()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        let records: Vec<(&str, &str)> = records
+            .iter()
+            .map(|record| (record.header(), record.sequence()))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                ("q1/1 two parts", "ACGT"),
+                ("q1/2 two parts", "TTGA"),
+                ("q2/1", "CCA"),
+                ("q3/1", "AC"),
+                ("q3/3", "GG")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_delimited_names_records_by_line() {
+        let path = temp_path("line_delimited.txt");
+        fs::write(&path, "acgt\n\n  TTGA \nCC").unwrap();
+        let records: Vec<Record> = Reader::from_file(&path)
+            .unwrap()
+            .line_delimited(true)
+            .collect::<eyre::Result<_>>()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        let records: Vec<(&str, &str)> = records
+            .iter()
+            .map(|record| (record.header(), record.sequence()))
+            .collect();
+        assert_eq!(
+            records,
+            [("line-1", "ACGT"), ("line-3", "TTGA"), ("line-4", "CC")]
+        );
+    }
+}
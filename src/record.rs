@@ -16,6 +16,11 @@ impl Record {
         self.header.as_ref()
     }
 
+    /// The first whitespace delimited word of the header, used to identify the record
+    pub fn name(&self) -> &str {
+        self.header.split_whitespace().next().unwrap_or_default()
+    }
+
     pub fn sequence(&self) -> &str {
         self.sequence.as_ref()
     }
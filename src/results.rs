@@ -0,0 +1,146 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use eyre::{eyre, Result, WrapErr};
+
+/// The positions (or `start-end` intervals) reported for each query header
+pub type Results = BTreeMap<String, BTreeSet<String>>;
+
+/// Splits a querysa output line `header, count, positions...` into the header and
+/// its positions
+///
+/// Headers may contain `, ` themselves, the header ends at the first field that is
+/// followed by exactly as many positions as it counts. Lines written with
+/// `--delimiter tab` are split on tabs instead.
+///
+/// ```
+/// # use assignment_1::results::parse_result_line;
+/// let (header, positions) = parse_result_line("query-1, 2, 40, 7").unwrap();
+/// assert_eq!(header, "query-1");
+/// assert_eq!(positions, ["40", "7"]);
+/// let (header, positions) = parse_result_line("chr1, left arm, 1, 3-9").unwrap();
+/// assert_eq!((header, positions), ("chr1, left arm", vec!["3-9"]));
+/// assert!(parse_result_line("query-1, 3, 40").is_err());
+/// assert_eq!(parse_result_line("query-1\t1\t40").unwrap(), ("query-1", vec!["40"]));
+/// ```
+pub fn parse_result_line(line: &str) -> Result<(&str, Vec<&str>)> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let sep = if line.contains('\t') { "\t" } else { ", " };
+    let fields: Vec<&str> = line.split(sep).collect();
+    let header_end = (1..fields.len())
+        .find(|&idx| fields[idx].parse::<usize>() == Ok(fields.len() - idx - 1))
+        .ok_or_else(|| eyre!("{line:?} is not a querysa result line"))?;
+    let header = &line[..fields[..header_end].join(sep).len()];
+    Ok((header, fields[header_end + 1..].to_vec()))
+}
+
+/// Reads every line of a querysa output file into the set of positions per header
+pub fn read_results<P>(path: P) -> Result<Results>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let reader =
+        BufReader::new(File::open(path).wrap_err(format!("Could not open results {path:?}"))?);
+    let mut results = Results::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (header, positions) =
+            parse_result_line(&line).wrap_err(format!("line {} of {path:?}", number + 1))?;
+        let positions = positions.into_iter().map(String::from).collect();
+        if results.insert(header.to_string(), positions).is_some() {
+            return Err(eyre!("The query {header} is reported twice in {path:?}"));
+        }
+    }
+    Ok(results)
+}
+
+/// How two result files differ, see [`diff_results`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ResultsDiff {
+    /// Queries only reported in the first file
+    pub only_first: Vec<String>,
+    /// Queries only reported in the second file
+    pub only_second: Vec<String>,
+    /// Queries reported in both files with different sets of positions
+    pub differing: Vec<String>,
+    /// Queries reported in both files with the same positions
+    pub matching: usize,
+}
+
+impl ResultsDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_first.is_empty() && self.only_second.is_empty() && self.differing.is_empty()
+    }
+}
+
+/// Compares the positions reported for each query, ignoring their order
+///
+/// ```
+/// # use assignment_1::results::{diff_results, Results};
+/// let results = |lines: &[(&str, &[&str])]| -> Results {
+///     lines
+///         .iter()
+///         .map(|(header, positions)| {
+///             (header.to_string(), positions.iter().map(|x| x.to_string()).collect())
+///         })
+///         .collect()
+/// };
+/// let first = results(&[("a", &["1", "5"]), ("b", &["2"]), ("c", &[])]);
+/// let second = results(&[("a", &["5", "1"]), ("b", &["3"]), ("d", &[])]);
+/// let diff = diff_results(&first, &second);
+/// assert_eq!(diff.matching, 1);
+/// assert_eq!(diff.differing, ["b"]);
+/// assert_eq!(diff.only_first, ["c"]);
+/// assert_eq!(diff.only_second, ["d"]);
+/// ```
+pub fn diff_results(first: &Results, second: &Results) -> ResultsDiff {
+    let mut diff = ResultsDiff::default();
+    for (header, positions) in first {
+        match second.get(header) {
+            None => diff.only_first.push(header.clone()),
+            Some(other) if other == positions => diff.matching += 1,
+            Some(_) => diff.differing.push(header.clone()),
+        }
+    }
+    diff.only_second = second
+        .keys()
+        .filter(|header| !first.contains_key(*header))
+        .cloned()
+        .collect();
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    #[test]
+    fn read_results_ignores_position_order() {
+        let (first, second) = (temp_path("results_a.txt"), temp_path("results_b.txt"));
+        std::fs::write(&first, "q1, 2, 10, 3\nq2, 0\n\nq3, 1, 4\n").unwrap();
+        std::fs::write(&second, "q2, 0\nq1, 2, 3, 10\nq3, 1, 5\n").unwrap();
+        let diff = diff_results(
+            &read_results(&first).unwrap(),
+            &read_results(&second).unwrap(),
+        );
+        assert_eq!(diff.matching, 2);
+        assert_eq!(diff.differing, ["q3"]);
+        assert!(diff.only_first.is_empty() && diff.only_second.is_empty());
+
+        std::fs::write(&second, "q1, 0\nq1, 0\n").unwrap();
+        assert!(read_results(&second).is_err());
+        std::fs::write(&second, "q1, 2, 10\n").unwrap();
+        assert!(read_results(&second).is_err());
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+    }
+}
@@ -1,4 +1,9 @@
-use std::cmp::{min, Ordering};
+use std::{
+    cmp::{min, Ordering},
+    ops::Range,
+};
+
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Comparison {
@@ -12,7 +17,62 @@ pub struct Bound {
     pub comparison: Comparison,
 }
 
-pub type Span = (u32, u32);
+/// A half-open range `start..end` of suffix array indices (e.g. the suffixes
+/// starting with a query) or of sequence positions (e.g. a record)
+///
+/// ```rust
+/// # use assignment_1::search::Span;
+/// let suffix_array = [6, 2, 0, 3, 1, 4, 5];
+/// let span = Span::new(2, 4);
+/// assert_eq!(span.len(), 2);
+/// assert_eq!(&suffix_array[span.range()], &[0, 3]);
+/// assert_eq!(span.iter_positions(&suffix_array).collect::<Vec<u32>>(), vec![0, 3]);
+/// assert!(Span::new(3, 3).is_empty());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Span {
+    pub fn new(start: u32, end: u32) -> Self {
+        Self { start, end }
+    }
+
+    /// The number of suffix array indices in the span
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    /// The span as a range for slicing the suffix array
+    pub fn range(&self) -> Range<usize> {
+        self.start as usize..self.end as usize
+    }
+
+    /// The sequence positions of the suffixes in the span, in suffix array order
+    pub fn iter_positions<'a>(&self, suffix_array: &'a [u32]) -> impl Iterator<Item = u32> + 'a {
+        suffix_array[self.range()].iter().copied()
+    }
+}
+
+/// A reference sequence the searches can read one byte at a time
+///
+/// Implemented for plain byte slices and for storage that decodes bytes on the fly
+pub trait Sequence {
+    fn byte_at(&self, index: usize) -> u8;
+}
+
+impl Sequence for [u8] {
+    #[inline]
+    fn byte_at(&self, index: usize) -> u8 {
+        self[index]
+    }
+}
 
 pub enum QueryMode {
     Naive,
@@ -35,9 +95,37 @@ pub enum QueryMode {
 /// /// The sequence and the prefix share the first 3 bytes
 /// assert_eq!(result.lcp, 3);
 /// ```
-pub fn compare_bytes(sequence_bytes: &[u8], prefix_bytes: &[u8], offset: usize) -> Comparison {
+///
+/// Any [`Sequence`] can be compared, e.g. a 2-bit [`PackedSequence`](crate::encoding::PackedSequence)
+/// gives the same result without decoding it into a byte slice first
+///
+/// ```rust
+/// # use assignment_1::{encoding::PackedSequence, search::compare_bytes};
+/// let packed = PackedSequence::pack("CTGGAAC").unwrap();
+/// let result = compare_bytes(&packed, "CTGA".as_bytes(), 0);
+/// assert_eq!(result.lcp, 3);
+/// ```
+pub fn compare_bytes<S>(sequence_bytes: &S, prefix_bytes: &[u8], offset: usize) -> Comparison
+where
+    S: Sequence + ?Sized,
+{
+    compare_suffix(sequence_bytes, 0, prefix_bytes, offset)
+}
+
+/// Compares the suffix of the reference sequence starting at `suffix` to the target prefix
+///
+/// Same as [`compare_bytes`] but reads the sequence through the [`Sequence`] trait
+pub fn compare_suffix<S>(
+    sequence_bytes: &S,
+    suffix: usize,
+    prefix_bytes: &[u8],
+    offset: usize,
+) -> Comparison
+where
+    S: Sequence + ?Sized,
+{
     for (idx, byte) in prefix_bytes[offset..].iter().enumerate() {
-        let ordering = sequence_bytes[idx + offset].cmp(byte);
+        let ordering = sequence_bytes.byte_at(suffix + idx + offset).cmp(byte);
         if ordering != Ordering::Equal {
             return Comparison {
                 ordering,
@@ -51,8 +139,8 @@ pub fn compare_bytes(sequence_bytes: &[u8], prefix_bytes: &[u8], offset: usize)
     }
 }
 
-pub fn naive_bisect_by<F>(
-    sequence_bytes: &[u8],
+pub fn naive_bisect_by<S, F>(
+    sequence_bytes: &S,
     prefix_bytes: &[u8],
     suffix_array: &[u32],
     span: &Span,
@@ -60,15 +148,17 @@ pub fn naive_bisect_by<F>(
     // ) -> usize
 ) -> u32
 where
+    S: Sequence + ?Sized,
     F: FnMut(&Ordering) -> bool,
 {
-    let (mut left, mut right) = span;
+    let (mut left, mut right) = (span.start, span.end);
     while left < right {
         //let center: usize = (left + right) / 2;
         let center: u32 = (left + right) / 2;
-        let comparison: Comparison = compare_bytes(
+        let comparison: Comparison = compare_suffix(
             //&sequence_bytes[suffix_array[center] as usize..],
-            &sequence_bytes[suffix_array[center as usize] as usize..],
+            sequence_bytes,
+            suffix_array[center as usize] as usize,
             prefix_bytes,
             0,
         );
@@ -81,24 +171,58 @@ where
     left
 }
 
-pub fn simple_accelerant_bisect_by<F>(
-    sequence_bytes: &[u8],
+pub fn simple_accelerant_bisect_by<S, F>(
+    sequence_bytes: &S,
+    prefix_bytes: &[u8],
+    suffix_array: &[u32],
+    left: &mut Bound,
+    right: &mut Bound,
+    f: F,
+) where
+    S: Sequence + ?Sized,
+    F: FnMut(&Ordering) -> bool,
+{
+    simple_accelerant_bisect_visit(
+        sequence_bytes,
+        prefix_bytes,
+        suffix_array,
+        left,
+        right,
+        f,
+        &mut |_, _| {},
+    )
+}
+
+/// The simple accelerant bisection, calling `visit` with the min lcp and the new bound after each comparison
+fn simple_accelerant_bisect_visit<S, F, V>(
+    sequence_bytes: &S,
     prefix_bytes: &[u8],
     suffix_array: &[u32],
     left: &mut Bound,
     right: &mut Bound,
     mut f: F,
+    visit: &mut V,
 ) where
+    S: Sequence + ?Sized,
     F: FnMut(&Ordering) -> bool,
+    V: FnMut(usize, &Bound),
 {
     while left.index < right.index {
         let center = (left.index + right.index) / 2;
         let min_lcp = min(left.comparison.lcp, right.comparison.lcp);
-        let comparison = compare_bytes(
-            &sequence_bytes[suffix_array[center] as usize..],
+        let comparison = compare_suffix(
+            sequence_bytes,
+            suffix_array[center] as usize,
             prefix_bytes,
             min_lcp,
         );
+        visit(
+            min_lcp,
+            &Bound {
+                index: center,
+                comparison,
+            },
+        );
         if f(&comparison.ordering) {
             left.index = center + 1;
             left.comparison = comparison;
@@ -109,18 +233,94 @@ pub fn simple_accelerant_bisect_by<F>(
     }
 }
 
-pub fn simple_accelerant_search(
-    sequence_bytes: &[u8],
+pub fn simple_accelerant_search<S>(
+    sequence_bytes: &S,
+    prefix_bytes: &[u8],
+    suffix_array: &[u32],
+    span: &Span,
+) -> Option<Span>
+where
+    S: Sequence + ?Sized,
+{
+    simple_accelerant_search_visit(
+        sequence_bytes,
+        prefix_bytes,
+        suffix_array,
+        span,
+        &mut |_, _| {},
+    )
+}
+
+/// One comparison made while bisecting in [`simple_accelerant_search_traced`]
+#[cfg(feature = "trace")]
+#[derive(Debug, Clone, Copy)]
+pub struct TraceStep {
+    /// The number of leading bytes skipped because both bounds already share them with the prefix
+    pub min_lcp: usize,
+    /// The suffix array index compared against and the result of the comparison
+    pub bound: Bound,
+}
+
+/// Runs [`simple_accelerant_search`] and records every comparison made while bisecting
+///
+/// Intended for visualizing how the min lcp grows and how many bytes are skipped
+/// compared to the naive search, which always starts comparing at byte 0.
+///
+/// ```rust
+/// # use assignment_1::search::{simple_accelerant_search_traced, Span};
+/// let sequence = "ACAACG$";
+/// let suffix_array = [6, 2, 0, 3, 1, 4, 5];
+/// let (result, steps) = simple_accelerant_search_traced(
+///     sequence.as_bytes(),
+///     "AC".as_bytes(),
+///     &suffix_array,
+///     &Span::new(0, 7),
+/// );
+/// assert_eq!(result, Some(Span::new(2, 4)));
+/// assert!(steps.iter().any(|step| step.min_lcp > 0));
+/// ```
+#[cfg(feature = "trace")]
+pub fn simple_accelerant_search_traced<S>(
+    sequence_bytes: &S,
     prefix_bytes: &[u8],
     suffix_array: &[u32],
     span: &Span,
-) -> Option<Span> {
+) -> (Option<Span>, Vec<TraceStep>)
+where
+    S: Sequence + ?Sized,
+{
+    let mut steps: Vec<TraceStep> = Vec::new();
+    let result = simple_accelerant_search_visit(
+        sequence_bytes,
+        prefix_bytes,
+        suffix_array,
+        span,
+        &mut |min_lcp, bound| {
+            steps.push(TraceStep {
+                min_lcp,
+                bound: *bound,
+            })
+        },
+    );
+    (result, steps)
+}
+
+fn simple_accelerant_search_visit<S, V>(
+    sequence_bytes: &S,
+    prefix_bytes: &[u8],
+    suffix_array: &[u32],
+    span: &Span,
+    visit: &mut V,
+) -> Option<Span>
+where
+    S: Sequence + ?Sized,
+    V: FnMut(usize, &Bound),
+{
     let mut left_bound = Bound {
-        index: span.0 as usize,
-        // index: span.0,
-        comparison: compare_bytes(
-            &sequence_bytes[suffix_array[span.0 as usize] as usize..],
-            // &sequence_bytes[suffix_array[span.0] as usize..],
+        index: span.start as usize,
+        comparison: compare_suffix(
+            sequence_bytes,
+            suffix_array[span.start as usize] as usize,
             prefix_bytes,
             0,
         ),
@@ -129,23 +329,23 @@ pub fn simple_accelerant_search(
         return None;
     }
     let mut right_bound = Bound {
-        index: span.1 as usize,
-        // index: span.1,
-        comparison: compare_bytes(
-            // &sequence_bytes[suffix_array[span.1 - 1] as usize..],
-            &sequence_bytes[suffix_array[span.1 as usize - 1] as usize..],
+        index: span.end as usize,
+        comparison: compare_suffix(
+            sequence_bytes,
+            suffix_array[span.end as usize - 1] as usize,
             prefix_bytes,
             0,
         ),
     };
     let right_bound_copy = right_bound;
-    simple_accelerant_bisect_by(
+    simple_accelerant_bisect_visit(
         sequence_bytes,
         prefix_bytes,
         suffix_array,
         &mut left_bound,
         &mut right_bound,
         |&x| x == Ordering::Less,
+        visit,
     );
     if left_bound.comparison.ordering != Ordering::Equal
         && right_bound.comparison.ordering != Ordering::Equal
@@ -153,29 +353,31 @@ pub fn simple_accelerant_search(
         return None;
     }
     let left = left_bound.index as u32;
-    // let left = left_bound.index;
     right_bound = right_bound_copy;
-    simple_accelerant_bisect_by(
+    simple_accelerant_bisect_visit(
         sequence_bytes,
         prefix_bytes,
         suffix_array,
         &mut left_bound,
         &mut right_bound,
         |&x| x != Ordering::Greater,
+        visit,
     );
-    Some((left, left_bound.index as u32))
-    // Some((left, left_bound.index))
+    Some(Span::new(left, left_bound.index as u32))
 }
 
-pub fn naive_search(
-    sequence_bytes: &[u8],
+pub fn naive_search<S>(
+    sequence_bytes: &S,
     prefix_bytes: &[u8],
     suffix_array: &[u32],
     span: &Span,
-) -> Option<Span> {
-    if compare_bytes(
-        // &sequence_bytes[suffix_array[span.0] as usize..],
-        &sequence_bytes[suffix_array[span.0 as usize] as usize..],
+) -> Option<Span>
+where
+    S: Sequence + ?Sized,
+{
+    if compare_suffix(
+        sequence_bytes,
+        suffix_array[span.start as usize] as usize,
         prefix_bytes,
         0,
     )
@@ -184,9 +386,9 @@ pub fn naive_search(
     {
         return None;
     }
-    if compare_bytes(
-        // &sequence_bytes[suffix_array[span.1 - 1] as usize..],
-        &sequence_bytes[suffix_array[span.1 as usize - 1] as usize..],
+    if compare_suffix(
+        sequence_bytes,
+        suffix_array[span.end as usize - 1] as usize,
         prefix_bytes,
         0,
     )
@@ -198,9 +400,9 @@ pub fn naive_search(
     let left = naive_bisect_by(sequence_bytes, prefix_bytes, suffix_array, span, |&x| {
         x == Ordering::Less
     });
-    if compare_bytes(
-        // &sequence_bytes[suffix_array[left] as usize..],
-        &sequence_bytes[suffix_array[left as usize] as usize..],
+    if compare_suffix(
+        sequence_bytes,
+        suffix_array[left as usize] as usize,
         prefix_bytes,
         0,
     )
@@ -213,17 +415,16 @@ pub fn naive_search(
         sequence_bytes,
         prefix_bytes,
         suffix_array,
-        // &(left, span.1),
-        &(left, span.1),
+        &Span::new(left, span.end),
         |&x| x != Ordering::Greater,
     );
-    Some((left, right))
-    // Some((left as u32, right as u32))
+    Some(Span::new(left, right))
 }
 
 #[cfg(test)]
 mod search_tests {
     use super::*;
+    use crate::encoding::PackedSequence;
     use rand::{distributions::Slice, rngs::StdRng, Rng, SeedableRng};
     use suffix::SuffixTable;
 
@@ -246,13 +447,14 @@ mod search_tests {
             .collect()
     }
 
+    #[allow(clippy::mut_range_bound)]
     fn very_naive_search(
         sequence_bytes: &[u8],
         prefix_bytes: &[u8],
         suffix_array: &[u32],
         span: &Span,
     ) -> Option<Span> {
-        let (mut start, end) = *span;
+        let Span { mut start, end } = *span;
         let mut found: bool = false;
         for idx in start..end {
             // let elem = suffix_array[idx];
@@ -265,12 +467,12 @@ mod search_tests {
                     start = idx;
                     found = true;
                 }
-                (false, true) => return Some((start, idx)),
+                (false, true) => return Some(Span::new(start, idx)),
                 _ => {}
             }
         }
         if found {
-            Some((start, end))
+            Some(Span::new(start, end))
         } else {
             None
         }
@@ -317,7 +519,7 @@ mod search_tests {
             prefix_bytes,
             &suffix_array,
             // &(0, suffix_array.len()),
-            &(0, suffix_array.len() as u32),
+            &Span::new(0, suffix_array.len() as u32),
         );
         assert_eq!(result, None);
     }
@@ -332,7 +534,7 @@ mod search_tests {
             prefix_bytes,
             &suffix_array,
             // &(0, suffix_array.len()),
-            &(0, suffix_array.len() as u32),
+            &Span::new(0, suffix_array.len() as u32),
         );
         assert_eq!(result, None);
     }
@@ -347,9 +549,120 @@ mod search_tests {
             prefix_bytes,
             &suffix_array,
             // &(0, suffix_array.len()),
-            &(0, suffix_array.len() as u32),
+            &Span::new(0, suffix_array.len() as u32),
         );
-        assert_eq!(result.unwrap(), (9, 11));
+        assert_eq!(result.unwrap(), Span::new(9, 11));
+    }
+
+    #[test]
+    fn homopolymer_reference_returns_all_qualifying_suffixes() {
+        for length in 1..20 {
+            let sequence = "A".repeat(length) + "$";
+            let suffix_array = get_suffix_array(&sequence);
+            let span = Span::new(0, suffix_array.len() as u32);
+            for query_length in 1..length + 2 {
+                let prefix = "A".repeat(query_length);
+                // the suffixes of length >= query_length are sorted last
+                let expected = if query_length <= length {
+                    Some(Span::new(query_length as u32, suffix_array.len() as u32))
+                } else {
+                    None
+                };
+                let baseline =
+                    very_naive_search(sequence.as_bytes(), prefix.as_bytes(), &suffix_array, &span);
+                assert_eq!(baseline, expected);
+                let naive_result =
+                    naive_search(sequence.as_bytes(), prefix.as_bytes(), &suffix_array, &span);
+                assert_eq!(naive_result, expected, "naive {prefix} in {sequence}");
+                let simpaccel_result = simple_accelerant_search(
+                    sequence.as_bytes(),
+                    prefix.as_bytes(),
+                    &suffix_array,
+                    &span,
+                );
+                assert_eq!(
+                    simpaccel_result, expected,
+                    "simpaccel {prefix} in {sequence}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn homopolymer_reference_rejects_other_nucleotides() {
+        let sequence = "AAAAAA$".to_string();
+        let suffix_array = get_suffix_array(&sequence);
+        let span = Span::new(0, suffix_array.len() as u32);
+        for prefix in ["C", "AAC", "T", "AAAAAC"] {
+            assert_eq!(
+                naive_search(sequence.as_bytes(), prefix.as_bytes(), &suffix_array, &span),
+                None
+            );
+            assert_eq!(
+                simple_accelerant_search(
+                    sequence.as_bytes(),
+                    prefix.as_bytes(),
+                    &suffix_array,
+                    &span
+                ),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn queries_next_to_the_sentinel_exclude_it() {
+        // the `$` suffixes sort first, so the left bound of the bisection starts on them
+        for sequence in ["CCGTA$", "ACCC$", "TTTTT$", "GA$CA$", "A$A$", "CGTACA$AC$"] {
+            let suffix_array = get_suffix_array(sequence);
+            let span = Span::new(0, suffix_array.len() as u32);
+            for prefix in ["A", "AA", "AC", "CA", "C", "T"] {
+                let expected =
+                    very_naive_search(sequence.as_bytes(), prefix.as_bytes(), &suffix_array, &span);
+                for result in [
+                    naive_search(sequence.as_bytes(), prefix.as_bytes(), &suffix_array, &span),
+                    simple_accelerant_search(
+                        sequence.as_bytes(),
+                        prefix.as_bytes(),
+                        &suffix_array,
+                        &span,
+                    ),
+                ] {
+                    assert_eq!(result, expected, "{prefix} in {sequence}");
+                    if let Some(result) = result {
+                        assert!(result
+                            .iter_positions(&suffix_array)
+                            .all(|position| sequence.as_bytes()[position as usize] != b'$'));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packed_sequence_results_match_byte_slice() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let sequence = generate_sequence(20000);
+        let packed = PackedSequence::pack(&sequence).unwrap();
+        let sa = get_suffix_array(&sequence);
+        let span = Span::new(0, sa.len() as u32);
+        for _ in 0..100 {
+            let prefix = generate_query(rng.gen_range(1..12) as u32);
+            let suffix = rng.gen_range(0..sequence.len() - prefix.len());
+            let bytes = &sequence.as_bytes()[suffix..];
+            let expected = compare_bytes(bytes, prefix.as_bytes(), 0);
+            let comparison = compare_suffix(&packed, suffix, prefix.as_bytes(), 0);
+            assert_eq!(comparison.lcp, expected.lcp);
+            assert_eq!(comparison.ordering, expected.ordering);
+            assert_eq!(
+                naive_search(&packed, prefix.as_bytes(), &sa, &span),
+                naive_search(sequence.as_bytes(), prefix.as_bytes(), &sa, &span)
+            );
+            assert_eq!(
+                simple_accelerant_search(&packed, prefix.as_bytes(), &sa, &span),
+                simple_accelerant_search(sequence.as_bytes(), prefix.as_bytes(), &sa, &span)
+            );
+        }
     }
 
     #[test]
@@ -360,7 +673,7 @@ mod search_tests {
         for _ in 0..100 {
             let prefix = generate_query(rng.gen_range(1..10) as u32);
             // let span = (0_usize, sa.len());
-            let span = (0_u32, sa.len() as u32);
+            let span = Span::new(0, sa.len() as u32);
             let baseline = very_naive_search(sequence.as_bytes(), prefix.as_bytes(), &sa, &span);
             let naive_result = naive_search(sequence.as_bytes(), prefix.as_bytes(), &sa, &span);
             let simpaccel_result =
@@ -1,19 +1,704 @@
-use std::cmp::{min, Ordering};
+use std::{
+    borrow::Cow,
+    cmp::{min, Ordering},
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    ops::{ControlFlow, RangeInclusive},
+    path::Path,
+    time::{Duration, Instant},
+};
 
-use serde::{Deserialize, Serialize};
+use eyre::{eyre, Result, WrapErr};
+use serde::{ser::SerializeStruct, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use suffix::SuffixTable;
 
 use crate::{
-    prefix_table::PrefixTable,
+    bloom::BloomFilter,
+    encoding::{
+        decode_codes, reverse_complement, unpack_codes, Alphabet, BitVec, PackedSequence, SoftMask,
+    },
+    normalize::{iupac_matches, normalize_sequence, NormalizePolicy},
+    prefix_table::{AdaptivePrefixTable, PrefixTable},
     record::Record,
-    search::{naive_search, simple_accelerant_search, Span},
+    search::{
+        compare_suffix, naive_bisect_by, naive_search, simple_accelerant_search, Sequence, Span,
+    },
 };
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq)]
 pub struct SuffixArray {
-    pub sequence: String,
+    sequence: String,
     pub suffix_array: Vec<u32>,
     prefix_table: Option<PrefixTable>,
+    checksum: String,
+    contigs: Vec<Contig>,
+    packed: Option<PackedSequence>,
+    sentinel: SentinelOrder,
+    alphabet: Alphabet,
+    /// The prefix table only covers a panel of k-mers, other prefixes search the full span
+    targeted_prefix_table: bool,
+    /// The bases that were lowercase in the reference, see [`BuildOptions::soft_masked`]
+    mask: Option<SoftMask>,
+    /// Search the full suffix array even when a prefix table is stored (not serialized)
+    skip_prefix_table: bool,
+    /// Rules out queries whose first k-mer is not in the reference before searching
+    bloom: Option<BloomFilter>,
+    /// The second half of the records are the reverse complements of the first half, see
+    /// [`BuildOptions::both_strands`]
+    both_strands: bool,
+    /// A prefix table of varying prefix lengths, used instead of `prefix_table` when set
+    adaptive_table: Option<AdaptivePrefixTable>,
+}
+
+/// The version of the serialized layout, bumped whenever the layout changes
+pub const FORMAT_VERSION: u32 = 7;
+
+/// The version of the layout written by [`SuffixArray::write_array_only`], with the
+/// high bit set so it is never mistaken for a [`FORMAT_VERSION`]
+pub const ARRAY_ONLY_FORMAT_VERSION: u32 = 0x8000_0001;
+
+/// The version of the layout written by [`PrefixTableIndex::write`]
+pub const PREFIX_TABLE_ONLY_FORMAT_VERSION: u32 = 0x8000_0003;
+
+/// Reads the format version every layout starts with, to tell the kinds of index apart
+/// before deserializing the rest of the file
+///
+/// Returns `None` for an index written in the [`LegacySuffixArray`] layout, which has no
+/// version. That layout starts with the u64 length of the sequence, whose upper half is
+/// always zero, while the versioned layouts follow the u32 version with the non-zero
+/// length of the suffix array (or, for the layouts of a prefix table index, which have the
+/// high bit of the version set, with the variant of the prefix table).
+pub fn read_format_version<P>(path: P) -> Result<Option<u32>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let mut buf_reader =
+        BufReader::new(File::open(path).wrap_err(format!("Could not open index file {path:?}"))?);
+    let mut header = [0_u8; 8];
+    buf_reader
+        .read_exact(&mut header)
+        .wrap_err(format!("{path:?} is too short to be an index"))?;
+    let version = u32::from_le_bytes(header[..4].try_into().unwrap());
+    let legacy = header[4..] == [0; 4] && version & 0x8000_0000 == 0;
+    Ok((!legacy).then_some(version))
+}
+
+/// The layout written before the format version was introduced: the derived
+/// serialization of the sequence, the suffix array and the prefix table, in that order
+#[derive(Deserialize)]
+struct LegacySuffixArray {
+    sequence: String,
+    suffix_array: Vec<u32>,
+    prefix_table: Option<PrefixTable>,
+}
+
+/// Byte offset of the first suffix array entry in an index written with bincode's
+/// default (fixed int) encoding: the u32 format version followed by the u64 entry count
+pub const SUFFIX_ARRAY_OFFSET: usize = 12;
+
+/// Serializes the format version followed by the suffix array so the table sits
+/// at a fixed offset ([`SUFFIX_ARRAY_OFFSET`] with bincode) as little endian u32s
+/// and can be read (or mapped) without parsing the sequence first. The sequence,
+/// prefix table and the remaining metadata follow.
+impl Serialize for SuffixArray {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("SuffixArray", 14)?;
+        state.serialize_field("version", &FORMAT_VERSION)?;
+        state.serialize_field("suffix_array", &self.suffix_array)?;
+        state.serialize_field("sequence", &self.sequence)?;
+        state.serialize_field("prefix_table", &self.prefix_table)?;
+        state.serialize_field("checksum", &self.checksum)?;
+        state.serialize_field("contigs", &self.contigs)?;
+        state.serialize_field("packed", &self.packed)?;
+        state.serialize_field("sentinel", &self.sentinel)?;
+        state.serialize_field("alphabet", &self.alphabet)?;
+        state.serialize_field("targeted_prefix_table", &self.targeted_prefix_table)?;
+        state.serialize_field("mask", &self.mask)?;
+        state.serialize_field("bloom", &self.bloom)?;
+        state.serialize_field("both_strands", &self.both_strands)?;
+        state.serialize_field("adaptive_table", &self.adaptive_table)?;
+        state.end()
+    }
+}
+
+/// Mirrors the field order written by the [`Serialize`] impl of [`SuffixArray`]
+#[derive(Deserialize)]
+#[serde(rename = "SuffixArray")]
+struct SerializedSuffixArray {
+    version: u32,
+    suffix_array: Vec<u32>,
+    sequence: String,
+    prefix_table: Option<PrefixTable>,
+    checksum: String,
+    contigs: Vec<Contig>,
+    packed: Option<PackedSequence>,
+    sentinel: SentinelOrder,
+    alphabet: Alphabet,
+    targeted_prefix_table: bool,
+    mask: Option<SoftMask>,
+    bloom: Option<BloomFilter>,
+    both_strands: bool,
+    adaptive_table: Option<AdaptivePrefixTable>,
+}
+
+impl<'de> Deserialize<'de> for SuffixArray {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let serialized = SerializedSuffixArray::deserialize(deserializer)?;
+        if serialized.version == ARRAY_ONLY_FORMAT_VERSION {
+            return Err(serde::de::Error::custom(
+                "index only holds the suffix array, pass the reference it was built from with --reference",
+            ));
+        }
+        if serialized.version != FORMAT_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "index was written in format version {} but version {FORMAT_VERSION} is supported, rebuild it with buildsa",
+                serialized.version
+            )));
+        }
+        Ok(Self {
+            sequence: serialized.sequence,
+            suffix_array: serialized.suffix_array,
+            prefix_table: serialized.prefix_table,
+            checksum: serialized.checksum,
+            contigs: serialized.contigs,
+            packed: serialized.packed,
+            sentinel: serialized.sentinel,
+            alphabet: serialized.alphabet,
+            targeted_prefix_table: serialized.targeted_prefix_table,
+            mask: serialized.mask,
+            skip_prefix_table: false,
+            bloom: serialized.bloom,
+            both_strands: serialized.both_strands,
+            adaptive_table: serialized.adaptive_table,
+        })
+    }
+}
+
+/// The layout written by [`SuffixArray::write_array_only`]: the suffix array and the
+/// metadata needed to rebuild the index once the reference is read again. The version
+/// and the suffix array come first as in the full layout
+#[derive(Serialize, Deserialize)]
+struct ArrayOnlyIndex<'a> {
+    version: u32,
+    suffix_array: Cow<'a, [u32]>,
+    checksum: Cow<'a, str>,
+    sentinel: SentinelOrder,
+    alphabet: Alphabet,
+}
+
+/// An index holding only a prefix table and the sequence, without the suffix array, made
+/// with [`SuffixArray::into_prefix_table_index`]
+///
+/// The spans of the table still tell how many suffixes start with each k-mer, so it
+/// counts the occurrences of queries of exactly k bases but cannot locate them.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct PrefixTableIndex {
+    version: u32,
+    prefix_table: PrefixTable,
+    sequence: String,
+    checksum: String,
+    alphabet: Alphabet,
+    /// Count a query together with its reverse complement
+    canonical: bool,
+}
+
+impl PrefixTableIndex {
+    /// Count every query together with its reverse complement, the canonical k-mer
+    /// convention where a k-mer and its reverse complement are the same k-mer
+    ///
+    /// The table itself stays keyed by k-mer rather than by canonical k-mer: the two
+    /// orientations of a k-mer cover separate ranges of the suffix array, which one span
+    /// cannot hold. The spans of both orientations are summed when a query is counted, and
+    /// [`PrefixTable::canonical_counts`] sums them for every canonical k-mer at once.
+    pub fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    pub fn is_canonical(&self) -> bool {
+        self.canonical
+    }
+
+    /// The length of the queries the index can count
+    pub fn k(&self) -> u16 {
+        self.prefix_table.k()
+    }
+
+    pub fn prefix_table(&self) -> &PrefixTable {
+        &self.prefix_table
+    }
+
+    pub fn checksum(&self) -> &str {
+        &self.checksum
+    }
+
+    /// Same as [`SuffixArray::verify_reference`]
+    pub fn verify_reference(&self, expected: &str) -> Result<()> {
+        if !self.checksum.eq_ignore_ascii_case(expected.trim()) {
+            return Err(eyre!(
+                "index was built from a reference with checksum {} but {expected} was expected",
+                self.checksum
+            ));
+        }
+        Ok(())
+    }
+
+    /// The number of occurrences of a query of exactly [`PrefixTableIndex::k`] bases, on
+    /// both strands for a [`PrefixTableIndex::canonical`] index
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::SuffixArray;
+    /// let mut sa = SuffixArray::from_sequence("ACGTACGGACG");
+    /// sa.initialize_prefix_table(3);
+    /// let index = sa.into_prefix_table_index().unwrap();
+    /// assert_eq!(index.count("ACG").unwrap(), 3);
+    /// assert_eq!(index.count("ttt").unwrap(), 0);
+    /// assert!(index.count("ACGT").is_err());
+    /// let index = index.canonical(true);
+    /// assert_eq!(index.count("CGT").unwrap(), 4);
+    /// assert_eq!(index.count("ACG").unwrap(), 4);
+    /// ```
+    pub fn count(&self, query: &str) -> Result<usize> {
+        let query = normalize_query(self.alphabet, query);
+        let k = self.k() as usize;
+        if query.len() != k {
+            return Err(eyre!(
+                "the index only holds a prefix table for k = {k} and cannot count a query of length {}",
+                query.len()
+            ));
+        }
+        let count = |kmer: &str| self.prefix_table.get(kmer).map_or(0, |span| span.len());
+        let reverse = reverse_complement(&query);
+        if self.canonical && reverse != query {
+            return Ok(count(&query) + count(&reverse));
+        }
+        Ok(count(&query))
+    }
+
+    /// Writes the index with bincode, load it with [`PrefixTableIndex::from_file`]
+    pub fn write<W>(&self, writer: W) -> Result<()>
+    where
+        W: Write,
+    {
+        bincode::serialize_into(writer, self).wrap_err("Failed to serialize prefix table")
+    }
+
+    /// Loads an index written by [`PrefixTableIndex::write`] and verifies its checksum
+    pub fn from_file<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if read_format_version(path)? != Some(PREFIX_TABLE_ONLY_FORMAT_VERSION) {
+            return Err(eyre!(
+                "{path:?} is not an index written by buildsa --preftab-only in format version {PREFIX_TABLE_ONLY_FORMAT_VERSION}, rebuild it"
+            ));
+        }
+        let buf_reader = BufReader::new(
+            File::open(path).wrap_err(format!("Could not open index file {path:?}"))?,
+        );
+        let index: Self =
+            bincode::deserialize_from(buf_reader).wrap_err("Failed to deserialize prefix table")?;
+        let actual = checksum_sequence(&index.sequence);
+        if actual != index.checksum {
+            return Err(eyre!(
+                "index is corrupted: recorded checksum {} but the sequence hashes to {actual}",
+                index.checksum
+            ));
+        }
+        Ok(index)
+    }
+}
+
+/// A machine-readable description of an index, see [`SuffixArray::info`]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexInfo {
+    /// The header of the first record
+    pub header: Option<String>,
+    pub records: usize,
+    /// The length of the sequence including the `$` sentinel
+    pub sequence_length: usize,
+    pub num_suffixes: usize,
+    pub sentinel: SentinelOrder,
+    pub has_prefix_table: bool,
+    pub prefix_table_k: Option<u16>,
+    /// `"sparse"`, `"dense"` (see [`PrefixTable::variant`]) or `"adaptive"`
+    pub prefix_table_variant: Option<&'static str>,
+    /// The version written in the index file, `None` for indices not read from bincode or
+    /// written before the layout was versioned
+    pub index_format_version: Option<u32>,
+}
+
+/// The strand of the reference an occurrence lies on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strand {
+    Forward,
+    Reverse,
+}
+
+/// Where the `$` sentinel sorts relative to the nucleotides when building the suffix array
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SentinelOrder {
+    /// `$` sorts before A (the default, the sentinel suffix is the first entry)
+    #[default]
+    Smallest,
+    /// `$` sorts after T (the sentinel suffix is the last entry)
+    Largest,
+}
+
+/// A region of the reference where several queries occur close together, see
+/// [`SuffixArray::co_occurrences`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoOccurrence {
+    /// The start of the first occurrence in the region
+    pub start: u32,
+    /// The end of the last occurring query in the region
+    pub end: u32,
+    /// The indices of the distinct queries occurring in the region, in increasing order
+    pub queries: Vec<usize>,
+}
+
+/// Time spent in each phase of a batch of searches, see [`SuffixArray::search_timed`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SearchTimings {
+    /// Narrowing the span with the prefix table (hashing the k-mer for sparse tables)
+    pub lookup: Duration,
+    /// Bisecting the narrowed span
+    pub bisection: Duration,
+}
+
+/// How much the prefix table narrowed the starting spans of a batch of queries,
+/// see [`SuffixArray::start_span`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StartSpanStats {
+    pub queries: usize,
+    /// Queries whose starting span is smaller than the full suffix array
+    pub narrowed: usize,
+    /// Queries the prefix table showed cannot occur, so no bisection was needed
+    pub ruled_out: usize,
+    /// Sum of the widths of the starting spans that were bisected
+    pub total_width: u64,
+}
+
+impl StartSpanStats {
+    /// Adds the starting span of a query in an index of `suffixes` suffixes
+    pub fn add(&mut self, start_span: Option<Span>, suffixes: usize) {
+        self.queries += 1;
+        match start_span {
+            Some(span) => {
+                self.narrowed += usize::from(span.len() < suffixes);
+                self.total_width += span.len() as u64;
+            }
+            None => {
+                self.narrowed += 1;
+                self.ruled_out += 1;
+            }
+        }
+    }
+
+    /// The mean width of the starting spans that were bisected
+    pub fn mean_width(&self) -> f64 {
+        let bisected = self.queries - self.ruled_out;
+        if bisected == 0 {
+            0.0
+        } else {
+            self.total_width as f64 / bisected as f64
+        }
+    }
+}
+
+/// Answers longest common extension queries in constant time with a range minimum
+/// structure over the LCP array, see [`SuffixArray::lce_index`]
+#[derive(Debug, Clone)]
+pub struct LceIndex {
+    /// The index of each suffix in the suffix array
+    rank: Vec<u32>,
+    /// `minima[level][x]` is the minimum of `lcp[x..x + 2^level]`
+    minima: Vec<Vec<u32>>,
+    /// The positions of the `$` sentinels in increasing order
+    sentinels: Vec<u32>,
+}
+
+impl LceIndex {
+    /// The length of the common prefix of the suffixes starting at `i` and `j`, same as
+    /// [`SuffixArray::lce`]
+    ///
+    /// # Panics
+    /// If `i` or `j` is not a position of the sequence
+    pub fn lce(&self, i: u32, j: u32) -> usize {
+        if i == j {
+            // the suffix extends up to the next sentinel
+            let end = self.sentinels[self.sentinels.partition_point(|&x| x < i)];
+            return (end - i) as usize;
+        }
+        let (ri, rj) = (
+            self.rank[i as usize] as usize,
+            self.rank[j as usize] as usize,
+        );
+        let (lo, hi) = (ri.min(rj) + 1, ri.max(rj) + 1);
+        let level = (usize::BITS - 1 - (hi - lo).leading_zeros()) as usize;
+        let minima = &self.minima[level];
+        minima[lo].min(minima[hi - (1 << level)]) as usize
+    }
+}
+
+/// Running statistics of the number of occurrences per query, kept in constant memory
+///
+/// The median is estimated by moving it one step towards every new count (the
+/// "frugal" streaming median), which converges to the median of the counts for long
+/// batches without storing them.
+///
+/// ```rust
+/// # use assignment_1::suffix_array::OccurrenceStats;
+/// let mut stats = OccurrenceStats::default();
+/// for count in [0, 4, 4, 9, 4, 4, 4, 4] {
+///     stats.add(count);
+/// }
+/// assert_eq!((stats.queries, stats.matched, stats.min, stats.max), (8, 7, 0, 9));
+/// assert_eq!(stats.mean(), 33.0 / 8.0);
+/// assert_eq!(stats.median(), 4);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OccurrenceStats {
+    pub queries: usize,
+    /// Queries with at least one occurrence
+    pub matched: usize,
+    pub total: u64,
+    pub min: usize,
+    pub max: usize,
+    median: usize,
+}
+
+impl OccurrenceStats {
+    /// Adds the number of occurrences of one query
+    pub fn add(&mut self, occurrences: usize) {
+        if self.queries == 0 {
+            self.min = occurrences;
+            self.median = occurrences;
+        }
+        self.queries += 1;
+        self.matched += usize::from(occurrences > 0);
+        self.total += occurrences as u64;
+        self.min = self.min.min(occurrences);
+        self.max = self.max.max(occurrences);
+        match occurrences.cmp(&self.median) {
+            Ordering::Greater => self.median += 1,
+            Ordering::Less => self.median -= 1,
+            Ordering::Equal => (),
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.queries == 0 {
+            0.0
+        } else {
+            self.total as f64 / self.queries as f64
+        }
+    }
+
+    /// The fraction of the queries with at least one occurrence
+    pub fn matched_fraction(&self) -> f64 {
+        if self.queries == 0 {
+            0.0
+        } else {
+            self.matched as f64 / self.queries as f64
+        }
+    }
+
+    /// The approximate median number of occurrences
+    pub fn median(&self) -> usize {
+        self.median
+    }
+}
+
+impl fmt::Display for OccurrenceStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "queries: {}", self.queries)?;
+        writeln!(
+            f,
+            "matched queries: {} ({:.1}%)",
+            self.matched,
+            100.0 * self.matched_fraction()
+        )?;
+        writeln!(f, "total occurrences: {}", self.total)?;
+        writeln!(f, "min occurrences: {}", self.min)?;
+        writeln!(f, "max occurrences: {}", self.max)?;
+        writeln!(f, "mean occurrences: {:.2}", self.mean())?;
+        write!(f, "approximate median occurrences: {}", self.median)
+    }
+}
+
+/// Options for constructing the suffix array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildOptions {
+    pub sentinel: SentinelOrder,
+    /// Threads used to sort the suffixes, more than 1 requires the `parallel` feature
+    pub threads: usize,
+    /// The reference is folded into this alphabet before it is indexed and
+    /// queries are folded the same way when searching
+    pub alphabet: Alphabet,
+    /// Record the lowercase (soft-masked) bases of the reference in a mask and index
+    /// them as uppercase, so matches in masked regions can be filtered out
+    pub soft_masked: bool,
+    /// Append the reverse complement of every record as another record, so a single
+    /// search finds the occurrences on both strands, see [`SuffixArray::locate_strand`]
+    pub both_strands: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            sentinel: SentinelOrder::Smallest,
+            threads: 1,
+            alphabet: Alphabet::Dna,
+            soft_masked: false,
+            both_strands: false,
+        }
+    }
+}
+
+/// Sorts the suffixes of `text` with the `suffix` crate, or in parallel when
+/// more than one thread is requested and the `parallel` feature is enabled
+fn sort_suffixes(text: &str, threads: usize) -> Vec<u32> {
+    #[cfg(feature = "parallel")]
+    if threads > 1 {
+        return crate::parallel::suffix_array(text.as_bytes(), threads);
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = threads;
+    SuffixTable::new(text).into_parts().1.into_owned()
+}
+
+/// The byte a sentinel is compared as when it sorts after every nucleotide
+const LARGEST_SENTINEL: u8 = b'~';
+
+/// Reads a sequence with its `$` sentinels replaced by a byte larger than any nucleotide
+struct SentinelLast<'a, S: ?Sized>(&'a S);
+
+impl<S> Sequence for SentinelLast<'_, S>
+where
+    S: Sequence + ?Sized,
+{
+    #[inline]
+    fn byte_at(&self, index: usize) -> u8 {
+        match self.0.byte_at(index) {
+            b'$' => LARGEST_SENTINEL,
+            byte => byte,
+        }
+    }
+}
+
+/// A named record in a multi-record reference and its (start, end) range in the sequence
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct Contig {
+    pub name: String,
+    pub span: Span,
+}
+
+impl Contig {
+    /// Returns the offset of a sequence position relative to the start of this contig
+    ///
+    /// ```rust
+    /// # use assignment_1::{search::Span, suffix_array::Contig};
+    /// let contig = Contig { name: String::from("chr2"), span: Span::new(5, 9) };
+    /// assert_eq!(contig.locate(6), Some(1));
+    /// assert_eq!(contig.locate(9), None);
+    /// ```
+    pub fn locate(&self, position: u32) -> Option<u32> {
+        if self.span.start <= position && position < self.span.end {
+            Some(position - self.span.start)
+        } else {
+            None
+        }
+    }
+}
+
+/// Accumulates how many occurrences cover each position of the reference
+///
+/// Occurrences are added to a difference array so each one costs O(1), the depth
+/// is only computed when the track is written.
+///
+/// ```rust
+/// # use assignment_1::{
+/// #     search::Span,
+/// #     suffix_array::{Contig, Coverage},
+/// # };
+/// let contigs = [Contig { name: String::from("chr1"), span: Span::new(0, 10) }];
+/// let mut coverage = Coverage::new(11);
+/// coverage.add(2, 4);
+/// coverage.add(4, 3);
+/// let mut bedgraph = Vec::new();
+/// coverage.write_bedgraph(&contigs, &mut bedgraph).unwrap();
+/// assert_eq!(
+///     String::from_utf8(bedgraph).unwrap(),
+///     "chr1\t2\t4\t1\nchr1\t4\t6\t2\nchr1\t6\t7\t1\n"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    deltas: Vec<i64>,
+}
+
+impl Coverage {
+    /// Coverage over a sequence of `len` bases (all positions start at depth 0)
+    pub fn new(len: usize) -> Self {
+        Self {
+            deltas: vec![0; len + 1],
+        }
+    }
+
+    /// Adds one to the depth of `[start, start + len)`, clipped to the sequence
+    pub fn add(&mut self, start: u32, len: u32) {
+        let last = self.deltas.len() - 1;
+        let start = (start as usize).min(last);
+        let end = (start + len as usize).min(last);
+        self.deltas[start] += 1;
+        self.deltas[end] -= 1;
+    }
+
+    /// Writes `name\tstart\tend\tdepth` lines for every run of constant, non-zero
+    /// depth inside each contig, with 0-based half-open positions relative to the contig
+    pub fn write_bedgraph(&self, contigs: &[Contig], writer: &mut dyn Write) -> io::Result<()> {
+        let depths: Vec<i64> = self
+            .deltas
+            .iter()
+            .scan(0, |depth, delta| {
+                *depth += delta;
+                Some(*depth)
+            })
+            .collect();
+        for contig in contigs {
+            let (start, end) = (contig.span.start as usize, contig.span.end as usize);
+            let mut run_start = start;
+            for position in start..=end {
+                let depth = depths.get(position).copied().unwrap_or(0);
+                if position < end && depth == depths[run_start] {
+                    continue;
+                }
+                if position > run_start && depths[run_start] > 0 {
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{}\t{}",
+                        contig.name,
+                        run_start - start,
+                        position - start,
+                        depths[run_start]
+                    )?;
+                }
+                run_start = position;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -22,21 +707,133 @@ pub struct Comparison {
     pub ordering: Ordering,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum QueryMode {
     Naive,
     Simpaccel,
+    /// Naive for queries shorter than `threshold` and simpaccel for longer ones, since
+    /// skipping the shared prefix of the bounds only pays off on long queries
+    Adaptive {
+        threshold: usize,
+    },
+}
+
+/// The query length from which [`QueryMode::Adaptive`] switches to simpaccel by default
+///
+/// Only the shortest queries, which leave little shared prefix for simpaccel to skip,
+/// use naive.
+pub const DEFAULT_ADAPTIVE_THRESHOLD: usize = 4;
+
+/// Merges occurrences of a query of length `query_len` into (start, end) intervals
+///
+/// `positions` must be sorted. An occurrence starting at most `gap` bases after the
+/// end of the current interval extends it, so with a gap of 0 adjacent and
+/// overlapping occurrences (e.g. a tandem repeat) collapse into one region.
+///
+/// ```rust
+/// # use assignment_1::{search::Span, suffix_array::merge_occurrences};
+/// assert_eq!(merge_occurrences(&[0, 2, 4, 10], 2, 0), vec![Span::new(0, 6), Span::new(10, 12)]);
+/// assert_eq!(merge_occurrences(&[0, 2, 4, 10], 2, 4), vec![Span::new(0, 12)]);
+/// assert_eq!(merge_occurrences(&[0, 3], 2, 0), vec![Span::new(0, 2), Span::new(3, 5)]);
+/// ```
+pub fn merge_occurrences(positions: &[u32], query_len: u32, gap: u32) -> Vec<Span> {
+    let mut intervals: Vec<Span> = Vec::new();
+    for &position in positions {
+        let end = position + query_len;
+        match intervals.last_mut() {
+            Some(last) if position <= last.end.saturating_add(gap) => last.end = last.end.max(end),
+            _ => intervals.push(Span::new(position, end)),
+        }
+    }
+    intervals
+}
+
+/// Joins the sequences of the records with `$` separators (without the final sentinel)
+/// and records the span each record occupies
+fn join_records(records: Vec<Record>) -> (String, Vec<Contig>) {
+    let mut sequence = String::new();
+    let mut contigs: Vec<Contig> = Vec::with_capacity(records.len());
+    for record in records {
+        if !sequence.is_empty() && !sequence.ends_with('$') {
+            sequence.push('$');
+        }
+        let start = sequence.len() as u32;
+        sequence.push_str(record.sequence());
+        let end = sequence.trim_end_matches('$').len().max(start as usize) as u32;
+        contigs.push(Contig {
+            name: record.name().to_string(),
+            span: Span::new(start, end),
+        });
+    }
+    (sequence, contigs)
+}
+
+/// Computes the hex encoded sha256 digest of a reference sequence
+///
+/// A trailing `$` sentinel is ignored so the checksum of an index matches
+/// the checksum of the sequence it was built from
+///
+/// ```rust
+/// # use assignment_1::suffix_array::checksum_sequence;
+/// assert_eq!(checksum_sequence("ACGT$"), checksum_sequence("ACGT"));
+/// assert_eq!(checksum_sequence("ACGT").len(), 64);
+/// ```
+pub fn checksum_sequence(sequence: &str) -> String {
+    let digest = Sha256::digest(sequence.trim_end_matches('$').as_bytes());
+    format!("{digest:x}")
+}
+
+/// Trims surrounding ASCII whitespace from a query, uppercases it with
+/// [`NormalizePolicy::QUERY`] and folds it into the alphabet of the index
+fn normalize_query(alphabet: Alphabet, query: &str) -> Cow<'_, str> {
+    let query = query.trim_matches(|x: char| x.is_ascii_whitespace());
+    let query = if query.bytes().any(|x| x.is_ascii_lowercase()) {
+        Cow::Owned(normalize_sequence(query, NormalizePolicy::QUERY).0)
+    } else {
+        Cow::Borrowed(query)
+    };
+    if alphabet == Alphabet::Dna {
+        query
+    } else {
+        Cow::Owned(alphabet.fold_str(&query).into_owned())
+    }
+}
+
+fn search_sequence<S>(
+    sequence: &S,
+    prefix_bytes: &[u8],
+    suffix_array: &[u32],
+    span: &Span,
+    mode: QueryMode,
+) -> Option<Span>
+where
+    S: Sequence + ?Sized,
+{
+    match mode {
+        QueryMode::Naive => naive_search(sequence, prefix_bytes, suffix_array, span),
+        QueryMode::Adaptive { threshold } if prefix_bytes.len() < threshold => {
+            naive_search(sequence, prefix_bytes, suffix_array, span)
+        }
+        QueryMode::Simpaccel | QueryMode::Adaptive { .. } => {
+            simple_accelerant_search(sequence, prefix_bytes, suffix_array, span)
+        }
+    }
 }
 
 impl SuffixArray {
-    fn build_prefix_table(&self, k: u16) -> PrefixTable {
+    fn build_prefix_table(&self, sequence: &str, k: u16) -> PrefixTable {
         let mut last_prefix: Option<&str> = None;
         let mut start: usize = 0;
         let offset = k as usize;
         let sa_len = self.suffix_array.len();
-        // let mut prefix_table: PrefixTable = PrefixTable::new(k);
-        let mut prefix_table: PrefixTable = PrefixTable::new_sparse(k);
+        // at most min(4^k, number of windows) distinct k-mers
+        let capacity = 4_usize
+            .checked_pow(k as u32)
+            .unwrap_or(usize::MAX)
+            .min(sa_len.saturating_sub(offset));
+        let mut prefix_table: PrefixTable = PrefixTable::sparse_with_capacity(k, capacity);
         for (idx, elem) in self.suffix_array.iter().map(|&x| x as usize).enumerate() {
-            let prefix = &self.sequence[elem..min(sa_len - 1, elem + offset)];
+            let prefix = &sequence[elem..min(sa_len - 1, elem + offset)];
             if last_prefix.is_none() {
                 last_prefix = Some(prefix);
                 start = idx;
@@ -45,9 +842,9 @@ impl SuffixArray {
 
             let previous = last_prefix.unwrap();
             if previous != prefix {
-                if previous.len() == offset && previous != "$" {
+                if previous.len() == offset && !previous.contains('$') {
                     // prefix_table.insert(previous.to_string(), (start, idx));
-                    prefix_table.insert(previous.to_string(), (start as u32, idx as u32));
+                    prefix_table.insert(previous.to_string(), Span::new(start as u32, idx as u32));
                     // prefix_table.insert(previous, (start, idx));
                 }
                 start = idx;
@@ -55,9 +852,9 @@ impl SuffixArray {
             }
         }
         if let Some(previous) = last_prefix {
-            if previous.len() == offset && previous != "$" {
+            if previous.len() == offset && !previous.contains('$') {
                 // prefix_table.insert(previous.to_string(), (start, sa_len));
-                prefix_table.insert(previous.to_string(), (start as u32, sa_len as u32));
+                prefix_table.insert(previous.to_string(), Span::new(start as u32, sa_len as u32));
                 // prefix_table.insert(previous, (start, sa_len));
             }
         }
@@ -65,106 +862,2977 @@ impl SuffixArray {
     }
 
     pub fn initialize_prefix_table(&mut self, k: u16) {
-        match &self.prefix_table {
-            Some(table) => {
-                if table.k() != k {
-                    self.prefix_table = Some(self.build_prefix_table(k))
+        if !self.targeted_prefix_table
+            && matches!(&self.prefix_table, Some(table) if table.k() == k)
+        {
+            return;
+        }
+        self.targeted_prefix_table = false;
+        self.adaptive_table = None;
+        let unpacked: String;
+        let sequence: &str = match &self.packed {
+            Some(packed) => {
+                unpacked = packed.unpack();
+                &unpacked
+            }
+            None => &self.sequence,
+        };
+        self.prefix_table = Some(self.build_prefix_table(sequence, k));
+    }
+
+    /// Builds an [`AdaptivePrefixTable`] instead of a prefix table of a single k: the
+    /// buckets of the `min_k`-mers holding more than `max_width` suffixes are split by
+    /// the following bases, up to `max_k` bases
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// let mut sa = SuffixArray::from_sequence(&("ACGT".repeat(8) + "ACCA"));
+    /// sa.initialize_adaptive_prefix_table(2, 6, 4);
+    /// let table = sa.adaptive_prefix_table().unwrap();
+    /// assert_eq!(table.lookup("ACGTACG").unwrap().len(), 8);
+    /// assert_eq!(table.lookup("ACCA").unwrap().len(), 1);
+    /// assert_eq!(sa.search("ACGTACG", QueryMode::Naive).unwrap().len(), 7);
+    /// ```
+    pub fn initialize_adaptive_prefix_table(&mut self, min_k: u16, max_k: u16, max_width: u32) {
+        self.prefix_table = None;
+        self.targeted_prefix_table = false;
+        let unpacked: String;
+        let sequence: &str = match &self.packed {
+            Some(packed) => {
+                unpacked = packed.unpack();
+                &unpacked
+            }
+            None => &self.sequence,
+        };
+        let bytes = sequence.as_bytes();
+        let mut table = AdaptivePrefixTable::new(min_k, max_k, max_width);
+        let mut pending: Vec<(String, Span)> =
+            self.build_prefix_table(sequence, min_k).iter().collect();
+        while let Some((prefix, span)) = pending.pop() {
+            if table.is_split(&prefix, span) {
+                // the suffixes of a bucket are sorted, so those sharing the next base are
+                // adjacent. A bucket never holds a `$` so every suffix has a next base
+                let next = |idx: usize| bytes[self.suffix_array[idx] as usize + prefix.len()];
+                let mut start = span.start as usize;
+                for idx in span.start as usize + 1..=span.end as usize {
+                    if idx < span.end as usize && next(idx) == next(start) {
+                        continue;
+                    }
+                    if next(start) != b'$' {
+                        let mut child = prefix.clone();
+                        child.push(next(start) as char);
+                        pending.push((child, Span::new(start as u32, idx as u32)));
+                    }
+                    start = idx;
                 }
             }
-            _ => self.prefix_table = Some(self.build_prefix_table(k)),
+            table.insert(prefix, span);
+        }
+        self.adaptive_table = Some(table);
+    }
+
+    pub fn adaptive_prefix_table(&self) -> Option<&AdaptivePrefixTable> {
+        self.adaptive_table.as_ref()
+    }
+
+    /// Builds a sparse prefix table holding only the spans of a panel of k-mers
+    ///
+    /// Every k-mer is searched instead of scanning the whole suffix array, so the
+    /// table stays small. Queries starting with a k-mer outside the panel fall back
+    /// to searching the full suffix array.
+    ///
+    /// ```rust
+    /// # use assignment_1::{
+    /// #     record::Record,
+    /// #     search::Span,
+    /// #     suffix_array::{QueryMode, SuffixArray},
+    /// # };
+    /// let mut record = Record::new();
+    /// record.push_sequence_part("ACGTACGTTT");
+    /// let mut sa = SuffixArray::from_record(record);
+    /// sa.build_targeted_prefix_table(&["ACG".to_string(), "GGG".to_string()]).unwrap();
+    /// assert_eq!(sa.search("ACGT", QueryMode::Naive), Some(Span::new(1, 3)));
+    /// assert_eq!(sa.search("GGGA", QueryMode::Naive), None);
+    /// assert_eq!(sa.search("TTT", QueryMode::Naive), Some(Span::new(10, 11)));
+    /// ```
+    pub fn build_targeted_prefix_table(&mut self, kmers: &[String]) -> Result<()> {
+        let k = match kmers.first() {
+            Some(kmer) if !kmer.is_empty() => kmer.len(),
+            Some(_) => return Err(eyre!("Targeted prefix table k-mers cannot be empty")),
+            None => return Err(eyre!("Targeted prefix table needs at least one k-mer")),
+        };
+        if let Some(kmer) = kmers.iter().find(|kmer| kmer.len() != k) {
+            return Err(eyre!(
+                "Targeted prefix table k-mers must share one length but {kmer} is not {k} long"
+            ));
+        }
+        let k = u16::try_from(k)?;
+        self.prefix_table = None;
+        self.targeted_prefix_table = false;
+        self.adaptive_table = None;
+        let full_span = Span::new(0, self.suffix_array.len() as u32);
+        let mut table = PrefixTable::new_sparse(k);
+        for kmer in kmers {
+            let kmer = self.alphabet.fold_str(kmer).into_owned();
+            // panel k-mers missing from the reference keep an empty span so the
+            // lookup still answers them without a search
+            let span = self
+                .bisect(&kmer, full_span, QueryMode::Naive)
+                .unwrap_or_default();
+            table.insert(kmer, span);
+        }
+        self.prefix_table = Some(table);
+        self.targeted_prefix_table = true;
+        Ok(())
+    }
+
+    /// Switches the sequence to 2-bit storage, searches decode it on the fly
+    pub fn pack(&mut self) -> Result<()> {
+        if self.packed.is_none() {
+            self.packed = Some(PackedSequence::pack(&self.sequence)?);
+            self.sequence = String::new();
+        }
+        Ok(())
+    }
+
+    /// Restores the plain storage of the sequence of a packed index
+    pub fn unpack(&mut self) {
+        if let Some(packed) = self.packed.take() {
+            self.sequence = packed.unpack();
+        }
+    }
+
+    pub fn is_packed(&self) -> bool {
+        self.packed.is_some()
+    }
+
+    /// The indexed sequence with its `$` sentinels, decoded from the 2-bit storage of a
+    /// packed index
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::SuffixArray;
+    /// let mut sa = SuffixArray::from_sequence("ACGT");
+    /// sa.pack().unwrap();
+    /// assert_eq!(sa.sequence(), "ACGT$");
+    /// ```
+    pub fn sequence(&self) -> Cow<'_, str> {
+        match &self.packed {
+            Some(packed) => Cow::Owned(packed.unpack()),
+            None => Cow::Borrowed(&self.sequence),
+        }
+    }
+
+    fn byte_at(&self, index: usize) -> u8 {
+        match &self.packed {
+            Some(packed) => packed.byte_at(index),
+            None => self.sequence.as_bytes()[index],
+        }
+    }
+
+    /// The length of the sequence including the `$` sentinel
+    pub fn sequence_len(&self) -> usize {
+        match &self.packed {
+            Some(packed) => packed.len(),
+            None => self.sequence.len(),
         }
     }
 
     pub fn from_record(record: Record) -> Self {
-        let Record { mut sequence, .. } = record;
+        Self::from_records(vec![record])
+    }
+
+    /// Indexes a sequence held in memory, without reading a FASTA file or building a [`Record`]
+    ///
+    /// The sequence is treated as a single unnamed record and the `$` sentinel is
+    /// appended if missing. The index can be queried right away.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// let sa = SuffixArray::from_sequence("ACGTACGGA");
+    /// let span = sa.search("ACG", QueryMode::Simpaccel).unwrap();
+    /// let mut positions = sa.suffix_array[span.range()].to_vec();
+    /// positions.sort();
+    /// assert_eq!(positions, vec![0, 4]);
+    /// assert_eq!(sa.search("TTT", QueryMode::Naive), None);
+    /// ```
+    pub fn from_sequence(sequence: &str) -> Self {
+        Self::from_record(Record {
+            header: String::new(),
+            sequence: sequence.to_string(),
+        })
+    }
+
+    /// Builds a single index over several records by joining their sequences with `$`
+    ///
+    /// Queries never contain `$` so matches cannot span two records. The range each
+    /// record occupies in the joined sequence is kept so results can be restricted to it.
+    pub fn from_records(records: Vec<Record>) -> Self {
+        Self::from_records_with_sentinel(records, SentinelOrder::Smallest)
+    }
+
+    /// Same as [`SuffixArray::from_records`] with a choice of where the sentinel sorts
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::{SentinelOrder, SuffixArray}};
+    /// let mut record = Record::new();
+    /// record.push_sequence_part("ACA");
+    /// let sa = SuffixArray::from_records_with_sentinel(vec![record], SentinelOrder::Largest);
+    /// assert_eq!(sa.suffix_array, vec![0, 2, 1, 3]);
+    /// ```
+    pub fn from_records_with_sentinel(records: Vec<Record>, sentinel: SentinelOrder) -> Self {
+        Self::from_records_with_options(
+            records,
+            BuildOptions {
+                sentinel,
+                ..BuildOptions::default()
+            },
+        )
+    }
+
+    /// Same as [`SuffixArray::from_records`] with the construction options given explicitly
+    pub fn from_records_with_options(mut records: Vec<Record>, options: BuildOptions) -> Self {
+        if options.both_strands {
+            let reverse: Vec<Record> = records
+                .iter()
+                .map(|record| Record {
+                    header: record.header.clone(),
+                    sequence: reverse_complement(record.sequence().trim_end_matches('$')),
+                })
+                .collect();
+            records.extend(reverse);
+        }
+        let (sequence, contigs) = join_records(records);
+        Self::from_joined_sequence(sequence, contigs, options)
+    }
+
+    /// Builds an index directly from unpacked 2-bit nucleotide codes (one code per byte)
+    ///
+    /// ```rust
+    /// # use assignment_1::{search::Span, suffix_array::{QueryMode, SuffixArray}};
+    /// let sa = SuffixArray::from_codes(&[0, 1, 2, 0, 1]).unwrap();
+    /// assert_eq!(sa.sequence(), "ACGAC$");
+    /// assert_eq!(sa.search_codes(&[0, 1], QueryMode::Naive).unwrap(), Some(Span::new(1, 3)));
+    /// ```
+    pub fn from_codes(codes: &[u8]) -> Result<Self> {
+        let mut bytes = decode_codes(codes)?;
+        let end = bytes.len() as u32;
+        bytes.push(b'$');
+        // decoded codes are always ASCII
+        let sequence = String::from_utf8(bytes)?;
+        Ok(Self::from_joined_sequence(
+            sequence,
+            vec![Contig {
+                name: String::new(),
+                span: Span::new(0, end),
+            }],
+            BuildOptions::default(),
+        ))
+    }
+
+    /// Builds an index from `len` 2-bit nucleotide codes packed four to a byte
+    pub fn from_packed_codes(packed: &[u8], len: usize) -> Result<Self> {
+        Self::from_codes(&unpack_codes(packed, len)?)
+    }
+
+    fn from_joined_sequence(
+        mut sequence: String,
+        contigs: Vec<Contig>,
+        options: BuildOptions,
+    ) -> Self {
         if !sequence.ends_with('$') {
             sequence.push('$');
         }
-        let (text, table) = SuffixTable::new(sequence).into_parts();
+        let BuildOptions {
+            sentinel,
+            threads,
+            alphabet,
+            soft_masked,
+            both_strands,
+        } = options;
+        let mask = soft_masked.then(|| {
+            let mask = SoftMask::from_sequence(&sequence);
+            sequence.make_ascii_uppercase();
+            mask
+        });
+        if alphabet != Alphabet::Dna {
+            sequence = alphabet.fold_str(&sequence).into_owned();
+        }
+        let checksum = checksum_sequence(&sequence);
+        let suffix_array = match sentinel {
+            SentinelOrder::Smallest => sort_suffixes(&sequence, threads),
+            SentinelOrder::Largest => {
+                let text = sequence.replace('$', &(LARGEST_SENTINEL as char).to_string());
+                sort_suffixes(&text, threads)
+            }
+        };
         Self {
-            suffix_array: table.into_owned(),
-            sequence: text.into_owned(),
+            suffix_array,
+            sequence,
             prefix_table: None,
+            checksum,
+            contigs,
+            packed: None,
+            sentinel,
+            alphabet,
+            targeted_prefix_table: false,
+            mask,
+            skip_prefix_table: false,
+            bloom: None,
+            both_strands,
+            adaptive_table: None,
         }
     }
 
-    fn get_start_span(&self, prefix: &str) -> Option<Span> {
-        if let Some(table) = &self.prefix_table {
-            let k = table.k() as usize;
-            if prefix.len() < table.k() as usize {
-                // return Some((0, self.suffix_array.len()));
-                return Some((0, self.suffix_array.len() as u32));
-            }
-            return table.get(&prefix[..k]);
+    pub fn sentinel_order(&self) -> SentinelOrder {
+        self.sentinel
+    }
+
+    /// The alphabet the reference was folded into, queries are folded the same way
+    pub fn alphabet(&self) -> Alphabet {
+        self.alphabet
+    }
+
+    /// The soft-masked bases of the reference if it was indexed with
+    /// [`BuildOptions::soft_masked`]
+    pub fn soft_mask(&self) -> Option<&SoftMask> {
+        self.mask.as_ref()
+    }
+
+    /// Whether none of the `len` bases starting at `position` are soft-masked,
+    /// always true for an index without a mask
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::{BuildOptions, SuffixArray}};
+    /// let record = Record { header: String::from("chr1"), sequence: String::from("ACGTacgtACGT") };
+    /// let options = BuildOptions { soft_masked: true, ..BuildOptions::default() };
+    /// let sa = SuffixArray::from_records_with_options(vec![record], options);
+    /// assert_eq!(sa.sequence(), "ACGTACGTACGT$");
+    /// assert!(sa.is_unmasked(0, 4));
+    /// assert!(!sa.is_unmasked(2, 3));
+    /// assert!(sa.is_unmasked(8, 4));
+    /// ```
+    pub fn is_unmasked(&self, position: u32, len: u32) -> bool {
+        !self
+            .mask
+            .as_ref()
+            .is_some_and(|mask| mask.any_masked(Span::new(position, position + len)))
+    }
+
+    /// Iterates the suffixes of the sequence in lexicographic (suffix array) order
+    ///
+    /// Every suffix ends with the `$` sentinel, and the sentinel on its own is
+    /// included as the first suffix (the last with [`SentinelOrder::Largest`]).
+    /// Use `.skip(1)` to leave it out.
+    ///
+    /// # Panics
+    /// If the index is packed, call [`SuffixArray::unpack`] first
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::SuffixArray};
+    /// let mut record = Record::new();
+    /// record.push_sequence_part("CAB");
+    /// let sa = SuffixArray::from_record(record);
+    /// let suffixes: Vec<&str> = sa.sorted_suffixes().collect();
+    /// assert_eq!(suffixes, vec!["$", "AB$", "B$", "CAB$"]);
+    /// ```
+    pub fn sorted_suffixes(&self) -> impl Iterator<Item = &str> + '_ {
+        assert!(
+            !self.is_packed(),
+            "cannot borrow suffixes of a packed index"
+        );
+        self.suffix_array
+            .iter()
+            .map(|&idx| &self.sequence[idx as usize..])
+    }
+
+    /// Builds an index from a sequence (ending in `$`) and its already sorted suffix array
+    ///
+    /// The sequence is treated as a single unnamed record
+    pub fn from_parts(sequence: String, suffix_array: Vec<u32>) -> Self {
+        let checksum = checksum_sequence(&sequence);
+        let end = sequence.trim_end_matches('$').len() as u32;
+        Self {
+            sequence,
+            suffix_array,
+            prefix_table: None,
+            checksum,
+            contigs: vec![Contig {
+                name: String::new(),
+                span: Span::new(0, end),
+            }],
+            packed: None,
+            sentinel: SentinelOrder::Smallest,
+            alphabet: Alphabet::Dna,
+            targeted_prefix_table: false,
+            mask: None,
+            skip_prefix_table: false,
+            bloom: None,
+            both_strands: false,
+            adaptive_table: None,
         }
-        // Some((0, self.suffix_array.len()))
-        Some((0, self.suffix_array.len() as u32))
     }
 
-    pub fn naive_search(&self, prefix: &str) -> Option<Span> {
-        let span;
-        if let Some(start_span) = self.get_start_span(prefix) {
-            span = start_span;
-        } else {
-            return None;
+    /// Same as [`SuffixArray::from_parts`] with a prefix table, checking that the parts
+    /// describe a valid index instead of trusting them
+    ///
+    /// Lets tests craft an index state directly, e.g. a synthetic suffix array, without
+    /// sorting the suffixes again. Fails if the sequence does not end with `$`, if the
+    /// suffix array is not the sorted suffixes of the sequence (see [`SuffixArray::validate`])
+    /// or if a span of the prefix table holds a suffix that does not start with its k-mer.
+    ///
+    /// ```rust
+    /// # use assignment_1::{prefix_table::PrefixTable, search::Span, suffix_array::SuffixArray};
+    /// let mut table = PrefixTable::new_sparse(1);
+    /// table.insert(String::from("A"), Span::new(1, 3));
+    /// let sa = SuffixArray::from_raw_parts("ACA$".into(), vec![3, 2, 0, 1], Some(table.clone()));
+    /// assert_eq!(sa.unwrap().prefix_table(), Some(&table));
+    /// assert!(SuffixArray::from_raw_parts("ACA$".into(), vec![3, 0, 2, 1], None).is_err());
+    /// table.insert(String::from("C"), Span::new(2, 4));
+    /// assert!(SuffixArray::from_raw_parts("ACA$".into(), vec![3, 2, 0, 1], Some(table)).is_err());
+    /// ```
+    pub fn from_raw_parts(
+        sequence: String,
+        suffix_array: Vec<u32>,
+        prefix_table: Option<PrefixTable>,
+    ) -> Result<Self> {
+        if !sequence.ends_with('$') {
+            return Err(eyre!(
+                "the sequence of an index must end with the $ sentinel"
+            ));
         }
-        let sequence_bytes = self.sequence.as_bytes();
-        let prefix_bytes = prefix.as_bytes();
-        // if self.suffix_array[span.0..span.1].is_empty() {
-        if self.suffix_array[span.0 as usize..span.1 as usize].is_empty() {
-            return None;
+        let mut index = Self::from_parts(sequence, suffix_array);
+        index.validate()?;
+        for (kmer, span) in prefix_table.iter().flat_map(PrefixTable::iter) {
+            let suffixes = index.suffix_array.get(span.range()).ok_or_else(|| {
+                eyre!("the prefix table span of {kmer} ends past the suffix array")
+            })?;
+            if let Some(suffix) = suffixes
+                .iter()
+                .find(|&&suffix| !index.sequence[suffix as usize..].starts_with(&kmer))
+            {
+                return Err(eyre!(
+                    "the prefix table span of {kmer} holds the suffix at {suffix} which does not start with it"
+                ));
+            }
         }
-        naive_search(sequence_bytes, prefix_bytes, &self.suffix_array, &span)
+        index.prefix_table = prefix_table;
+        Ok(index)
     }
 
-    pub fn simple_accelerant_search(&self, prefix: &str) -> Option<Span> {
-        let span;
-        if let Some(start_span) = self.get_start_span(prefix) {
-            span = start_span;
-        } else {
-            return None;
+    /// Loads an index written by buildsa in the default bincode format
+    ///
+    /// Indices written before the layout was versioned are still read, their checksum and
+    /// metadata are filled in as if the sequence was a single unnamed record.
+    pub fn from_bincode_file<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let version = read_format_version(path)?;
+        if version == Some(PREFIX_TABLE_ONLY_FORMAT_VERSION) {
+            return Err(eyre!(
+                "{path:?} only holds a prefix table, it can only count queries of its k"
+            ));
         }
-        let sequence_bytes = self.sequence.as_bytes();
-        let prefix_bytes = prefix.as_bytes();
-        // if self.suffix_array[span.0..span.1].is_empty() {
-        if self.suffix_array[span.0 as usize..span.1 as usize].is_empty() {
-            return None;
+        let buf_reader = BufReader::new(
+            File::open(path).wrap_err(format!("Could not open index file {path:?}"))?,
+        );
+        if version.is_none() {
+            let LegacySuffixArray {
+                sequence,
+                suffix_array,
+                prefix_table,
+            } = bincode::deserialize_from(buf_reader)
+                .wrap_err("Failed to deserialize suffix array")?;
+            let mut index = Self::from_parts(sequence, suffix_array);
+            index.prefix_table = prefix_table;
+            return Ok(index);
         }
-        simple_accelerant_search(sequence_bytes, prefix_bytes, &self.suffix_array, &span)
+        bincode::deserialize_from(buf_reader).wrap_err("Failed to deserialize suffix array")
     }
-}
-
-#[cfg(test)]
-mod tests {
 
-    use super::*;
+    /// Drops the suffix array, keeping the prefix table and the sequence as a much smaller
+    /// index for counting queries of exactly k bases
+    ///
+    /// Fails without a prefix table or with one that only covers a panel of k-mers.
+    pub fn into_prefix_table_index(mut self) -> Result<PrefixTableIndex> {
+        if self.targeted_prefix_table {
+            return Err(eyre!(
+                "a targeted prefix table does not cover every k-mer of the reference"
+            ));
+        }
+        let prefix_table = self
+            .prefix_table
+            .take()
+            .ok_or_else(|| eyre!("the index has no prefix table"))?;
+        self.unpack();
+        Ok(PrefixTableIndex {
+            version: PREFIX_TABLE_ONLY_FORMAT_VERSION,
+            prefix_table,
+            sequence: self.sequence,
+            checksum: self.checksum,
+            alphabet: self.alphabet,
+            canonical: false,
+        })
+    }
 
-    fn get_suffix_array(sequence: &str) -> SuffixArray {
-        let record: Record = Record {
-            sequence: sequence.to_string(),
-            header: String::from("test"),
+    /// Writes only the suffix array (with the format version, the checksum of the reference,
+    /// the sentinel order and the alphabet), leaving out the sequence, the prefix table and
+    /// the soft mask. Load it with [`SuffixArray::from_array_only_file`]
+    pub fn write_array_only<W>(&self, writer: W) -> Result<()>
+    where
+        W: Write,
+    {
+        let index = ArrayOnlyIndex {
+            version: ARRAY_ONLY_FORMAT_VERSION,
+            suffix_array: Cow::Borrowed(&self.suffix_array),
+            checksum: Cow::Borrowed(&self.checksum),
+            sentinel: self.sentinel,
+            alphabet: self.alphabet,
         };
-        return SuffixArray::from_record(record);
+        bincode::serialize_into(writer, &index).wrap_err("Failed to serialize suffix array")
     }
 
-    #[test]
-    fn naive_and_accelerated_search_produce_the_same_result() {
-        let sa: SuffixArray = get_suffix_array("AGGTGGCAATGCGCGCTCATCGCCTTGCAT$");
-        let prefix: &str = "GCA";
-        let (naive_start, naive_end) = sa.naive_search(&prefix).unwrap();
-        assert_eq!(naive_end - naive_start, 2);
-        // sa.suffix_array[naive_start..naive_end]
-        sa.suffix_array[naive_start as usize..naive_end as usize]
-            .iter()
-            .for_each(|idx| {
-                assert!(
-                    String::from_utf8((&sa.sequence.as_bytes()[*idx as usize..]).to_vec())
-                        .unwrap()
-                        .starts_with(prefix)
-                );
-            });
-        let (accelerated_start, accelerated_end) = sa.simple_accelerant_search(&prefix).unwrap();
-        assert_eq!(naive_start, accelerated_start);
-        assert_eq!(accelerated_end, naive_end);
+    /// Loads a suffix array written by [`SuffixArray::write_array_only`], supplying the
+    /// sequence from the records of the reference it was built from
+    ///
+    /// Fails if the joined records do not hash to the checksum stored with the array.
+    pub fn from_array_only_file<P>(path: P, records: Vec<Record>) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let buf_reader = BufReader::new(
+            File::open(path).wrap_err(format!("Could not open index file {path:?}"))?,
+        );
+        let index: ArrayOnlyIndex =
+            bincode::deserialize_from(buf_reader).wrap_err("Failed to deserialize suffix array")?;
+        if index.version != ARRAY_ONLY_FORMAT_VERSION {
+            return Err(eyre!(
+                "{path:?} is not an index written by buildsa --array-only"
+            ));
+        }
+        let (mut sequence, contigs) = join_records(records);
+        if !sequence.ends_with('$') {
+            sequence.push('$');
+        }
+        if index.alphabet != Alphabet::Dna {
+            sequence = index.alphabet.fold_str(&sequence).into_owned();
+        }
+        let checksum = checksum_sequence(&sequence);
+        if checksum != index.checksum {
+            return Err(eyre!(
+                "the suffix array was built from a reference with checksum {} but the given reference hashes to {checksum}",
+                index.checksum
+            ));
+        }
+        if index.suffix_array.len() != sequence.len() {
+            return Err(eyre!(
+                "the suffix array has {} entries but the reference has {} suffixes",
+                index.suffix_array.len(),
+                sequence.len()
+            ));
+        }
+        Ok(Self {
+            sequence,
+            suffix_array: index.suffix_array.into_owned(),
+            prefix_table: None,
+            checksum,
+            contigs,
+            packed: None,
+            sentinel: index.sentinel,
+            alphabet: index.alphabet,
+            targeted_prefix_table: false,
+            mask: None,
+            skip_prefix_table: false,
+            bloom: None,
+            both_strands: false,
+            adaptive_table: None,
+        })
+    }
+
+    /// Computes the Burrows-Wheeler Transform of the sequence
+    ///
+    /// `bwt[i]` is the character preceding the i-th smallest suffix. The suffix
+    /// starting at position 0 has no predecessor so it wraps around to the `$` sentinel.
+    pub fn bwt(&self) -> Vec<u8> {
+        let len = self.sequence_len();
+        self.suffix_array
+            .iter()
+            .map(|&idx| self.byte_at((idx as usize + len - 1) % len))
+            .collect()
+    }
+
+    /// Computes the longest common prefix array with Kasai's algorithm
+    ///
+    /// `lcp[i]` is the length of the common prefix of the suffixes at `i - 1` and `i`
+    /// in the suffix array (`lcp[0]` is 0). Common prefixes stop at `$` so they never
+    /// span two records.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::SuffixArray;
+    /// let sa = SuffixArray::from_sequence("BANANA");
+    /// // $, A$, ANA$, ANANA$, BANANA$, NA$, NANA$
+    /// assert_eq!(sa.lcp_array(), vec![0, 0, 1, 3, 0, 0, 2]);
+    /// ```
+    pub fn lcp_array(&self) -> Vec<u32> {
+        let len = self.suffix_array.len();
+        let mut rank = vec![0_usize; len];
+        for (idx, &suffix) in self.suffix_array.iter().enumerate() {
+            rank[suffix as usize] = idx;
+        }
+        let mut lcp = vec![0_u32; len];
+        let mut matched = 0_usize;
+        for suffix in 0..len {
+            if rank[suffix] == 0 {
+                matched = 0;
+                continue;
+            }
+            let previous = self.suffix_array[rank[suffix] - 1] as usize;
+            while suffix + matched < len
+                && previous + matched < len
+                && self.byte_at(suffix + matched) != b'$'
+                && self.byte_at(suffix + matched) == self.byte_at(previous + matched)
+            {
+                matched += 1;
+            }
+            lcp[rank[suffix]] = matched as u32;
+            matched = matched.saturating_sub(1);
+        }
+        lcp
+    }
+
+    /// The length of the longest common extension of the suffixes starting at `i` and
+    /// `j`, i.e. of their common prefix, compared byte by byte
+    ///
+    /// Like the [`SuffixArray::lcp_array`] the extension stops at `$`. For many queries
+    /// build an [`LceIndex`] with [`SuffixArray::lce_index`] instead.
+    ///
+    /// # Panics
+    /// If `i` or `j` is not a position of the sequence
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::SuffixArray;
+    /// let sa = SuffixArray::from_sequence("BANANA");
+    /// assert_eq!(sa.lce(1, 3), 3);
+    /// assert_eq!(sa.lce(0, 2), 0);
+    /// assert_eq!(sa.lce(4, 4), 2);
+    /// assert_eq!(sa.lce(6, 1), 0);
+    /// ```
+    pub fn lce(&self, i: u32, j: u32) -> usize {
+        let len = self.sequence_len();
+        let (i, j) = (i as usize, j as usize);
+        assert!(
+            i < len && j < len,
+            "positions {i} and {j} must be less than {len}"
+        );
+        // the later suffix is the shorter one, so reading the earlier one never runs off the end
+        let (earlier, later) = (i.min(j), i.max(j));
+        let extension = match &self.packed {
+            // the packed bases cannot be borrowed as a slice to compare against
+            Some(packed) => (0..len - later)
+                .take_while(|&offset| {
+                    packed.byte_at(earlier + offset) == packed.byte_at(later + offset)
+                })
+                .count(),
+            None => {
+                let bytes = self.sequence.as_bytes();
+                compare_suffix(bytes, earlier, &bytes[later..], 0).lcp
+            }
+        };
+        // a common extension ends at the first sentinel
+        (0..extension)
+            .take_while(|&offset| self.byte_at(later + offset) != b'$')
+            .count()
+    }
+
+    /// Builds the LCP array and a range minimum structure over it (O(n log n) space) so
+    /// [`LceIndex::lce`] answers longest common extension queries in constant time
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::SuffixArray;
+    /// let sa = SuffixArray::from_sequence("BANANA");
+    /// let index = sa.lce_index();
+    /// assert_eq!(index.lce(1, 3), 3);
+    /// assert_eq!(index.lce(4, 4), sa.lce(4, 4));
+    /// ```
+    pub fn lce_index(&self) -> LceIndex {
+        let lcp = self.lcp_array();
+        let mut rank = vec![0_u32; self.suffix_array.len()];
+        for (idx, &suffix) in self.suffix_array.iter().enumerate() {
+            rank[suffix as usize] = idx as u32;
+        }
+        let mut minima = vec![lcp];
+        let mut width = 1;
+        while 2 * width <= minima[0].len() {
+            let previous = minima.last().unwrap();
+            let level = (0..previous.len() - width)
+                .map(|x| previous[x].min(previous[x + width]))
+                .collect();
+            minima.push(level);
+            width *= 2;
+        }
+        let sentinels = (0..self.sequence_len())
+            .filter(|&position| self.byte_at(position) == b'$')
+            .map(|position| position as u32)
+            .collect();
+        LceIndex {
+            rank,
+            minima,
+            sentinels,
+        }
+    }
+
+    /// Finds a longest substring occurring at least twice in the reference
+    ///
+    /// Returns the position of its first occurrence and its length, or `None` when
+    /// no base occurs twice. Ties are broken by the smallest position.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::SuffixArray;
+    /// let sa = SuffixArray::from_sequence("BANANA");
+    /// assert_eq!(sa.longest_repeated_substring(), Some((1, 3)));
+    /// assert_eq!(SuffixArray::from_sequence("ACGT").longest_repeated_substring(), None);
+    /// ```
+    pub fn longest_repeated_substring(&self) -> Option<(u32, usize)> {
+        let lcp = self.lcp_array();
+        let length = lcp.iter().copied().max().filter(|&length| length > 0)?;
+        let position = lcp
+            .iter()
+            .enumerate()
+            .filter(|&(_, &value)| value == length)
+            .flat_map(|(idx, _)| [self.suffix_array[idx - 1], self.suffix_array[idx]])
+            .min()?;
+        Some((position, length as usize))
+    }
+
+    /// The records this index was built from in the order they appear in the sequence
+    pub fn contigs(&self) -> &[Contig] {
+        &self.contigs
+    }
+
+    /// Whether the index also holds the reverse complement of every record, see
+    /// [`BuildOptions::both_strands`]
+    pub fn has_both_strands(&self) -> bool {
+        self.both_strands
+    }
+
+    /// The records of the reference, without the reverse complements of an index built
+    /// with [`BuildOptions::both_strands`]
+    pub fn forward_contigs(&self) -> &[Contig] {
+        match self.both_strands {
+            true => &self.contigs[..self.contigs.len() / 2],
+            false => &self.contigs,
+        }
+    }
+
+    /// The strand of an occurrence `query_len` bases long starting at `position` of the
+    /// indexed sequence, and the position its leftmost base has on the forward strand
+    ///
+    /// ```rust
+    /// # use assignment_1::{
+    /// #     record::Record,
+    /// #     suffix_array::{BuildOptions, QueryMode, Strand, SuffixArray},
+    /// # };
+    /// let record = Record { header: String::from("chr1"), sequence: String::from("AACGGT") };
+    /// let options = BuildOptions { both_strands: true, ..BuildOptions::default() };
+    /// let sa = SuffixArray::from_records_with_options(vec![record], options);
+    /// assert_eq!(sa.sequence(), "AACGGT$ACCGTT$");
+    /// // CCG only occurs on the reverse strand, as the reverse complement of CGG at 2
+    /// let span = sa.search("CCG", QueryMode::Naive).unwrap();
+    /// let position = sa.suffix_array[span.start as usize];
+    /// assert_eq!(sa.locate_strand(position, 3), (Strand::Reverse, 2));
+    /// assert_eq!(sa.locate_strand(1, 3), (Strand::Forward, 1));
+    /// ```
+    pub fn locate_strand(&self, position: u32, query_len: u32) -> (Strand, u32) {
+        let forward = self.forward_contigs();
+        let reverse = &self.contigs[forward.len()..];
+        match reverse
+            .iter()
+            .rposition(|contig| position >= contig.span.start)
+        {
+            Some(idx) => {
+                // the reverse record is as long as its forward record
+                let offset = position - reverse[idx].span.start;
+                let span = forward[idx].span;
+                let end = (span.len() as u32).saturating_sub(offset);
+                (Strand::Reverse, span.start + end.saturating_sub(query_len))
+            }
+            None => (Strand::Forward, position),
+        }
+    }
+
+    pub fn contig(&self, name: &str) -> Option<&Contig> {
+        self.contigs.iter().find(|contig| contig.name == name)
+    }
+
+    /// The indexed reference without the trailing `$` sentinel
+    ///
+    /// This is the reference after the reader sanitized it, so it only matches the
+    /// original file once line breaks are removed and bases are uppercased. Bases
+    /// other than A, C, G and T were replaced with random nucleotides (and U read as T
+    /// for RNA) so those positions will differ. Records of a multi-record reference
+    /// are separated by `$`, see [`SuffixArray::reconstruct_record`].
+    ///
+    /// Panics if the index is packed, call [`SuffixArray::unpack`] first.
+    pub fn reconstruct_sequence(&self) -> &str {
+        assert!(
+            self.packed.is_none(),
+            "unpack the index before reconstructing the sequence"
+        );
+        self.sequence.strip_suffix('$').unwrap_or(&self.sequence)
+    }
+
+    /// The sequence of the record with the given name, subject to the same caveats
+    /// as [`SuffixArray::reconstruct_sequence`]
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::SuffixArray};
+    /// let records = vec![
+    ///     Record { header: String::from("chr1"), sequence: String::from("ACGT") },
+    ///     Record { header: String::from("chr2"), sequence: String::from("TTGCA") },
+    /// ];
+    /// let sa = SuffixArray::from_records(records);
+    /// assert_eq!(sa.reconstruct_sequence(), "ACGT$TTGCA");
+    /// assert_eq!(sa.reconstruct_record("chr2"), Some("TTGCA"));
+    /// ```
+    pub fn reconstruct_record(&self, name: &str) -> Option<&str> {
+        let span = self.contig(name)?.span;
+        Some(&self.reconstruct_sequence()[span.range()])
+    }
+
+    /// The checksum of the reference sequence recorded when the index was built
+    pub fn checksum(&self) -> &str {
+        self.checksum.as_ref()
+    }
+
+    /// Recomputes the checksum of the stored sequence and compares it to the recorded one
+    pub fn verify_checksum(&self) -> Result<()> {
+        let actual = match &self.packed {
+            Some(packed) => checksum_sequence(&packed.unpack()),
+            None => checksum_sequence(&self.sequence),
+        };
+        if actual != self.checksum {
+            return Err(eyre!(
+                "index is corrupted: recorded checksum {} but the sequence hashes to {actual}",
+                self.checksum
+            ));
+        }
+        Ok(())
+    }
+
+    /// Human readable differences between two indices, empty if they are structurally the
+    /// same, to find out why a rebuilt index gives different results
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::SuffixArray;
+    /// let mut sa = SuffixArray::from_sequence("ACGTACGGACG");
+    /// let other = SuffixArray::from_sequence("ACGTACGGACC");
+    /// assert!(sa.describe_diff(&SuffixArray::from_sequence("ACGTACGGACG")).is_empty());
+    /// sa.initialize_prefix_table(2);
+    /// let diff = sa.describe_diff(&other);
+    /// assert!(diff.iter().any(|x| x.starts_with("first differing suffix array entry at 4:")));
+    /// assert!(diff.contains(&String::from("prefix table: k=2 vs none")));
+    /// ```
+    pub fn describe_diff(&self, other: &Self) -> Vec<String> {
+        let mut diff = Vec::new();
+        let mut compare = |what: &str, a: String, b: String| {
+            if a != b {
+                diff.push(format!("{what}: {a} vs {b}"));
+            }
+        };
+        compare(
+            "sequence length",
+            self.sequence_len().to_string(),
+            other.sequence_len().to_string(),
+        );
+        compare("checksum", self.checksum.clone(), other.checksum.clone());
+        compare(
+            "records",
+            self.contigs.len().to_string(),
+            other.contigs.len().to_string(),
+        );
+        compare(
+            "sentinel order",
+            format!("{:?}", self.sentinel),
+            format!("{:?}", other.sentinel),
+        );
+        compare(
+            "alphabet",
+            format!("{:?}", self.alphabet),
+            format!("{:?}", other.alphabet),
+        );
+        compare(
+            "packed",
+            self.is_packed().to_string(),
+            other.is_packed().to_string(),
+        );
+        let describe_table = |sa: &Self| match &sa.prefix_table {
+            Some(table) if sa.targeted_prefix_table => format!("targeted k={}", table.k()),
+            Some(table) => format!("k={}", table.k()),
+            None => String::from("none"),
+        };
+        compare("prefix table", describe_table(self), describe_table(other));
+        let describe_bloom = |sa: &Self| match &sa.bloom {
+            Some(bloom) => format!("k={} with {} bits", bloom.k(), bloom.len()),
+            None => String::from("none"),
+        };
+        compare("Bloom filter", describe_bloom(self), describe_bloom(other));
+        let describe_mask = |sa: &Self| match &sa.mask {
+            Some(mask) => format!("{} masked bases", mask.count()),
+            None => String::from("none"),
+        };
+        compare("soft mask", describe_mask(self), describe_mask(other));
+        compare(
+            "suffix array length",
+            self.suffix_array.len().to_string(),
+            other.suffix_array.len().to_string(),
+        );
+        let first_difference = self
+            .suffix_array
+            .iter()
+            .zip(&other.suffix_array)
+            .position(|(a, b)| a != b);
+        if let Some(idx) = first_difference {
+            diff.push(format!(
+                "first differing suffix array entry at {idx}: {} vs {}",
+                self.suffix_array[idx], other.suffix_array[idx]
+            ));
+        }
+        diff
+    }
+
+    /// Checks that the index is internally consistent
+    ///
+    /// Verifies the checksum, that the suffix array is a permutation of the
+    /// positions in the sequence and that neighbouring suffixes are in sorted order.
+    pub fn validate(&self) -> Result<()> {
+        self.verify_checksum()?;
+        let len = self.sequence_len();
+        if self.suffix_array.len() != len {
+            return Err(eyre!(
+                "index is corrupted: {} suffixes for a sequence of length {len}",
+                self.suffix_array.len()
+            ));
+        }
+        let mut seen = vec![false; len];
+        for &idx in self.suffix_array.iter() {
+            match seen.get_mut(idx as usize) {
+                Some(seen) if !*seen => *seen = true,
+                Some(_) => return Err(eyre!("index is corrupted: suffix {idx} appears twice")),
+                None => return Err(eyre!("index is corrupted: suffix {idx} is out of range")),
+            }
+        }
+        for (rank, pair) in self.suffix_array.windows(2).enumerate() {
+            if self.compare_suffixes(pair[0] as usize, pair[1] as usize) != Ordering::Less {
+                return Err(eyre!(
+                    "index is corrupted: suffixes {} and {} at rank {rank} are out of order",
+                    pair[0],
+                    pair[1]
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares two suffixes of the sequence using the index's sentinel order
+    fn compare_suffixes(&self, a: usize, b: usize) -> Ordering {
+        let len = self.sequence_len();
+        let byte_at = |index: usize| match (self.byte_at(index), self.sentinel) {
+            (b'$', SentinelOrder::Largest) => LARGEST_SENTINEL,
+            (byte, _) => byte,
+        };
+        let (mut a, mut b) = (a, b);
+        while a < len && b < len {
+            match byte_at(a).cmp(&byte_at(b)) {
+                Ordering::Equal => {
+                    a += 1;
+                    b += 1;
+                }
+                ordering => return ordering,
+            }
+        }
+        // the suffix that ran out first is a prefix of the other
+        (len - a).cmp(&(len - b))
+    }
+
+    /// Verifies the index and checks that it was built from the reference with the expected checksum
+    pub fn verify_reference(&self, expected: &str) -> Result<()> {
+        self.verify_checksum()?;
+        if !self.checksum.eq_ignore_ascii_case(expected.trim()) {
+            return Err(eyre!(
+                "index was built from a reference with checksum {} but {expected} was expected",
+                self.checksum
+            ));
+        }
+        Ok(())
+    }
+
+    /// Builds a [`BloomFilter`] over the k-mers of the reference so queries whose first
+    /// `k` bases are definitely absent are ruled out before searching. Saved with the index
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// let mut sa = SuffixArray::from_sequence("ACGTACGGACG");
+    /// sa.initialize_bloom_filter(4, 0.01);
+    /// assert_eq!(sa.search("ACGG", QueryMode::Naive).map(|span| span.len()), Some(1));
+    /// assert_eq!(sa.search("ACGAT", QueryMode::Naive), None);
+    /// ```
+    pub fn initialize_bloom_filter(&mut self, k: u16, false_positive_rate: f64) {
+        let sequence = match &self.packed {
+            Some(packed) => Cow::Owned(packed.unpack()),
+            None => Cow::Borrowed(self.sequence.as_str()),
+        };
+        self.bloom = Some(BloomFilter::from_sequence(
+            sequence.as_bytes(),
+            k,
+            false_positive_rate,
+        ));
+    }
+
+    pub fn bloom_filter(&self) -> Option<&BloomFilter> {
+        self.bloom.as_ref()
+    }
+
+    pub fn prefix_table(&self) -> Option<&PrefixTable> {
+        self.prefix_table.as_ref()
+    }
+
+    /// Describes the index without its sequence or arrays, `index_format_version` is
+    /// left for the caller that read the file to fill in
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::SuffixArray};
+    /// let mut sa = SuffixArray::from_record(Record {
+    ///     header: String::from("chr1"),
+    ///     sequence: String::from("ACGTACGT"),
+    /// });
+    /// sa.initialize_prefix_table(2);
+    /// let info = sa.info();
+    /// assert_eq!(info.header.as_deref(), Some("chr1"));
+    /// assert_eq!((info.sequence_length, info.num_suffixes), (9, 9));
+    /// assert_eq!(info.prefix_table_k, Some(2));
+    /// ```
+    pub fn info(&self) -> IndexInfo {
+        let table = self.prefix_table.as_ref();
+        let adaptive = self.adaptive_table.as_ref();
+        IndexInfo {
+            header: self.contigs.first().map(|contig| contig.name.clone()),
+            records: self.contigs.len(),
+            sequence_length: self.sequence_len(),
+            num_suffixes: self.suffix_array.len(),
+            sentinel: self.sentinel,
+            has_prefix_table: table.is_some() || adaptive.is_some(),
+            prefix_table_k: table
+                .map(PrefixTable::k)
+                .or(adaptive.map(AdaptivePrefixTable::min_k)),
+            prefix_table_variant: table
+                .map(PrefixTable::variant)
+                .or(adaptive.map(|_| "adaptive")),
+            index_format_version: None,
+        }
+    }
+
+    /// Ignore the stored prefix table when searching so every query bisects the
+    /// full suffix array (useful to compare both from a single index)
+    pub fn skip_prefix_table(&mut self, skip: bool) {
+        self.skip_prefix_table = skip;
+    }
+
+    /// The span of the suffix array a query is bisected in after the prefix table lookup,
+    /// `None` if the prefix table shows the query does not occur
+    ///
+    /// Without a prefix table (or for queries shorter than its k) this is the full array.
+    ///
+    /// ```rust
+    /// # use assignment_1::{search::Span, suffix_array::SuffixArray};
+    /// let mut sa = SuffixArray::from_sequence("ACGTACGGACG");
+    /// assert_eq!(sa.start_span("ACGT"), Some(Span::new(0, 12)));
+    /// sa.initialize_prefix_table(2);
+    /// assert_eq!(sa.start_span("acgt").map(|span| span.len()), Some(3));
+    /// assert_eq!(sa.start_span("A"), Some(Span::new(0, 12)));
+    /// assert_eq!(sa.start_span("TT"), None);
+    /// ```
+    pub fn start_span(&self, query: &str) -> Option<Span> {
+        self.get_start_span(&self.normalize_query(query))
+    }
+
+    fn get_start_span(&self, prefix: &str) -> Option<Span> {
+        if let Some(bloom) = &self.bloom {
+            if prefix
+                .get(..bloom.k() as usize)
+                .is_some_and(|kmer| !bloom.contains(kmer))
+            {
+                return None;
+            }
+        }
+        if let Some(table) = self
+            .adaptive_table
+            .as_ref()
+            .filter(|_| !self.skip_prefix_table)
+        {
+            if prefix.len() < table.min_k() as usize {
+                return Some(Span::new(0, self.suffix_array.len() as u32));
+            }
+            return table.lookup(prefix);
+        }
+        if let Some(table) = self
+            .prefix_table
+            .as_ref()
+            .filter(|_| !self.skip_prefix_table)
+        {
+            let k = table.k() as usize;
+            if prefix.len() < table.k() as usize {
+                // return Some((0, self.suffix_array.len()));
+                return Some(Span::new(0, self.suffix_array.len() as u32));
+            }
+            return match table.get(&prefix[..k]) {
+                Some(span) => Some(span),
+                None if self.targeted_prefix_table => {
+                    Some(Span::new(0, self.suffix_array.len() as u32))
+                }
+                None => None,
+            };
+        }
+        // Some((0, self.suffix_array.len()))
+        Some(Span::new(0, self.suffix_array.len() as u32))
+    }
+
+    /// Finds the range of the suffix array whose suffixes start with the query
+    ///
+    /// The prefix table (if present) narrows the starting span before bisecting
+    /// with the algorithm selected by `mode`. Leading and trailing ASCII whitespace
+    /// in the query is ignored and lowercase bases match their uppercase base.
+    ///
+    /// The span holds every start position, so occurrences of a self-overlapping
+    /// query may overlap (`AA` occurs 3 times in `AAAA`), unlike `str::matches`.
+    /// See [`SuffixArray::count_nonoverlapping`] for the non-overlapping count.
+    pub fn search(&self, prefix: &str, mode: QueryMode) -> Option<Span> {
+        let prefix = self.normalize_query(prefix);
+        let span = self.get_start_span(&prefix)?;
+        self.bisect(&prefix, span, mode)
+    }
+
+    /// Same as [`SuffixArray::search`] but adds the time spent in the prefix table
+    /// lookup and in the bisection to `timings`
+    pub fn search_timed(
+        &self,
+        prefix: &str,
+        mode: QueryMode,
+        timings: &mut SearchTimings,
+    ) -> Option<Span> {
+        let prefix = self.normalize_query(prefix);
+        let now = Instant::now();
+        let span = self.get_start_span(&prefix);
+        let looked_up = Instant::now();
+        timings.lookup += looked_up - now;
+        let result = self.bisect(&prefix, span?, mode);
+        timings.bisection += looked_up.elapsed();
+        result
+    }
+
+    /// See [`normalize_query`]
+    fn normalize_query<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        normalize_query(self.alphabet, query)
+    }
+
+    /// Bisects `span` of the suffix array for the suffixes starting with `prefix`
+    fn bisect(&self, prefix: &str, span: Span, mode: QueryMode) -> Option<Span> {
+        let prefix_bytes = prefix.as_bytes();
+        // matches never span a `$`, and a suffix matching one could be compared past
+        // the end of the sequence
+        if span.is_empty() || prefix_bytes.contains(&b'$') {
+            return None;
+        }
+        let suffix_array = &self.suffix_array;
+        match (&self.packed, self.sentinel) {
+            (Some(packed), SentinelOrder::Smallest) => {
+                search_sequence(packed, prefix_bytes, suffix_array, &span, mode)
+            }
+            (Some(packed), SentinelOrder::Largest) => search_sequence(
+                &SentinelLast(packed),
+                prefix_bytes,
+                suffix_array,
+                &span,
+                mode,
+            ),
+            (None, SentinelOrder::Smallest) => {
+                let sequence = self.sequence.as_bytes();
+                search_sequence(sequence, prefix_bytes, suffix_array, &span, mode)
+            }
+            (None, SentinelOrder::Largest) => {
+                let sequence = SentinelLast(self.sequence.as_bytes());
+                search_sequence(&sequence, prefix_bytes, suffix_array, &span, mode)
+            }
+        }
+    }
+
+    /// The number of suffixes that sort before the query, i.e. the index it would be
+    /// inserted at in the suffix array whether or not it occurs
+    ///
+    /// Suffixes starting with the query are not counted, so for a query that occurs
+    /// the rank is the start of its span.
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::SuffixArray};
+    /// let mut record = Record::new();
+    /// record.push_sequence_part("ACGTACGGA");
+    /// let sa = SuffixArray::from_record(record);
+    /// // only $ and A$ sort before ACGC
+    /// assert_eq!(sa.rank("ACGC"), 2);
+    /// assert_eq!(sa.rank("ACG"), sa.naive_search("ACG").unwrap().start as usize);
+    /// ```
+    pub fn rank(&self, query: &str) -> usize {
+        let query = self.normalize_query(query);
+        let query_bytes = query.as_bytes();
+        let span = Span::new(0, self.suffix_array.len() as u32);
+        let suffix_array = &self.suffix_array;
+        let is_smaller = |ordering: &Ordering| *ordering == Ordering::Less;
+        let rank = match (&self.packed, self.sentinel) {
+            (Some(packed), SentinelOrder::Smallest) => {
+                naive_bisect_by(packed, query_bytes, suffix_array, &span, is_smaller)
+            }
+            (Some(packed), SentinelOrder::Largest) => naive_bisect_by(
+                &SentinelLast(packed),
+                query_bytes,
+                suffix_array,
+                &span,
+                is_smaller,
+            ),
+            (None, SentinelOrder::Smallest) => {
+                let sequence = self.sequence.as_bytes();
+                naive_bisect_by(sequence, query_bytes, suffix_array, &span, is_smaller)
+            }
+            (None, SentinelOrder::Largest) => {
+                let sequence = SentinelLast(self.sequence.as_bytes());
+                naive_bisect_by(&sequence, query_bytes, suffix_array, &span, is_smaller)
+            }
+        };
+        rank as usize
+    }
+
+    /// Like [`SuffixArray::search`] but with `fallback` set a miss (for example a k-mer
+    /// missing from the prefix table) retries with shorter prefixes of the query
+    ///
+    /// Returns the length of the longest prefix of the query that occurs in the
+    /// reference together with its span.
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::{QueryMode, SuffixArray}};
+    /// let mut record = Record::new();
+    /// record.push_sequence_part("ACGTACGGA");
+    /// let mut sa = SuffixArray::from_record(record);
+    /// sa.initialize_prefix_table(4);
+    /// assert_eq!(sa.search_with_fallback("ACGGT", QueryMode::Naive, false), None);
+    /// let (length, span) = sa.search_with_fallback("ACGGT", QueryMode::Naive, true).unwrap();
+    /// assert_eq!(length, 4);
+    /// assert_eq!(sa.suffix_array[span.range()], [4]);
+    /// ```
+    pub fn search_with_fallback(
+        &self,
+        prefix: &str,
+        mode: QueryMode,
+        fallback: bool,
+    ) -> Option<(usize, Span)> {
+        let prefix = prefix.trim_matches(|x: char| x.is_ascii_whitespace());
+        if let Some(span) = self.search(prefix, mode) {
+            return Some((prefix.len(), span));
+        }
+        if !fallback {
+            return None;
+        }
+        // every prefix of an occurring prefix also occurs so bisect on the length
+        let (mut found, mut missing) = (0, prefix.len());
+        let mut best: Option<(usize, Span)> = None;
+        while found + 1 < missing {
+            let length = (found + missing) / 2;
+            match self.search(&prefix[..length], mode) {
+                Some(span) => {
+                    found = length;
+                    best = Some((length, span));
+                }
+                None => missing = length,
+            }
+        }
+        best
+    }
+
+    /// Searches for the longest window of the query left after trimming at most
+    /// `max_clip` bases from each end, for reads whose ends may not match
+    ///
+    /// Returns the bases clipped from the (left, right) end and the span of the window.
+    /// Between windows of the same length the one clipping fewer bases on the left wins.
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::{QueryMode, SuffixArray}};
+    /// let mut record = Record::new();
+    /// record.push_sequence_part("ACGTACGGACG");
+    /// let sa = SuffixArray::from_record(record);
+    /// let (left, right, span) = sa.search_soft_clipped("TTACGG", 2, QueryMode::Naive).unwrap();
+    /// assert_eq!((left, right), (1, 0));
+    /// assert_eq!(sa.suffix_array[span.range()], [3]);
+    /// assert_eq!(sa.search_soft_clipped("TTTTTT", 2, QueryMode::Naive), None);
+    /// ```
+    pub fn search_soft_clipped(
+        &self,
+        query: &str,
+        max_clip: usize,
+        mode: QueryMode,
+    ) -> Option<(usize, usize, Span)> {
+        let query = query.trim_matches(|x: char| x.is_ascii_whitespace());
+        for clipped in 0..=2 * max_clip {
+            if clipped >= query.len() {
+                break;
+            }
+            for left in clipped.saturating_sub(max_clip)..=clipped.min(max_clip) {
+                let right = clipped - left;
+                if let Some(span) = self.search(&query[left..query.len() - right], mode) {
+                    return Some((left, right, span));
+                }
+            }
+        }
+        None
+    }
+
+    /// The number of occurrences of each prefix of the query with a length in
+    /// `min_len..=max_len`, as (length, count) pairs
+    ///
+    /// Longer prefixes can only occur less often, so the profile stops at the
+    /// first length without occurrences (or the end of the query).
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::{QueryMode, SuffixArray}};
+    /// let mut record = Record::new();
+    /// record.push_sequence_part("ACGTACGGACG");
+    /// let sa = SuffixArray::from_record(record);
+    /// let profile = sa.length_profile("ACGTT", 2, 10, QueryMode::Simpaccel);
+    /// assert_eq!(profile, vec![(2, 3), (3, 3), (4, 1)]);
+    /// ```
+    pub fn length_profile(
+        &self,
+        query: &str,
+        min_len: usize,
+        max_len: usize,
+        mode: QueryMode,
+    ) -> Vec<(usize, u32)> {
+        let query = query.trim_matches(|x: char| x.is_ascii_whitespace());
+        let mut profile = Vec::new();
+        for length in min_len.max(1)..=max_len.min(query.len()) {
+            match self.search(&query[..length], mode) {
+                Some(span) => profile.push((length, span.len() as u32)),
+                None => break,
+            }
+        }
+        profile
+    }
+
+    /// The number of occurrences of each window of `k` bases of the query starting every
+    /// `step` bases, as (offset, count) pairs
+    ///
+    /// Only whole windows are searched, so a query shorter than `k` has no windows.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// let sa = SuffixArray::from_sequence("ACGTACGGACG");
+    /// let tiles = sa.tile_counts("ACGGT", 3, 1, QueryMode::Naive);
+    /// assert_eq!(tiles, vec![(0, 3), (1, 1), (2, 0)]);
+    /// assert_eq!(sa.tile_counts("ACGGT", 2, 2, QueryMode::Naive), vec![(0, 3), (2, 1)]);
+    /// assert!(sa.tile_counts("ACG", 4, 1, QueryMode::Naive).is_empty());
+    /// ```
+    pub fn tile_counts(
+        &self,
+        query: &str,
+        k: usize,
+        step: usize,
+        mode: QueryMode,
+    ) -> Vec<(usize, u32)> {
+        let query = query.trim_matches(|x: char| x.is_ascii_whitespace());
+        if k == 0 || k > query.len() {
+            return Vec::new();
+        }
+        (0..=query.len() - k)
+            .step_by(step.max(1))
+            .map(|offset| {
+                let count = self
+                    .search(&query[offset..offset + k], mode)
+                    .map_or(0, |span| span.len() as u32);
+                (offset, count)
+            })
+            .collect()
+    }
+
+    /// The length of the shortest prefix of the query that occurs exactly once in the
+    /// reference, `None` if no prefix does
+    ///
+    /// The number of occurrences only shrinks as the prefix grows, so the lengths are
+    /// bisected for the first one with at most one occurrence.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::SuffixArray;
+    /// let sa = SuffixArray::from_sequence("ACGTACGGACG");
+    /// assert_eq!(sa.minimum_unique_prefix("ACGTT"), Some(4));
+    /// assert_eq!(sa.minimum_unique_prefix("GGA"), Some(2));
+    /// // ACG occurs 3 times and ACGC never
+    /// assert_eq!(sa.minimum_unique_prefix("ACGC"), None);
+    /// assert_eq!(sa.minimum_unique_prefix("ACG"), None);
+    /// ```
+    pub fn minimum_unique_prefix(&self, query: &str) -> Option<usize> {
+        let query = query.trim_matches(|x: char| x.is_ascii_whitespace());
+        let count = |length: usize| {
+            self.simple_accelerant_search(&query[..length])
+                .map_or(0, |span| span.len())
+        };
+        let (mut low, mut high) = (1, query.len() + 1);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if count(mid) <= 1 {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        (low <= query.len() && count(low) == 1).then_some(low)
+    }
+
+    /// Finds the regions where at least `min_queries` distinct queries occur within
+    /// `window` bases of each other
+    ///
+    /// The occurrences of every query are swept in position order. Each window of
+    /// occurrences starting less than `window` bases apart that contains at least
+    /// `min_queries` distinct queries qualifies, and qualifying windows sharing an
+    /// occurrence are merged into one region. Positions are in the joined sequence of
+    /// a multi-record index. No window fits in a `window` of 0 bases.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{CoOccurrence, QueryMode, SuffixArray};
+    /// let sa = SuffixArray::from_sequence("TATAAAGGCCCCCCCCCCTATAAACCCCCCCCGGCC");
+    /// let regions = sa.co_occurrences(&["TATA", "GGCC"], 10, 2, QueryMode::Naive);
+    /// assert_eq!(
+    ///     regions,
+    ///     vec![CoOccurrence { start: 0, end: 10, queries: vec![0, 1] }]
+    /// );
+    /// ```
+    pub fn co_occurrences(
+        &self,
+        queries: &[&str],
+        window: u32,
+        min_queries: usize,
+        mode: QueryMode,
+    ) -> Vec<CoOccurrence> {
+        if window == 0 {
+            return Vec::new();
+        }
+        let mut events: Vec<(u32, usize)> = queries
+            .iter()
+            .enumerate()
+            .filter_map(|(query, prefix)| Some((query, self.search(prefix, mode)?)))
+            .flat_map(|(query, span)| {
+                span.iter_positions(&self.suffix_array)
+                    .map(move |position| (position, query))
+            })
+            .collect();
+        events.sort_unstable();
+        let mut counts = vec![0_usize; queries.len()];
+        let mut distinct = 0_usize;
+        let mut left = 0_usize;
+        // (first, last) event index of each region
+        let mut regions: Vec<(usize, usize)> = Vec::new();
+        for right in 0..events.len() {
+            let (position, query) = events[right];
+            counts[query] += 1;
+            distinct += usize::from(counts[query] == 1);
+            while left <= right && position - events[left].0 >= window {
+                let query = events[left].1;
+                counts[query] -= 1;
+                distinct -= usize::from(counts[query] == 0);
+                left += 1;
+            }
+            if distinct < min_queries.max(1) {
+                continue;
+            }
+            match regions.last_mut() {
+                Some((_, last)) if left <= *last => *last = right,
+                _ => regions.push((left, right)),
+            }
+        }
+        regions
+            .into_iter()
+            .map(|(first, last)| {
+                let region = &events[first..=last];
+                let mut matched: Vec<usize> = region.iter().map(|&(_, query)| query).collect();
+                matched.sort_unstable();
+                matched.dedup();
+                CoOccurrence {
+                    start: region[0].0,
+                    end: region
+                        .iter()
+                        .map(|&(position, query)| {
+                            let query =
+                                queries[query].trim_matches(|x: char| x.is_ascii_whitespace());
+                            position + query.len() as u32
+                        })
+                        .max()
+                        .unwrap_or(region[0].0),
+                    queries: matched,
+                }
+            })
+            .collect()
+    }
+
+    /// Finds the pairs of positions `(p, q)` where `first` occurs at `p` and `second`
+    /// occurs at `q = p + d` for a distance `d` in `distance`, sorted by `p` then `q`
+    ///
+    /// Both queries are searched once and their sorted positions are intersected. The
+    /// positions are in the joined sequence, so in a multi-record index a pair can span
+    /// two records.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// let sa = SuffixArray::from_sequence("TATAGGCCTATAAAGGCC");
+    /// assert_eq!(sa.paired_occurrences("TATA", "GGCC", 4..=4, QueryMode::Naive), vec![(0, 4)]);
+    /// assert_eq!(
+    ///     sa.paired_occurrences("TATA", "GGCC", 4..=6, QueryMode::Naive),
+    ///     vec![(0, 4), (8, 14)]
+    /// );
+    /// assert!(sa.paired_occurrences("TATA", "CCCC", 0..=20, QueryMode::Naive).is_empty());
+    /// ```
+    pub fn paired_occurrences(
+        &self,
+        first: &str,
+        second: &str,
+        distance: RangeInclusive<u32>,
+        mode: QueryMode,
+    ) -> Vec<(u32, u32)> {
+        let sorted_positions = |query: &str| -> Vec<u32> {
+            let mut positions: Vec<u32> = match self.search(query, mode) {
+                Some(span) => span.iter_positions(&self.suffix_array).collect(),
+                None => Vec::new(),
+            };
+            positions.sort_unstable();
+            positions
+        };
+        let firsts = sorted_positions(first);
+        if firsts.is_empty() {
+            return Vec::new();
+        }
+        let seconds = sorted_positions(second);
+        let (min_distance, max_distance) = (*distance.start(), *distance.end());
+        let mut pairs = Vec::new();
+        for p in firsts {
+            let lowest = p.saturating_add(min_distance);
+            let highest = p.saturating_add(max_distance);
+            let start = seconds.partition_point(|&q| q < lowest);
+            pairs.extend(
+                seconds[start..]
+                    .iter()
+                    .take_while(|&&q| q <= highest)
+                    .map(|&q| (p, q)),
+            );
+        }
+        pairs
+    }
+
+    /// Counts the occurrences of a query that do not overlap, choosing greedily from
+    /// the left as `str::matches` does
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::SuffixArray};
+    /// let mut record = Record::new();
+    /// record.push_sequence_part("AAAA");
+    /// let sa = SuffixArray::from_record(record);
+    /// assert_eq!(sa.naive_search("AA").unwrap().len(), 3);
+    /// assert_eq!(sa.count_nonoverlapping("AA"), 2);
+    /// ```
+    pub fn count_nonoverlapping(&self, query: &str) -> usize {
+        let query_len = query.trim_matches(|x: char| x.is_ascii_whitespace()).len() as u32;
+        let Some(span) = self.simple_accelerant_search(query) else {
+            return 0;
+        };
+        let mut positions = self.suffix_array[span.range()].to_vec();
+        positions.sort_unstable();
+        let mut count = 0;
+        let mut next_free = 0;
+        for position in positions {
+            if position >= next_free {
+                count += 1;
+                next_free = position + query_len.max(1);
+            }
+        }
+        count
+    }
+
+    /// Counts the occurrences of a query in fixed size windows of the reference
+    ///
+    /// Bin `i` covers positions `i * bin_size..(i + 1) * bin_size`. The last bin
+    /// is partial when the reference length is not a multiple of `bin_size`.
+    /// Returns an empty histogram when `bin_size` is 0.
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::{QueryMode, SuffixArray}};
+    /// let mut record = Record::new();
+    /// record.push_sequence_part("ACGACGTTACG");
+    /// let sa = SuffixArray::from_record(record);
+    /// assert_eq!(sa.occurrence_histogram("ACG", 4, QueryMode::Naive), vec![2, 0, 1]);
+    /// ```
+    pub fn occurrence_histogram(
+        &self,
+        prefix: &str,
+        bin_size: usize,
+        mode: QueryMode,
+    ) -> Vec<usize> {
+        if bin_size == 0 {
+            return Vec::new();
+        }
+        let reference_len = (0..self.sequence_len())
+            .rev()
+            .find(|&idx| self.byte_at(idx) != b'$')
+            .map_or(0, |idx| idx + 1);
+        let mut histogram = vec![0_usize; reference_len.div_ceil(bin_size)];
+        let _ = self.for_each_occurrence(prefix, mode, |idx| {
+            histogram[idx as usize / bin_size] += 1;
+            ControlFlow::<()>::Continue(())
+        });
+        histogram
+    }
+
+    /// Searches for a query and calls `f` with the position of every occurrence, in
+    /// suffix array order, without collecting them
+    ///
+    /// Returning [`ControlFlow::Break`] from `f` stops at that occurrence and the break
+    /// value is returned.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// # use std::ops::ControlFlow;
+    /// let sa = SuffixArray::from_sequence("ACGTACGGACG");
+    /// let mut count = 0;
+    /// let _ = sa.for_each_occurrence("ACG", QueryMode::Naive, |_| {
+    ///     count += 1;
+    ///     ControlFlow::<()>::Continue(())
+    /// });
+    /// assert_eq!(count, 3);
+    /// let found = sa.for_each_occurrence("ACG", QueryMode::Naive, |position| match position {
+    ///     4 => ControlFlow::Break(position),
+    ///     _ => ControlFlow::Continue(()),
+    /// });
+    /// assert_eq!(found, ControlFlow::Break(4));
+    /// ```
+    pub fn for_each_occurrence<B, F>(&self, query: &str, mode: QueryMode, f: F) -> ControlFlow<B>
+    where
+        F: FnMut(u32) -> ControlFlow<B>,
+    {
+        match self.search(query, mode) {
+            Some(span) => span.iter_positions(&self.suffix_array).try_for_each(f),
+            None => ControlFlow::Continue(()),
+        }
+    }
+
+    /// Searches for a query and collects the positions of at most `cap` occurrences, in
+    /// suffix array order, and whether the query occurs more often than that
+    ///
+    /// The whole span is still searched, only collecting the positions stops early.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// let sa = SuffixArray::from_sequence("ACGTACGGACG");
+    /// let (positions, more) = sa.search_capped("ACG", QueryMode::Naive, 2);
+    /// assert_eq!((positions.len(), more), (2, true));
+    /// assert_eq!(sa.search_capped("ACG", QueryMode::Naive, 3).1, false);
+    /// assert_eq!(sa.search_capped("ACG", QueryMode::Naive, 0), (vec![], true));
+    /// assert_eq!(sa.search_capped("TTT", QueryMode::Naive, 2), (vec![], false));
+    /// ```
+    pub fn search_capped(&self, query: &str, mode: QueryMode, cap: usize) -> (Vec<u32>, bool) {
+        match self.search(query, mode) {
+            Some(span) => (
+                span.iter_positions(&self.suffix_array).take(cap).collect(),
+                span.len() > cap,
+            ),
+            None => (Vec::new(), false),
+        }
+    }
+
+    /// The sorted start positions where the query matches a reference that keeps IUPAC
+    /// codes (see [`Reader::iupac`](crate::reader::Reader::iupac)), a reference `R`
+    /// matching a query `A` or `G`
+    ///
+    /// Such matches are not contiguous in the suffix array since `R` sorts after both `A`
+    /// and `G`, so the suffix array cannot be bisected. Instead the suffixes in the bucket
+    /// of every prefix table k-mer compatible with the start of the query are compared one
+    /// by one, and without a usable prefix table (or for queries shorter than its k) the
+    /// whole sequence is scanned. Expect this to be much slower than [`SuffixArray::search`].
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::SuffixArray;
+    /// let mut sa = SuffixArray::from_sequence("ACRTTAGTNCAT");
+    /// assert_eq!(sa.search_iupac("CAT"), vec![1, 9]);
+    /// sa.initialize_prefix_table(2);
+    /// assert_eq!(sa.search_iupac("CAT"), vec![1, 9]);
+    /// assert_eq!(sa.search_iupac("GTAC"), vec![6]);
+    /// assert!(sa.search_iupac("ACC").is_empty());
+    /// ```
+    pub fn search_iupac(&self, query: &str) -> Vec<u32> {
+        let query = self.normalize_query(query);
+        let query = query.as_bytes();
+        let len = self.sequence_len();
+        if query.is_empty() || query.contains(&b'$') {
+            return Vec::new();
+        }
+        // `$` matches nothing so a match never runs past the end of the sequence
+        let matches_at = |position: usize| {
+            position + query.len() <= len
+                && query
+                    .iter()
+                    .enumerate()
+                    .all(|(offset, &base)| iupac_matches(self.byte_at(position + offset), base))
+        };
+        let table = self.prefix_table.as_ref().filter(|table| {
+            !self.skip_prefix_table
+                && !self.targeted_prefix_table
+                && query.len() >= table.k() as usize
+        });
+        let mut positions: Vec<u32> = match table {
+            Some(table) => table
+                .iter()
+                .filter(|(kmer, _)| {
+                    kmer.bytes()
+                        .zip(query)
+                        .all(|(reference, &base)| iupac_matches(reference, base))
+                })
+                .flat_map(|(_, span)| span.iter_positions(&self.suffix_array))
+                .filter(|&position| matches_at(position as usize))
+                .collect(),
+            None => (0..len)
+                .filter(|&position| matches_at(position))
+                .map(|position| position as u32)
+                .collect(),
+        };
+        positions.sort_unstable();
+        positions
+    }
+
+    /// The start positions of the occurrences of a query as a [`BitVec`] over the sequence,
+    /// so the occurrences of several queries can be intersected or united
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// let sa = SuffixArray::from_sequence("ACGTACGGACG");
+    /// let acg = sa.occurrence_bitset("ACG", QueryMode::Naive);
+    /// assert_eq!(acg.ones().collect::<Vec<_>>(), [0, 4, 8]);
+    /// assert_eq!(acg.len(), sa.sequence_len());
+    /// let ac = sa.occurrence_bitset("AC", QueryMode::Naive);
+    /// let acgt = sa.occurrence_bitset("ACGT", QueryMode::Naive);
+    /// assert_eq!((&ac & &acgt).count_ones(), 1);
+    /// ```
+    pub fn occurrence_bitset(&self, query: &str, mode: QueryMode) -> BitVec {
+        let mut bitset = BitVec::new(self.sequence_len());
+        if let Some(span) = self.search(query, mode) {
+            span.iter_positions(&self.suffix_array)
+                .for_each(|position| bitset.set(position as usize));
+        }
+        bitset
+    }
+
+    /// Like [`SuffixArray::search`] for a query given as unpacked 2-bit nucleotide codes
+    pub fn search_codes(&self, codes: &[u8], mode: QueryMode) -> Result<Option<Span>> {
+        let prefix = String::from_utf8(decode_codes(codes)?)?;
+        Ok(self.search(&prefix, mode))
+    }
+
+    /// Like [`SuffixArray::search`] but only reports occurrences where the query is a
+    /// whole suffix of a record, i.e. the match is followed by the `$` sentinel
+    ///
+    /// Suffixes equal to the query followed by `$` sort before every other suffix
+    /// starting with the query, so the result is the leading part of the full span
+    /// (the trailing part with [`SentinelOrder::Largest`]).
+    ///
+    /// ```rust
+    /// # use assignment_1::{record::Record, suffix_array::{QueryMode, SuffixArray}};
+    /// let mut record = Record::new();
+    /// record.push_sequence_part("ACGTACG");
+    /// let sa = SuffixArray::from_record(record);
+    /// let span = sa.search_whole("ACG", QueryMode::Naive).unwrap();
+    /// assert_eq!(sa.suffix_array[span.range()], [4]);
+    /// assert_eq!(sa.search_whole("GTA", QueryMode::Naive), None);
+    /// ```
+    pub fn search_whole(&self, prefix: &str, mode: QueryMode) -> Option<Span> {
+        let span = self.search(prefix, mode)?;
+        let query_len = prefix.trim_matches(|x: char| x.is_ascii_whitespace()).len();
+        let is_whole = |&&idx: &&u32| self.byte_at(idx as usize + query_len) == b'$';
+        let occurrences = self.suffix_array[span.range()].iter();
+        let whole = match self.sentinel {
+            SentinelOrder::Smallest => occurrences.take_while(is_whole).count() as u32,
+            SentinelOrder::Largest => occurrences.rev().take_while(is_whole).count() as u32,
+        };
+        match (whole, self.sentinel) {
+            (0, _) => None,
+            (_, SentinelOrder::Smallest) => Some(Span::new(span.start, span.start + whole)),
+            (_, SentinelOrder::Largest) => Some(Span::new(span.end - whole, span.end)),
+        }
+    }
+
+    /// Searches for a query on both strands, returning the spans of the query and of
+    /// its reverse complement
+    ///
+    /// Positions in the second span are where the reverse complement starts on the
+    /// indexed (forward) strand. A palindromic query (its own reverse complement) has
+    /// the same span on both strands.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// let sa = SuffixArray::from_sequence("AACGTTTGCA");
+    /// let (forward, reverse) = sa.search_strands("TGC", QueryMode::Naive);
+    /// assert_eq!(sa.suffix_array[forward.unwrap().start as usize], 6);
+    /// assert_eq!(sa.suffix_array[reverse.unwrap().start as usize], 7);
+    /// ```
+    pub fn search_strands(&self, prefix: &str, mode: QueryMode) -> (Option<Span>, Option<Span>) {
+        let prefix = prefix.trim_matches(|x: char| x.is_ascii_whitespace());
+        (
+            self.search(prefix, mode),
+            self.search(&reverse_complement(prefix), mode),
+        )
+    }
+
+    /// Counts the occurrences of a query and of its reverse complement together
+    ///
+    /// A palindromic query (its own reverse complement) is only counted once.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// let sa = SuffixArray::from_sequence("AACGTTTGCA");
+    /// assert_eq!(sa.count_canonical("TGC", QueryMode::Naive), 2);
+    /// assert_eq!(sa.count_canonical("ACGT", QueryMode::Naive), 1);
+    /// ```
+    pub fn count_canonical(&self, prefix: &str, mode: QueryMode) -> usize {
+        let prefix = prefix.trim_matches(|x: char| x.is_ascii_whitespace());
+        let count = |span: Option<Span>| span.map_or(0, |span| span.len());
+        let (forward, reverse) = self.search_strands(prefix, mode);
+        if reverse_complement(prefix) == prefix {
+            return count(forward);
+        }
+        count(forward) + count(reverse)
+    }
+
+    /// Like [`SuffixArray::search`] but only keeps the occurrence at position 0, so a
+    /// query matches only when it is a prefix of the whole reference
+    ///
+    /// For an index over several records this is the start of the first record.
+    ///
+    /// ```rust
+    /// # use assignment_1::suffix_array::{QueryMode, SuffixArray};
+    /// let sa = SuffixArray::from_sequence("ACGTACG");
+    /// let span = sa.search_anchored("ACG", QueryMode::Naive).unwrap();
+    /// assert_eq!(sa.suffix_array[span.range()], [0]);
+    /// assert_eq!(sa.search_anchored("TACG", QueryMode::Naive), None);
+    /// ```
+    pub fn search_anchored(&self, prefix: &str, mode: QueryMode) -> Option<Span> {
+        let Span { start, end } = self.search(prefix, mode)?;
+        (start..end)
+            .find(|&idx| self.suffix_array[idx as usize] == 0)
+            .map(|idx| Span::new(idx, idx + 1))
+    }
+
+    pub fn naive_search(&self, prefix: &str) -> Option<Span> {
+        self.search(prefix, QueryMode::Naive)
+    }
+
+    pub fn simple_accelerant_search(&self, prefix: &str) -> Option<Span> {
+        self.search(prefix, QueryMode::Simpaccel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::temp_path;
+    use itertools::Itertools;
+
+    #[allow(clippy::needless_return)]
+    fn get_suffix_array(sequence: &str) -> SuffixArray {
+        let record: Record = Record {
+            sequence: sequence.to_string(),
+            header: String::from("test"),
+        };
+        return SuffixArray::from_record(record);
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn naive_and_accelerated_search_produce_the_same_result() {
+        let sa: SuffixArray = get_suffix_array("AGGTGGCAATGCGCGCTCATCGCCTTGCAT$");
+        let prefix: &str = "GCA";
+        let naive = sa.naive_search(&prefix).unwrap();
+        assert_eq!(naive.len(), 2);
+        // sa.suffix_array[naive_start..naive_end]
+        naive.iter_positions(&sa.suffix_array).for_each(|idx| {
+            assert!(
+                String::from_utf8((&sa.sequence.as_bytes()[idx as usize..]).to_vec())
+                    .unwrap()
+                    .starts_with(prefix)
+            );
+        });
+        let accelerated = sa.simple_accelerant_search(&prefix).unwrap();
+        assert_eq!(naive, accelerated);
+    }
+
+    #[test]
+    fn homopolymer_reference_with_prefix_table() {
+        for length in [1_usize, 2, 5, 12] {
+            let mut sa: SuffixArray = get_suffix_array(&"A".repeat(length));
+            for k in [None, Some(1_u16), Some(3)] {
+                if let Some(k) = k {
+                    sa.initialize_prefix_table(k);
+                }
+                for query_length in 1..length + 2 {
+                    let prefix = "A".repeat(query_length);
+                    let expected = if query_length <= length {
+                        Some(Span::new(query_length as u32, length as u32 + 1))
+                    } else {
+                        None
+                    };
+                    assert_eq!(sa.naive_search(&prefix), expected, "k={k:?} {prefix}");
+                    assert_eq!(
+                        sa.simple_accelerant_search(&prefix),
+                        expected,
+                        "k={k:?} {prefix}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn codes_and_text_build_the_same_index() {
+        let sequence = "AGGTGGCAATGCGCGCTCATCGCCTTGCAT";
+        let sa = get_suffix_array(sequence);
+        let codes: Vec<u8> = sequence
+            .bytes()
+            .map(|x| crate::encoding::encode_nucleotide(x).unwrap())
+            .collect();
+        let from_codes = SuffixArray::from_codes(&codes).unwrap();
+        let from_packed =
+            SuffixArray::from_packed_codes(&crate::encoding::pack_codes(&codes), codes.len())
+                .unwrap();
+        assert_eq!(from_codes.sequence, sa.sequence);
+        assert_eq!(from_codes.suffix_array, sa.suffix_array);
+        assert_eq!(from_packed, from_codes);
+        assert_eq!(
+            from_codes
+                .search_codes(&[2, 1, 0], QueryMode::Simpaccel)
+                .unwrap(),
+            sa.simple_accelerant_search("GCA")
+        );
+        assert!(SuffixArray::from_codes(&[0, 7]).is_err());
+    }
+
+    #[test]
+    fn packed_and_unpacked_searches_match() {
+        let mut sa = SuffixArray::from_records(vec![
+            Record {
+                header: String::from("chr1"),
+                sequence: String::from("AGGTGGCAATGCGCGCTCATCGCCTTGCAT"),
+            },
+            Record {
+                header: String::from("chr2"),
+                sequence: String::from("TTGCATGCA"),
+            },
+        ]);
+        sa.initialize_prefix_table(2);
+        let mut packed = SuffixArray::from_records(vec![
+            Record {
+                header: String::from("chr1"),
+                sequence: String::from("AGGTGGCAATGCGCGCTCATCGCCTTGCAT"),
+            },
+            Record {
+                header: String::from("chr2"),
+                sequence: String::from("TTGCATGCA"),
+            },
+        ]);
+        packed.pack().unwrap();
+        packed.initialize_prefix_table(2);
+        assert!(packed.is_packed());
+        assert!(packed.sequence.is_empty());
+        assert!(packed.verify_checksum().is_ok());
+        assert_eq!(packed.bwt(), sa.bwt());
+        for prefix in [
+            "GCA",
+            "T",
+            "TTGCAT",
+            "CAT",
+            "GGGG",
+            "AGGTGGCAATGCGCGCTCATCGCCTTGCAT",
+        ] {
+            for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+                assert_eq!(packed.search(prefix, mode), sa.search(prefix, mode));
+                assert_eq!(
+                    packed.search_whole(prefix, mode),
+                    sa.search_whole(prefix, mode)
+                );
+            }
+        }
+        let bytes = bincode::serialize(&packed).unwrap();
+        let mut copied: SuffixArray = bincode::deserialize(&bytes).unwrap();
+        assert!(copied.is_packed());
+        assert_eq!(
+            copied.search("TTGCAT", QueryMode::Simpaccel),
+            sa.search("TTGCAT", QueryMode::Simpaccel)
+        );
+        copied.unpack();
+        assert_eq!(copied.sequence, sa.sequence);
+    }
+
+    #[test]
+    fn sentinel_order_changes_span_boundaries() {
+        let record = || Record {
+            header: String::from("test"),
+            sequence: String::from("ACA"),
+        };
+        let smallest =
+            SuffixArray::from_records_with_sentinel(vec![record()], SentinelOrder::Smallest);
+        let mut largest =
+            SuffixArray::from_records_with_sentinel(vec![record()], SentinelOrder::Largest);
+        // $, A$, ACA$, CA$ vs ACA$, A$, CA$, $
+        assert_eq!(smallest.suffix_array, vec![3, 2, 0, 1]);
+        assert_eq!(largest.suffix_array, vec![0, 2, 1, 3]);
+        for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+            assert_eq!(smallest.search("A", mode), Some(Span::new(1, 3)));
+            assert_eq!(largest.search("A", mode), Some(Span::new(0, 2)));
+            assert_eq!(smallest.search("CA", mode), Some(Span::new(3, 4)));
+            assert_eq!(largest.search("CA", mode), Some(Span::new(2, 3)));
+            assert_eq!(smallest.search_whole("A", mode), Some(Span::new(1, 2)));
+            assert_eq!(largest.search_whole("A", mode), Some(Span::new(1, 2)));
+            assert_eq!(largest.search("T", mode), None);
+        }
+        largest.initialize_prefix_table(1);
+        largest.pack().unwrap();
+        assert_eq!(
+            largest.search("A", QueryMode::Simpaccel),
+            Some(Span::new(0, 2))
+        );
+        assert_eq!(largest.search("C", QueryMode::Naive), Some(Span::new(2, 3)));
+    }
+
+    #[test]
+    fn largest_sentinel_search_matches_baseline() {
+        let sequence = "AGGTGGCAATGCGCGCTCATCGCCTTGCATTTGCA";
+        let sa = SuffixArray::from_records_with_sentinel(
+            vec![Record {
+                header: String::from("test"),
+                sequence: String::from(sequence),
+            }],
+            SentinelOrder::Largest,
+        );
+        for prefix in ["GCA", "T", "TTGCA", "CAT", "A", "GGGG"] {
+            let mut expected: Vec<u32> = (0..sequence.len())
+                .filter(|&idx| sequence[idx..].starts_with(prefix))
+                .map(|idx| idx as u32)
+                .collect();
+            for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+                let mut found = match sa.search(prefix, mode) {
+                    Some(span) => sa.suffix_array[span.range()].to_vec(),
+                    None => Vec::new(),
+                };
+                found.sort();
+                expected.sort();
+                assert_eq!(found, expected, "{prefix}");
+            }
+        }
+    }
+
+    #[test]
+    fn search_with_fallback_finds_longest_prefix() {
+        let mut sa: SuffixArray = get_suffix_array("AGGTGGCAATGCGCGCTCATCGCCTTGCAT");
+        for k in [None, Some(3_u16)] {
+            if let Some(k) = k {
+                sa.initialize_prefix_table(k);
+            }
+            for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+                assert_eq!(
+                    sa.search_with_fallback("GCA", mode, true),
+                    sa.search("GCA", mode).map(|span| (3, span))
+                );
+                let (length, span) = sa.search_with_fallback("TTGCAA", mode, true).unwrap();
+                assert_eq!(length, 5);
+                assert_eq!(Some(span), sa.search("TTGCA", mode));
+                let (length, span) = sa.search_with_fallback("CAAAA", mode, true).unwrap();
+                assert_eq!(length, 3);
+                assert_eq!(Some(span), sa.search("CAA", mode));
+                assert_eq!(sa.search_with_fallback("TTGCAA", mode, false), None);
+                assert_eq!(sa.search_with_fallback("NAC", mode, true), None);
+            }
+        }
+    }
+
+    #[test]
+    fn targeted_prefix_table_falls_back_to_full_span() {
+        let reference = "AGGTGGCAATGCGCGCTCATCGCCTTGCAT";
+        let baseline = get_suffix_array(reference);
+        let mut sa = get_suffix_array(reference);
+        let panel = ["GCA", "TGC", "AAA"].map(String::from);
+        sa.build_targeted_prefix_table(&panel).unwrap();
+        assert_eq!(sa.prefix_table().unwrap().get("AAA"), Some(Span::new(0, 0)));
+        assert_eq!(sa.get_start_span("CGC"), Some(Span::new(0, 31)));
+        let bytes = bincode::serialize(&sa).unwrap();
+        let restored: SuffixArray = bincode::deserialize(&bytes).unwrap();
+        for sa in [&sa, &restored] {
+            for query in ["GCAT", "TGCG", "AAAT", "CGCG", "CAT", "GC", "TTTT"] {
+                for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+                    assert_eq!(sa.search(query, mode), baseline.search(query, mode));
+                }
+            }
+        }
+        assert!(sa.build_targeted_prefix_table(&[]).is_err());
+        assert!(sa
+            .build_targeted_prefix_table(&["GCA".to_string(), "GC".to_string()])
+            .is_err());
+        sa.initialize_prefix_table(3);
+        assert_eq!(sa.get_start_span("AAA"), None);
+    }
+
+    #[test]
+    fn adaptive_prefix_table_splits_repetitive_buckets() {
+        let reference = format!(
+            "{}GATTACAGGCTTAACGTCA{}",
+            "CA".repeat(40),
+            "TTAG".repeat(10)
+        );
+        let baseline = get_suffix_array(&reference);
+        let mut sa = get_suffix_array(&reference);
+        sa.initialize_adaptive_prefix_table(2, 8, 3);
+        assert!(sa.prefix_table().is_none());
+        let table = sa.adaptive_prefix_table().unwrap();
+        assert_eq!(table.depth(), 8);
+        // CACACACA still occurs more than 3 times at the maximum length
+        assert!(table.lookup("CACACACACA").unwrap().len() > 3);
+        assert_eq!(table.lookup("GATTACA").unwrap().len(), 1);
+        let restored: SuffixArray =
+            bincode::deserialize(&bincode::serialize(&sa).unwrap()).unwrap();
+        assert_eq!(restored.adaptive_prefix_table(), sa.adaptive_prefix_table());
+        for query in [
+            "CACA",
+            "ACAG",
+            "TTAGTT",
+            "GATTACA",
+            "AGG",
+            "C",
+            "TTAGTTAGTTAGTTAG",
+            "CAT",
+            "GGG",
+        ] {
+            for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+                assert_eq!(restored.search(query, mode), baseline.search(query, mode));
+            }
+        }
+        sa.initialize_prefix_table(3);
+        assert!(sa.adaptive_prefix_table().is_none());
+    }
+
+    #[test]
+    fn raw_parts_index_searches_like_a_built_one() {
+        let built = {
+            let mut sa = get_suffix_array("GATTACAGATTA");
+            sa.initialize_prefix_table(2);
+            sa
+        };
+        let sa = SuffixArray::from_raw_parts(
+            built.sequence.clone(),
+            built.suffix_array.clone(),
+            built.prefix_table().cloned(),
+        )
+        .unwrap();
+        for query in ["GATTA", "TA", "ACAG", "A", "TTT"] {
+            for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+                assert_eq!(sa.search(query, mode), built.search(query, mode));
+            }
+        }
+        // a sequence holding only the sentinel has no matches
+        let empty = SuffixArray::from_raw_parts(String::from("$"), vec![0], None).unwrap();
+        assert_eq!(empty.search("A", QueryMode::Simpaccel), None);
+        assert!(SuffixArray::from_raw_parts(String::from("ACGT"), vec![0, 1, 2, 3], None).is_err());
+        assert!(SuffixArray::from_raw_parts(String::from("AC$"), vec![2, 0, 0], None).is_err());
+    }
+
+    #[test]
+    fn skipping_prefix_table_searches_full_span() {
+        let mut suffix_array = get_suffix_array("ACGTACGGA");
+        suffix_array.initialize_prefix_table(3);
+        assert_eq!(suffix_array.get_start_span("AC"), Some(Span::new(0, 10)));
+        assert_ne!(suffix_array.get_start_span("ACGT"), Some(Span::new(0, 10)));
+        assert_eq!(suffix_array.get_start_span("TTT"), None);
+        suffix_array.skip_prefix_table(true);
+        assert_eq!(suffix_array.get_start_span("ACGT"), Some(Span::new(0, 10)));
+        assert_eq!(suffix_array.get_start_span("TTT"), Some(Span::new(0, 10)));
+        let baseline = get_suffix_array("ACGTACGGA");
+        for query in ["ACG", "GTA", "TTT", "A"] {
+            assert_eq!(
+                suffix_array.search(query, QueryMode::Naive),
+                baseline.search(query, QueryMode::Naive)
+            );
+        }
+    }
+
+    #[test]
+    fn timed_search_matches_search() {
+        let mut sa = get_suffix_array("ACGTACGGA");
+        sa.initialize_prefix_table(2);
+        let mut timings = SearchTimings::default();
+        for query in ["ACG", "GTA", "TTT", "A", "CGGA"] {
+            for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+                assert_eq!(
+                    sa.search_timed(query, mode, &mut timings),
+                    sa.search(query, mode)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn homopolymer_overlapping_and_nonoverlapping_counts() {
+        let sa = get_suffix_array("AAAAAAA");
+        for (query, overlapping, nonoverlapping) in [
+            ("A", 7, 7),
+            ("AA", 6, 3),
+            ("AAA", 5, 2),
+            ("AAAAAAA", 1, 1),
+            ("C", 0, 0),
+        ] {
+            let count = sa.naive_search(query).map_or(0, |span| span.len());
+            assert_eq!(count, overlapping);
+            assert_eq!(sa.count_nonoverlapping(query), nonoverlapping);
+            assert_eq!(
+                "AAAAAAA".matches(query).count(),
+                nonoverlapping,
+                "str::matches counts non-overlapping occurrences"
+            );
+        }
+    }
+
+    #[test]
+    fn purine_pyrimidine_index_round_trip() {
+        let record = Record {
+            header: String::from("test"),
+            sequence: String::from("ACGTTGCA"),
+        };
+        let options = BuildOptions {
+            alphabet: Alphabet::PurinePyrimidine,
+            ..BuildOptions::default()
+        };
+        let mut sa = SuffixArray::from_records_with_options(vec![record], options);
+        sa.initialize_prefix_table(2);
+        assert_eq!(sa.reconstruct_sequence(), "RYRYYRYR");
+        sa.validate().unwrap();
+
+        let bytes = bincode::serialize(&sa).unwrap();
+        let copied: SuffixArray = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(copied.alphabet(), Alphabet::PurinePyrimidine);
+        for query in ["ACG", "GTA", "RYR", "ATG"] {
+            let span = copied.search(query, QueryMode::Simpaccel).unwrap();
+            let mut positions = copied.suffix_array[span.range()].to_vec();
+            positions.sort();
+            // RYR occurs at 0 and 5
+            assert_eq!(positions, [0, 5], "{query}");
+        }
+        // CT reduces to YY, which only occurs at 3
+        let expected = sa.naive_search("CT").unwrap();
+        assert_eq!(sa.suffix_array[expected.range()], [3]);
+        assert_eq!(copied.naive_search("CT"), Some(expected));
+    }
+
+    #[test]
+    fn soft_mask_survives_serialization() {
+        let records = vec![
+            Record {
+                header: String::from("chr1"),
+                sequence: String::from("ACGTacgt"),
+            },
+            Record {
+                header: String::from("chr2"),
+                sequence: String::from("acgtACGT"),
+            },
+        ];
+        let options = BuildOptions {
+            soft_masked: true,
+            ..BuildOptions::default()
+        };
+        let sa = SuffixArray::from_records_with_options(records, options);
+        assert_eq!(sa.sequence, "ACGTACGT$ACGTACGT$");
+        let bytes = bincode::serialize(&sa).unwrap();
+        let copied: SuffixArray = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(copied.soft_mask().unwrap().count(), 8);
+        let span = copied.search("ACGT", QueryMode::Naive).unwrap();
+        let mut unmasked: Vec<u32> = span
+            .iter_positions(&copied.suffix_array)
+            .filter(|&idx| copied.is_unmasked(idx, 4))
+            .collect();
+        unmasked.sort();
+        assert_eq!(unmasked, [0, 13]);
+        // a match straddling the boundary of a masked region is left out too
+        assert!(!copied.is_unmasked(2, 4));
+        assert!(get_suffix_array("acgt").is_unmasked(0, 4));
+    }
+
+    #[test]
+    fn both_strands_index_finds_reverse_complement_matches() {
+        let records = || {
+            vec![
+                Record {
+                    header: String::from("chr1"),
+                    sequence: String::from("GATTACAGGCTTAACG"),
+                },
+                Record {
+                    header: String::from("chr2"),
+                    sequence: String::from("CCGTTAAGCCTG"),
+                },
+            ]
+        };
+        let single = SuffixArray::from_records(records());
+        let options = BuildOptions {
+            both_strands: true,
+            ..BuildOptions::default()
+        };
+        let sa = SuffixArray::from_records_with_options(records(), options);
+        let sa: SuffixArray = bincode::deserialize(&bincode::serialize(&sa).unwrap()).unwrap();
+        assert!(sa.has_both_strands() && !single.has_both_strands());
+        assert_eq!(sa.forward_contigs(), single.contigs());
+        assert_eq!(sa.contigs().len(), 4);
+        for query in ["TTA", "GCC", "ACG", "CTTAA", "GATTACA", "TGTAATC"] {
+            let positions = |sa: &SuffixArray, query: &str| -> Vec<u32> {
+                sa.search(query, QueryMode::Naive)
+                    .map(|span| span.iter_positions(&sa.suffix_array).collect())
+                    .unwrap_or_default()
+            };
+            let expected: Vec<(Strand, u32)> = positions(&single, query)
+                .into_iter()
+                .map(|position| (Strand::Forward, position))
+                .chain(
+                    positions(&single, &reverse_complement(query))
+                        .into_iter()
+                        .map(|position| (Strand::Reverse, position)),
+                )
+                .sorted()
+                .collect();
+            let found: Vec<(Strand, u32)> = positions(&sa, query)
+                .into_iter()
+                .map(|position| sa.locate_strand(position, query.len() as u32))
+                .sorted()
+                .collect();
+            assert_eq!(found, expected, "{query}");
+        }
+    }
+
+    #[test]
+    fn both_strands_keep_the_soft_mask_of_the_reverse_strand() {
+        let records = vec![Record {
+            header: String::from("chr1"),
+            sequence: String::from("ACGTTacc"),
+        }];
+        let options = BuildOptions {
+            soft_masked: true,
+            both_strands: true,
+            ..BuildOptions::default()
+        };
+        let sa = SuffixArray::from_records_with_options(records, options);
+        assert_eq!(sa.sequence, "ACGTTACC$GGTAACGT$");
+        let mask = sa.soft_mask().unwrap();
+        let masked: Vec<usize> = (0..sa.sequence_len())
+            .filter(|&position| mask.is_masked(position))
+            .collect();
+        assert_eq!(masked, [5, 6, 7, 9, 10, 11]);
+    }
+
+    #[test]
+    fn legacy_layout_is_migrated_on_load() {
+        let mut sa = get_suffix_array("ACGTACGGACGTTACGTACGA");
+        sa.initialize_prefix_table(3);
+        // the layout of indices written before the format version was added
+        let legacy = (&sa.sequence, &sa.suffix_array, sa.prefix_table());
+        let path = temp_path("legacy_layout.sa");
+        bincode::serialize_into(File::create(&path).unwrap(), &legacy).unwrap();
+        assert_eq!(read_format_version(&path).unwrap(), None);
+
+        let loaded = SuffixArray::from_bincode_file(&path).unwrap();
+        assert_eq!(loaded.sequence, sa.sequence);
+        assert_eq!(loaded.suffix_array, sa.suffix_array);
+        // small sparse tables are written as dense tables
+        let table = loaded.prefix_table().unwrap();
+        assert_eq!(table.k(), 3);
+        assert_eq!(table.get("ACG"), sa.prefix_table().unwrap().get("ACG"));
+        assert_eq!(loaded.checksum(), sa.checksum());
+        assert!(loaded.verify_checksum().is_ok());
+        assert_eq!(
+            loaded.search("ACG", QueryMode::Simpaccel),
+            sa.search("ACG", QueryMode::Simpaccel)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn array_only_index_takes_the_sequence_from_the_reference() {
+        let records = || {
+            vec![
+                Record {
+                    header: String::from("chr1"),
+                    sequence: String::from("ACGTNNACG"),
+                },
+                Record {
+                    header: String::from("chr2"),
+                    sequence: String::from("GGACG"),
+                },
+            ]
+        };
+        let sa = SuffixArray::from_records_with_sentinel(records(), SentinelOrder::Largest);
+        let path = temp_path("array_only.sa");
+        sa.write_array_only(File::create(&path).unwrap()).unwrap();
+
+        let loaded = SuffixArray::from_array_only_file(&path, records()).unwrap();
+        assert_eq!(loaded.sequence, sa.sequence);
+        assert_eq!(loaded.suffix_array, sa.suffix_array);
+        assert_eq!(loaded.contigs(), sa.contigs());
+        assert_eq!(loaded.sentinel_order(), SentinelOrder::Largest);
+        assert_eq!(
+            loaded.search("ACG", QueryMode::Naive),
+            sa.search("ACG", QueryMode::Naive)
+        );
+
+        let mut other = records();
+        other[1].sequence = String::from("GGACC");
+        assert!(SuffixArray::from_array_only_file(&path, other).is_err());
+        assert!(bincode::deserialize::<SuffixArray>(&std::fs::read(&path).unwrap()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn prefix_table_index_counts_like_the_suffix_array() {
+        let sequence = "ACGTACGGACGTTNACGTACGA";
+        let mut sa = get_suffix_array(sequence);
+        sa.initialize_prefix_table(3);
+        let expected: Vec<usize> = ["ACG", "CGT", "GGA", "TTT", "CGA"]
+            .iter()
+            .map(|query| {
+                sa.search(query, QueryMode::Naive)
+                    .map_or(0, |span| span.len())
+            })
+            .collect();
+        let index = sa.into_prefix_table_index().unwrap();
+        let path = temp_path("preftab_only.sa");
+        index.write(File::create(&path).unwrap()).unwrap();
+
+        let loaded = PrefixTableIndex::from_file(&path).unwrap();
+        assert_eq!(loaded, index);
+        assert_eq!(
+            read_format_version(&path).unwrap(),
+            Some(PREFIX_TABLE_ONLY_FORMAT_VERSION)
+        );
+        for (query, expected) in ["ACG", "CGT", "GGA", "TTT", "CGA"].iter().zip(expected) {
+            assert_eq!(loaded.count(query).unwrap(), expected, "{query}");
+        }
+        assert!(loaded.count("AC").is_err());
+        assert!(SuffixArray::from_bincode_file(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(get_suffix_array(sequence)
+            .into_prefix_table_index()
+            .is_err());
+    }
+
+    #[test]
+    fn bloom_filter_keeps_every_match_and_is_saved() {
+        let mut sa = get_suffix_array("ACGTACGGACGTTACGTACGAAGT");
+        let queries = ["ACGT", "ACGTAC", "GAAGT", "TTT", "AAG", "CGTTACG", "GGGG"];
+        let expected: Vec<Option<Span>> = queries
+            .iter()
+            .map(|query| sa.search(query, QueryMode::Simpaccel))
+            .collect();
+        sa.initialize_prefix_table(2);
+        sa.initialize_bloom_filter(4, 0.01);
+        let bytes = bincode::serialize(&sa).unwrap();
+        let loaded: SuffixArray = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.bloom_filter(), sa.bloom_filter());
+        for (query, expected) in queries.iter().zip(expected) {
+            for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+                assert_eq!(loaded.search(query, mode), expected, "{query}");
+            }
+        }
+    }
+
+    #[test]
+    fn iupac_search_matches_a_linear_scan() {
+        let reference = "ACGRTTAYGNNACSTGKAC";
+        let mut sa = get_suffix_array(reference);
+        let expected = |query: &str| -> Vec<u32> {
+            (0..=reference.len().saturating_sub(query.len()))
+                .filter(|&position| {
+                    reference.as_bytes()[position..position + query.len()]
+                        .iter()
+                        .zip(query.bytes())
+                        .all(|(&r, q)| iupac_matches(r, q))
+                })
+                .map(|position| position as u32)
+                .collect()
+        };
+        let queries: Vec<String> = (1..=4)
+            .flat_map(|len| {
+                (0..len)
+                    .map(|_| "ACGT".chars())
+                    .multi_cartesian_product()
+                    .map(|chars| chars.into_iter().collect::<String>())
+            })
+            .collect();
+        for k in [None, Some(2), Some(3)] {
+            if let Some(k) = k {
+                sa.initialize_prefix_table(k);
+            }
+            for query in &queries {
+                assert_eq!(sa.search_iupac(query), expected(query), "{query} k={k:?}");
+            }
+        }
+        // a query for the ambiguity code itself only matches it
+        assert_eq!(sa.search_iupac("GR"), vec![2]);
+    }
+
+    #[test]
+    fn query_equal_to_the_entire_reference() {
+        for reference in ["AGGTGGCAATGCGCGCTCATCGCCTTGCAT", "AAAAAAA", "A"] {
+            for sentinel in [SentinelOrder::Smallest, SentinelOrder::Largest] {
+                let record = Record {
+                    header: String::from("test"),
+                    sequence: reference.to_string(),
+                };
+                let mut sa = SuffixArray::from_records_with_sentinel(vec![record], sentinel);
+                for k in [None, Some(1), Some(reference.len() as u16)] {
+                    if let Some(k) = k {
+                        sa.initialize_prefix_table(k);
+                    }
+                    for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+                        let span = sa.search(reference, mode).unwrap();
+                        assert_eq!(span.len(), 1, "{reference} {sentinel:?} k={k:?}");
+                        assert_eq!(sa.suffix_array[span.start as usize], 0);
+                        assert_eq!(sa.search_whole(reference, mode), Some(span));
+                        assert_eq!(sa.search_anchored(reference, mode), Some(span));
+                        for longer in ["A", "$", "$A"] {
+                            assert_eq!(sa.search(&format!("{reference}{longer}"), mode), None);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rank_counts_smaller_suffixes() {
+        let sa = get_suffix_array("ACGTACGGATTAC");
+        for query in ["ACGC", "AA", "CA", "GGG", "TTT", "ZZ", "A", "ACG", "TAC"] {
+            let smaller = sa
+                .sorted_suffixes()
+                .filter(|suffix| *suffix < query && !suffix.starts_with(query))
+                .count();
+            assert_eq!(sa.rank(query), smaller, "{query}");
+        }
+        assert_eq!(sa.naive_search("ACGC"), None);
+        assert_eq!(sa.rank("ZZ"), sa.suffix_array.len());
+    }
+
+    #[test]
+    fn bwt_matches_hand_computed_transform() {
+        let sa: SuffixArray = get_suffix_array("BANANA");
+        assert_eq!(sa.bwt(), b"ANNB$AA".to_vec());
+        let sa: SuffixArray = get_suffix_array("ACAACG$");
+        assert_eq!(sa.bwt(), b"GC$AAAC".to_vec());
+    }
+
+    #[test]
+    fn occurrence_histogram_handles_partial_bin() {
+        let sa: SuffixArray = get_suffix_array("AAAAAAA");
+        assert_eq!(
+            sa.occurrence_histogram("AA", 4, QueryMode::Simpaccel),
+            vec![4, 2]
+        );
+        assert_eq!(
+            sa.occurrence_histogram("C", 3, QueryMode::Naive),
+            vec![0, 0, 0]
+        );
+        assert!(sa.occurrence_histogram("A", 0, QueryMode::Naive).is_empty());
+    }
+
+    #[test]
+    fn coverage_is_reported_per_contig() {
+        let records = vec![
+            Record {
+                header: String::from("chr1"),
+                sequence: String::from("ACGTTGCA"),
+            },
+            Record {
+                header: String::from("chr2"),
+                sequence: String::from("TTGCAACG"),
+            },
+        ];
+        let sa = SuffixArray::from_records(records);
+        let mut coverage = Coverage::new(sa.sequence_len());
+        for query in ["TTGCA", "ACG", "GCA"] {
+            let span = sa.search(query, QueryMode::Simpaccel).unwrap();
+            span.iter_positions(&sa.suffix_array)
+                .for_each(|idx| coverage.add(idx, query.len() as u32));
+        }
+        let mut bedgraph = Vec::new();
+        coverage
+            .write_bedgraph(sa.contigs(), &mut bedgraph)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(bedgraph).unwrap(),
+            "chr1\t0\t5\t1\nchr1\t5\t8\t2\nchr2\t0\t2\t1\nchr2\t2\t5\t2\nchr2\t5\t8\t1\n"
+        );
+    }
+
+    #[test]
+    fn anchored_search_only_reports_reference_start() {
+        let sa = SuffixArray::from_sequence("ACGTACGACG");
+        for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+            assert_eq!(sa.search("ACG", mode).unwrap().len(), 3);
+            let span = sa.search_anchored("ACG", mode).unwrap();
+            assert_eq!(sa.suffix_array[span.range()], [0]);
+            assert_eq!(sa.search_anchored("ACGTACGACG", mode), Some(span));
+            assert!(sa.search("CGA", mode).is_some());
+            assert_eq!(sa.search_anchored("CGA", mode), None);
+            assert_eq!(sa.search_anchored("ACGTT", mode), None);
+        }
+    }
+
+    #[test]
+    fn minimum_unique_prefix_matches_the_length_profile() {
+        let sa = get_suffix_array("ACGTACGGACGTTACGTACGA");
+        for query in ["ACGTACGA", "ACGTT", "CGTA", "TTT", "A", "GGACGTT"] {
+            let expected = sa
+                .length_profile(query, 1, query.len(), QueryMode::Naive)
+                .into_iter()
+                .find(|&(_, count)| count <= 1)
+                .filter(|&(_, count)| count == 1)
+                .map(|(length, _)| length);
+            assert_eq!(sa.minimum_unique_prefix(query), expected, "{query}");
+        }
+        assert_eq!(sa.minimum_unique_prefix("ACGTACGA"), Some(8));
+        assert_eq!(sa.minimum_unique_prefix(""), None);
+    }
+
+    #[test]
+    fn lce_of_long_suffixes_stops_at_the_first_mismatch() {
+        use rand::{distributions::Slice, rngs::StdRng, Rng, SeedableRng};
+        let nucleotides = ['A', 'C', 'G', 'T'];
+        let half: String = StdRng::seed_from_u64(3)
+            .sample_iter(Slice::new(&nucleotides).unwrap())
+            .take(100_000)
+            .collect();
+        let mut sa = SuffixArray::from_sequence(&format!("AC{half}{half}"));
+        for _ in 0..2 {
+            assert_eq!(sa.lce(0, 1), 0);
+            assert_eq!(sa.lce(1, 0), 0);
+            assert_eq!(sa.lce(2, 100_002), 100_000);
+            assert_eq!(sa.lce(100_002, 100_002), 100_000);
+            sa.pack().unwrap();
+        }
+    }
+
+    #[test]
+    fn lce_index_matches_the_naive_extension() {
+        let records = || {
+            vec![
+                Record {
+                    header: String::from("chr1"),
+                    sequence: String::from("ACGTACGGACGTAC"),
+                },
+                Record {
+                    header: String::from("chr2"),
+                    sequence: String::from("GTACGTAA"),
+                },
+            ]
+        };
+        for sentinel in [SentinelOrder::Smallest, SentinelOrder::Largest] {
+            let sa = SuffixArray::from_records_with_sentinel(records(), sentinel);
+            let index = sa.lce_index();
+            let len = sa.sequence_len() as u32;
+            for i in 0..len {
+                for j in 0..len {
+                    assert_eq!(index.lce(i, j), sa.lce(i, j), "{i} {j}");
+                }
+            }
+            // ACGTAC at 0 and 8, the extension stops at the record separator
+            assert_eq!(sa.lce(0, 8), 6);
+            assert_eq!(sa.lce(15, 19), 3);
+        }
+    }
+
+    #[test]
+    fn lcp_array_matches_naive_common_prefixes() {
+        let sa = get_suffix_array("AGGTGGCAATGCGCGCTCATCGCCTTGCAT");
+        let suffixes: Vec<&str> = sa.sorted_suffixes().collect();
+        let expected: Vec<u32> = std::iter::once(0)
+            .chain(suffixes.windows(2).map(|pair| {
+                pair[0]
+                    .bytes()
+                    .zip(pair[1].bytes())
+                    .take_while(|(a, b)| a == b && *a != b'$')
+                    .count() as u32
+            }))
+            .collect();
+        assert_eq!(sa.lcp_array(), expected);
+        assert_eq!(sa.longest_repeated_substring(), Some((10, 4)));
+        assert_eq!(sa.search("GCGC", QueryMode::Naive).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn longest_repeat_does_not_span_records() {
+        let records = ["ACGT", "ACGT"].map(|sequence| Record {
+            header: String::from("test"),
+            sequence: sequence.to_string(),
+        });
+        let sa = SuffixArray::from_records(records.into());
+        assert_eq!(sa.longest_repeated_substring(), Some((0, 4)));
+    }
+
+    #[test]
+    fn adaptive_search_matches_both_modes() {
+        let sa: SuffixArray = get_suffix_array("AGGTGGCAATGCGCGCTCATCGCCTTGCATGCGCGCTCAT");
+        for threshold in [0, 4, 100] {
+            let adaptive = QueryMode::Adaptive { threshold };
+            for query in [
+                "G",
+                "GC",
+                "GCGCGCTCAT",
+                "TCATCGCC",
+                "AAAA",
+                "TTGCATGCGCGCTCATX",
+            ] {
+                let expected = sa.search(query, QueryMode::Naive);
+                assert_eq!(sa.search(query, QueryMode::Simpaccel), expected);
+                assert_eq!(sa.search(query, adaptive), expected, "{query} {threshold}");
+            }
+        }
+    }
+
+    #[test]
+    fn co_occurrences_merge_overlapping_windows() {
+        // ACG at 0, 6 and 20, TTT at 3 and 25, GGG at 8
+        let sa = SuffixArray::from_sequence("ACGTTTACGGGCACACACACACGCATTTCA");
+        let queries = ["ACG", "TTT", "GGG"];
+        let regions = sa.co_occurrences(&queries, 6, 2, QueryMode::Simpaccel);
+        assert_eq!(
+            regions,
+            vec![
+                CoOccurrence {
+                    start: 0,
+                    end: 11,
+                    queries: vec![0, 1, 2],
+                },
+                CoOccurrence {
+                    start: 20,
+                    end: 28,
+                    queries: vec![0, 1],
+                },
+            ]
+        );
+        // 20 and 25 are no longer less than the window apart
+        assert_eq!(sa.co_occurrences(&queries, 5, 2, QueryMode::Naive).len(), 1);
+        let regions = sa.co_occurrences(&queries, 6, 3, QueryMode::Naive);
+        assert_eq!(regions.len(), 1);
+        assert_eq!((regions[0].start, regions[0].end), (3, 11));
+        assert!(sa
+            .co_occurrences(&queries, 2, 2, QueryMode::Naive)
+            .is_empty());
+        assert!(sa
+            .co_occurrences(&["CCCC", "ACG"], 100, 2, QueryMode::Naive)
+            .is_empty());
+    }
+
+    #[test]
+    fn co_occurrences_in_an_empty_window_are_empty() {
+        let sa = SuffixArray::from_sequence("ACGTTTACGGGCACACACACACGCATTTCA");
+        assert!(sa
+            .co_occurrences(&["ACG", "TTT"], 0, 1, QueryMode::Naive)
+            .is_empty());
+        assert!(sa
+            .co_occurrences(&["ACG", "TTT"], 0, 2, QueryMode::Simpaccel)
+            .is_empty());
+    }
+
+    #[test]
+    fn search_ignores_surrounding_whitespace() {
+        let sa: SuffixArray = get_suffix_array("AGGTGGCAATGCGCGCTCATCGCCTTGCAT$");
+        for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+            let expected = sa.search("GCA", mode);
+            assert!(expected.is_some());
+            assert_eq!(sa.search("GCA\n", mode), expected);
+            assert_eq!(sa.search(" GCA \r\n", mode), expected);
+        }
+    }
+
+    #[test]
+    fn start_span_stats_count_narrowed_and_ruled_out_queries() {
+        let mut sa = get_suffix_array("ACGTACGGACG");
+        let queries = ["ACGT", "GG", "TT", "A"];
+        let mut stats = StartSpanStats::default();
+        for query in queries {
+            stats.add(sa.start_span(query), sa.sequence_len());
+        }
+        assert_eq!((stats.narrowed, stats.ruled_out), (0, 0));
+        sa.initialize_prefix_table(2);
+        let mut stats = StartSpanStats::default();
+        for query in queries {
+            stats.add(sa.start_span(query), sa.sequence_len());
+        }
+        // AC and GG narrow, TT is ruled out and A is shorter than k
+        assert_eq!(stats.queries, 4);
+        assert_eq!((stats.narrowed, stats.ruled_out), (3, 1));
+        assert_eq!(stats.mean_width(), (3 + 1 + 12) as f64 / 3.0);
+    }
+
+    #[test]
+    fn lowercase_queries_are_normalized() {
+        let sa: SuffixArray = get_suffix_array("AGGTGGCAATGCGCGCTCATCGCCTTGCAT$");
+        for mode in [QueryMode::Naive, QueryMode::Simpaccel] {
+            assert_eq!(sa.search("gcA", mode), sa.search("GCA", mode));
+            assert_eq!(sa.search(" cgc\n", mode), sa.search("CGC", mode));
+        }
+        assert_eq!(sa.rank("tgc"), sa.rank("TGC"));
+        let options = BuildOptions {
+            alphabet: Alphabet::PurinePyrimidine,
+            ..BuildOptions::default()
+        };
+        let record = Record {
+            header: String::from("test"),
+            sequence: String::from("ACGTTGCA"),
+        };
+        let folded = SuffixArray::from_records_with_options(vec![record], options);
+        assert_eq!(
+            folded.search("gtt", QueryMode::Naive),
+            folded.search("GTT", QueryMode::Naive)
+        );
+    }
+
+    #[test]
+    fn multi_record_search_is_restricted_to_contig() {
+        let records = vec![
+            Record {
+                header: String::from("chr1 first"),
+                sequence: String::from("ACGTTGCA"),
+            },
+            Record {
+                header: String::from("chr2 second"),
+                sequence: String::from("TTGCAACG"),
+            },
+        ];
+        let sa = SuffixArray::from_records(records);
+        assert_eq!(sa.sequence, "ACGTTGCA$TTGCAACG$");
+        let chr2 = sa.contig("chr2").unwrap();
+        assert_eq!(chr2.span, Span::new(9, 17));
+        assert!(sa.contig("second").is_none());
+
+        let span = sa.simple_accelerant_search("TTGCA").unwrap();
+        let mut positions = sa.suffix_array[span.range()].to_vec();
+        positions.sort();
+        assert_eq!(positions, vec![3, 9]);
+        let in_chr2: Vec<u32> = positions.iter().filter_map(|&p| chr2.locate(p)).collect();
+        assert_eq!(in_chr2, vec![0]);
+        assert_eq!(sa.naive_search("GCAT"), None);
+    }
+
+    #[test]
+    fn validate_accepts_built_indices() {
+        let records = || {
+            vec![
+                Record {
+                    header: String::from("chr1"),
+                    sequence: String::from("ACGTTGCA"),
+                },
+                Record {
+                    header: String::from("chr2"),
+                    sequence: String::from("TTGCAACG"),
+                },
+            ]
+        };
+        for sentinel in [SentinelOrder::Smallest, SentinelOrder::Largest] {
+            let mut sa = SuffixArray::from_records_with_sentinel(records(), sentinel);
+            sa.validate().unwrap();
+            sa.pack().unwrap();
+            sa.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn validate_detects_unsorted_suffixes() {
+        let mut sa = get_suffix_array("ACGTACGGA");
+        sa.suffix_array.swap(3, 4);
+        assert!(sa.validate().is_err());
+        sa.suffix_array.swap(3, 4);
+        sa.suffix_array[3] = sa.suffix_array[4];
+        assert!(sa.validate().is_err());
+        sa.suffix_array.pop();
+        assert!(sa.validate().is_err());
+    }
+
+    #[test]
+    fn reconstructed_sequence_survives_serialization() {
+        let mut sa = get_suffix_array("ACGTACGGA");
+        sa.pack().unwrap();
+        let bytes = bincode::serialize(&sa).unwrap();
+        let mut copied: SuffixArray = bincode::deserialize(&bytes).unwrap();
+        copied.unpack();
+        assert_eq!(copied.reconstruct_sequence(), "ACGTACGGA");
+        assert_eq!(copied.reconstruct_record("test"), Some("ACGTACGGA"));
+        assert_eq!(copied.reconstruct_record("missing"), None);
+        assert_eq!(
+            checksum_sequence(copied.reconstruct_sequence()),
+            copied.checksum()
+        );
+    }
+
+    #[test]
+    fn serialized_suffix_array_is_at_fixed_offset() {
+        let mut sa = get_suffix_array("ACGTACGGA");
+        sa.initialize_prefix_table(2);
+        let bytes = bincode::serialize(&sa).unwrap();
+        assert_eq!(bytes[..4], FORMAT_VERSION.to_le_bytes());
+        assert_eq!(bytes[4..12], (sa.suffix_array.len() as u64).to_le_bytes());
+        let table: Vec<u32> = bytes[SUFFIX_ARRAY_OFFSET..]
+            .chunks_exact(4)
+            .take(sa.suffix_array.len())
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(table, sa.suffix_array);
+
+        let copied: SuffixArray = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(copied.suffix_array, sa.suffix_array);
+        assert_eq!(copied.sequence, sa.sequence);
+        assert_eq!(copied.checksum(), sa.checksum());
+        assert_eq!(
+            copied.search("CG", QueryMode::Naive),
+            sa.search("CG", QueryMode::Naive)
+        );
+    }
+
+    #[test]
+    fn unknown_format_version_is_rejected() {
+        let mut bytes = bincode::serialize(&get_suffix_array("ACGT")).unwrap();
+        bytes[..4].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(bincode::deserialize::<SuffixArray>(&bytes).is_err());
+    }
+
+    #[test]
+    fn checksum_matches_reference_sequence() {
+        let sa: SuffixArray = get_suffix_array("AGGTGGCAATGCGCGCTCATCGCCTTGCAT");
+        assert_eq!(
+            sa.checksum(),
+            checksum_sequence("AGGTGGCAATGCGCGCTCATCGCCTTGCAT")
+        );
+        assert!(sa.verify_checksum().is_ok());
+        assert!(sa
+            .verify_reference(&checksum_sequence("AGGTGGCAATGCGCGCTCATCGCCTTGCAT"))
+            .is_ok());
+        assert!(sa.verify_reference(&checksum_sequence("AGGT")).is_err());
+    }
+
+    #[test]
+    fn checksum_detects_modified_sequence() {
+        let mut sa: SuffixArray = get_suffix_array("AGGTGGCAATGCGCGCTCATCGCCTTGCAT");
+        sa.sequence.replace_range(0..1, "C");
+        assert!(sa.verify_checksum().is_err());
     }
 }
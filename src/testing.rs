@@ -0,0 +1,18 @@
+//! Helpers shared by the tests of the library and of the binaries
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A path in the temporary directory ending in `name`, unique to this call so tests running
+/// in parallel (or concurrent test runs) never share a file
+pub fn temp_path(name: &str) -> PathBuf {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!(
+        "assignment_1_test_{}_{id}_{name}",
+        std::process::id()
+    ))
+}